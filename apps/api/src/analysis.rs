@@ -0,0 +1,610 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use regex::Regex;
+use serde_json::{json, Value};
+use crate::ingest;
+use crate::llm::{self, ChatMessage, ChatRequest, JsonSchema, LlmError, LlmProvider};
+use crate::models::*;
+use crate::report;
+use crate::state::FileInfo;
+use crate::table::{Column, Table};
+
+// Prompt size when the request sets none, and the least it may ask for.
+pub const DEFAULT_PROMPT_TOKENS: usize = 3000;
+pub const MIN_PROMPT_TOKENS: usize = 500;
+// Rows kept in a profile; the prompt shows as many as fit.
+const SAMPLE_ROWS: usize = 10;
+// Longest text and array shown in samples and top values.
+const MAX_CELL_CHARS: usize = 80;
+const MAX_ITEMS: usize = 5;
+// Tries the LLM gets at a reply that matches the schema.
+const REPLY_ATTEMPTS: usize = 3;
+// Distinct values a string column may have and still count as categorical.
+const CATEGORICAL_MAX: usize = 20;
+// Values per column checked against the text shapes.
+const SHAPE_VALUES: usize = 1000;
+
+const INSTRUCTIONS: &str = "Using this profile, add any data patterns not listed above, suggest an API structure \
+    (endpoints and parameters) for serving this data, recommend the best AI model for processing it, and explain \
+    the recommendation and any data quality concerns in model_reasoning.";
+
+// Schema, per-column statistics, samples and patterns of a csv or json file;
+// None for anything that does not parse as a table.
+pub fn profile(file: &FileInfo) -> Option<FileProfile> {
+    let rows = ingest::parse_rows(file).ok()?;
+    let table = Table::from_rows(&rows);
+    let columns: Vec<ColumnProfile> = table.columns().iter().map(column_profile).collect();
+    let name = file.filename.to_lowercase();
+    let format = if name.ends_with(".csv") || file.file_type.contains("csv") { "csv" } else { "json" };
+    Some(FileProfile {
+        format: format.into(),
+        rows: table.len(),
+        patterns: table_patterns(format, &table, &columns),
+        sample_rows: sample(&table),
+        columns,
+    })
+}
+
+fn column_profile(col: &Column) -> ColumnProfile {
+    let spec = FeatureSpec { name: col.name.clone(), dtype: col.dtype().into(), ..Default::default() };
+    let mut stats = report::feature_stats(&spec, col);
+    stats.top_values.iter_mut().for_each(|v| v.value = clip(&v.value));
+    let patterns = column_patterns(col, &stats);
+    ColumnProfile { stats, patterns }
+}
+
+fn column_patterns(col: &Column, s: &FeatureStats) -> Vec<String> {
+    let non_null = col.non_null();
+    if non_null == 0 {
+        return vec!["always null".into()];
+    }
+    let mut out = Vec::new();
+    if s.null_count > 0 {
+        out.push(format!("{:.1}% null", s.null_count as f64 * 100.0 / s.count as f64));
+    }
+    if non_null > 1 && s.distinct == non_null {
+        out.push("unique".into());
+    }
+    if non_null > 1 && s.distinct == 1 {
+        out.push("constant".into());
+    }
+    match col.dtype() {
+        "datetime" => {
+            let ts: Vec<i64> = col.iter().filter_map(|c| c.as_micros()).collect();
+            if ts.windows(2).all(|w| w[0] <= w[1]) {
+                out.push("sorted ascending".into());
+            }
+            if let Some(step) = cadence(&ts) {
+                out.push(format!("regular cadence of {}", duration(step)));
+            }
+        }
+        "integer" | "number" => {
+            let xs: Vec<f64> = col.iter().filter_map(|c| c.as_f64()).collect();
+            let (lo, hi) = xs.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| (lo.min(*x), hi.max(*x)));
+            if col.dtype() == "integer" && lo >= 1e9 && hi < 1e10 {
+                out.push("epoch seconds".into());
+            } else if col.dtype() == "integer" && lo >= 1e12 && hi < 1e13 {
+                out.push("epoch milliseconds".into());
+            } else if lo >= 0.0 {
+                out.push("non-negative".into());
+            }
+            if xs.len() > 1 && xs.windows(2).all(|w| w[0] <= w[1]) {
+                out.push("monotonically increasing".into());
+            }
+        }
+        "string" => {
+            if (2..=CATEGORICAL_MAX).contains(&s.distinct) && s.distinct * 2 <= non_null {
+                out.push(format!("categorical, {} values", s.distinct));
+            }
+            let values: Vec<&str> = col.iter().filter_map(|c| c.as_str()).take(SHAPE_VALUES).collect();
+            out.extend(text_shape(&values).map(String::from));
+        }
+        _ => {}
+    }
+    out
+}
+
+// The first shape every value has, most specific first.
+fn text_shape(values: &[&str]) -> Option<&'static str> {
+    const SHAPES: &[(&str, &str)] = &[
+        ("uuids", r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$"),
+        ("email addresses", r"^[^@\s]+@[^@\s]+\.[^@\s]+$"),
+        ("urls", r"^https?://\S+$"),
+        ("dates without a time", r"^\d{4}-\d{2}-\d{2}$"),
+        ("ticker-like codes", r"^[A-Z0-9]{1,10}([-_/][A-Z0-9]{1,10})?$"),
+    ];
+    if values.is_empty() {
+        return None;
+    }
+    SHAPES.iter()
+        .find(|(_, p)| Regex::new(p).is_ok_and(|re| values.iter().all(|v| re.is_match(v))))
+        .map(|(name, _)| *name)
+}
+
+// Most common positive step between consecutive timestamps, when at least 70%
+// of the steps take it. Interleaved series still show their own step.
+fn cadence(ts: &[i64]) -> Option<i64> {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for d in ts.windows(2).map(|w| w[1] - w[0]).filter(|d| *d > 0) {
+        *counts.entry(d).or_default() += 1;
+    }
+    let total: usize = counts.values().sum();
+    let (step, n) = counts.into_iter().max_by_key(|(d, n)| (*n, -d))?;
+    (n * 10 >= total * 7).then_some(step)
+}
+
+fn duration(micros: i64) -> String {
+    let secs = micros / 1_000_000;
+    if secs == 0 {
+        return format!("{}ms", micros / 1000);
+    }
+    [("d", 86_400), ("h", 3_600), ("m", 60)].iter()
+        .find(|(_, n)| secs % n == 0)
+        .map(|(unit, n)| format!("{}{unit}", secs / n))
+        .unwrap_or_else(|| format!("{secs}s"))
+}
+
+fn table_patterns(format: &str, table: &Table, columns: &[ColumnProfile]) -> Vec<String> {
+    let mut out = vec![format!("tabular {} with {} rows and {} columns", format.to_uppercase(), table.len(), columns.len())];
+    let has = |c: &ColumnProfile, prefix: &str| c.patterns.iter().any(|p| p.starts_with(prefix));
+    let ts = columns.iter().find(|c| c.stats.dtype == "datetime" || has(c, "epoch"));
+    let mut entities: Vec<&ColumnProfile> = columns.iter().filter(|c| c.stats.dtype == "string" && has(c, "categorical")).collect();
+    match ts {
+        Some(t) if !entities.is_empty() => {
+            let t = &t.stats.feature;
+            // the entity that makes (entity, ts) unique, else the coarsest one
+            entities.sort_by_key(|e| (!unique_pair(table, &e.stats.feature, t), e.stats.distinct));
+            let e = &entities[0].stats.feature;
+            out.push(format!("time series of {t} per {e}"));
+            if unique_pair(table, e, t) {
+                out.push(format!("candidate key: {e} + {t}"));
+            }
+        }
+        Some(t) => out.push(format!("time series over {}", t.stats.feature)),
+        None => {}
+    }
+    // identifiers, not measurements that merely happen to differ; a handful of
+    // rows proves nothing
+    let id = columns.iter().find(|c| matches!(c.stats.dtype.as_str(), "string" | "integer") && has(c, "unique"));
+    if let Some(c) = id.filter(|_| table.len() >= SAMPLE_ROWS) {
+        out.push(format!("candidate key: {}", c.stats.feature));
+    }
+    out
+}
+
+fn unique_pair(table: &Table, a: &str, b: &str) -> bool {
+    let mut seen = HashSet::new();
+    (0..table.len()).all(|i| {
+        let (x, y) = (table.cell(a, i), table.cell(b, i));
+        !x.is_null() && !y.is_null() && seen.insert((x.to_value().to_string(), y.to_value().to_string()))
+    })
+}
+
+// Evenly spaced rows, first and last included.
+fn sample(table: &Table) -> Vec<Value> {
+    let n = table.len();
+    let picks: Vec<usize> = if n <= SAMPLE_ROWS {
+        (0..n).collect()
+    } else {
+        (0..SAMPLE_ROWS).map(|k| k * (n - 1) / (SAMPLE_ROWS - 1)).collect()
+    };
+    picks.into_iter().map(|i| clip(&table.row(i))).collect()
+}
+
+fn clip(v: &Value) -> Value {
+    match v {
+        Value::String(s) if s.chars().count() > MAX_CELL_CHARS => {
+            Value::String(format!("{}…", s.chars().take(MAX_CELL_CHARS).collect::<String>()))
+        }
+        Value::Array(xs) => Value::Array(xs.iter().take(MAX_ITEMS).map(clip).collect()),
+        Value::Object(o) => Value::Object(o.iter().map(|(k, v)| (k.clone(), clip(v))).collect()),
+        other => other.clone(),
+    }
+}
+
+// Rough count: about four characters per token for English and JSON.
+fn tokens(s: &str) -> usize {
+    s.len().div_ceil(4)
+}
+
+// The analysis prompt, kept within `budget` tokens. Columns get full detail
+// while they fit in 60% of it, then name and type up to 75%, and the rest are
+// counted by type; sample rows fill what is left. A large file is summarized
+// this way instead of being cut off mid-row.
+pub fn prompt(file: &FileInfo, profile: Option<&FileProfile>, budget: usize) -> String {
+    let budget = budget.max(MIN_PROMPT_TOKENS);
+    let mut out = format!("File: {} ({}, {})\n", file.filename, format_file_size(file.file_size), file.file_type);
+    let Some(p) = profile else {
+        out.push_str("The file could not be read as a table.\n");
+        if let Ok(text) = std::str::from_utf8(&file.content) {
+            let room = budget.saturating_sub(tokens(&out) + tokens(INSTRUCTIONS) + 10) * 4;
+            let preview: String = text.char_indices().take_while(|(i, _)| *i < room).map(|(_, c)| c).collect();
+            out.push_str(&format!("Beginning of the file:\n{preview}\n"));
+        }
+        out.push_str(INSTRUCTIONS);
+        return out;
+    };
+    out.push_str(&format!("Format: {}, {} rows, {} columns\n", p.format, p.rows, p.columns.len()));
+    out.push_str("Patterns:\n");
+    p.patterns.iter().for_each(|pat| out.push_str(&format!("- {pat}\n")));
+    out.push_str("Columns:\n");
+    // time, category and key columns say the most, so they go first
+    let mut columns: Vec<&ColumnProfile> = p.columns.iter().collect();
+    columns.sort_by_key(|c| !telling(c));
+    for (i, c) in columns.iter().enumerate() {
+        let line = column_line(c);
+        if tokens(&out) + tokens(&line) <= budget * 3 / 5 {
+            out.push_str(&line);
+            continue;
+        }
+        let brief = format!("- {} ({})\n", c.stats.feature, c.stats.dtype);
+        if tokens(&out) + tokens(&brief) <= budget * 3 / 4 {
+            out.push_str(&brief);
+            continue;
+        }
+        let mut by_type: BTreeMap<&str, usize> = BTreeMap::new();
+        columns[i..].iter().for_each(|c| *by_type.entry(c.stats.dtype.as_str()).or_default() += 1);
+        let counts: Vec<String> = by_type.iter().map(|(t, n)| format!("{n} {t}")).collect();
+        out.push_str(&format!("- and {} more columns: {}\n", p.columns.len() - i, counts.join(", ")));
+        break;
+    }
+    let room = budget.saturating_sub(tokens(&out) + tokens(INSTRUCTIONS) + 20);
+    let mut used = 0;
+    let rows: Vec<String> = p.sample_rows.iter()
+        .map(|r| r.to_string())
+        .take_while(|r| {
+            used += tokens(r) + 1;
+            used <= room
+        })
+        .collect();
+    if !rows.is_empty() {
+        out.push_str(&format!("Sample rows ({} of {}, spread across the file):\n", rows.len(), p.rows));
+        rows.iter().for_each(|r| out.push_str(&format!("{r}\n")));
+    }
+    out.push_str(INSTRUCTIONS);
+    out
+}
+
+fn telling(c: &ColumnProfile) -> bool {
+    c.stats.dtype == "datetime"
+        || c.patterns.iter().any(|p| p.starts_with("epoch") || p.starts_with("categorical") || (p == "unique" && c.stats.dtype != "number"))
+}
+
+fn column_line(c: &ColumnProfile) -> String {
+    let s = &c.stats;
+    let mut parts = vec![format!("{} nulls", s.null_count), format!("{} distinct", s.distinct)];
+    if let (Some(lo), Some(hi)) = (&s.min, &s.max) {
+        parts.push(format!("range {lo} to {hi}"));
+    }
+    if let Some(m) = s.mean {
+        parts.push(format!("mean {}", (m * 1e4).round() / 1e4));
+    }
+    // repeated values only; a list of one-offs says nothing
+    if s.distinct < s.count - s.null_count && !s.top_values.is_empty() {
+        let top: Vec<String> = s.top_values.iter().map(|v| format!("{} ({})", v.value, v.count)).collect();
+        parts.push(format!("top {}", top.join(", ")));
+    }
+    parts.extend(c.patterns.iter().cloned());
+    format!("- {} ({}): {}\n", s.feature, s.dtype, parts.join("; "))
+}
+
+// JSON schema of AnalysisReply. Every property is required and no others are
+// allowed, so a reply either fills the whole analysis or is sent back.
+pub fn reply_schema() -> Value {
+    let object = |props: Value| {
+        let required: Vec<&String> = props.as_object().map(|p| p.keys().collect()).unwrap_or_default();
+        json!({ "type": "object", "properties": props, "required": required, "additionalProperties": false })
+    };
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    let parameter = object(json!({
+        "name": { "type": "string" },
+        "type": { "type": "string", "enum": ["string", "number", "integer", "boolean", "array", "object"] },
+        "required": { "type": "boolean" },
+        "description": { "type": "string" },
+    }));
+    let endpoint = object(json!({
+        "path": { "type": "string", "description": "starts with /" },
+        "method": { "type": "string", "enum": ["GET", "POST", "PUT", "PATCH", "DELETE"] },
+        "description": { "type": "string" },
+        "parameters": { "type": "array", "items": parameter },
+    }));
+    object(json!({
+        "data_patterns": strings,
+        "suggested_api_structure": object(json!({
+            "endpoints": { "type": "array", "items": endpoint, "minItems": 1 },
+            "authentication": object(json!({
+                "type": { "type": "string", "enum": ["api_key", "oauth2", "bearer", "none"] },
+                "required": { "type": "boolean" },
+            })),
+            "rate_limits": object(json!({
+                "requests_per_minute": { "type": "integer", "minimum": 1 },
+                "requests_per_hour": { "type": "integer", "minimum": 1 },
+            })),
+        })),
+        "best_model": object(json!({
+            "id": { "type": "string" },
+            "name": { "type": "string" },
+            "description": { "type": "string" },
+            "max_tokens": { "type": "integer", "minimum": 1, "description": "context window" },
+            "cost_per_1k_tokens": { "type": "number", "minimum": 0, "description": "USD" },
+            "capabilities": strings,
+        })),
+        "model_reasoning": { "type": "string", "description": "why this model, and any data quality concerns" },
+    }))
+}
+
+// Asks the LLM for an AnalysisReply and merges it with the local analysis. The
+// reply's patterns are added after the detected ones.
+pub async fn analyze(llm: &dyn LlmProvider, file: &FileInfo, model: Option<String>, budget: usize) -> Result<DirectoryAnalysis, LlmError> {
+    let profile = profile(file);
+    let schema = JsonSchema { name: "directory_analysis".into(), schema: reply_schema() };
+    let req = ChatRequest {
+        messages: vec![
+            ChatMessage::system(format!(
+                "You are an expert API architect and data analyst. Analyze files and suggest optimal API designs. \
+                 Reply with a single JSON object matching this JSON schema:\n{}",
+                schema.schema
+            )),
+            ChatMessage::user(prompt(file, profile.as_ref(), budget)),
+        ],
+        max_tokens: 4000,
+        temperature: 0.3,
+        model,
+    };
+    let reply: AnalysisReply = llm::structured(llm, req, &schema, REPLY_ATTEMPTS).await?;
+
+    let mut analysis = base(file, profile.as_ref());
+    for p in reply.data_patterns {
+        if !analysis.data_patterns.contains(&p) {
+            analysis.data_patterns.push(p);
+        }
+    }
+    let mut api = reply.suggested_api_structure;
+    api.endpoints.iter_mut().filter(|e| !e.path.starts_with('/')).for_each(|e| e.path.insert(0, '/'));
+    analysis.suggested_api_structure = serde_json::to_value(api).ok();
+    analysis.best_model = Some(reply.best_model);
+    analysis.model_reasoning = Some(reply.model_reasoning);
+    Ok(analysis)
+}
+
+// What can be said about a file without a model.
+pub fn base(file: &FileInfo, profile: Option<&FileProfile>) -> DirectoryAnalysis {
+    DirectoryAnalysis {
+        path: format!("uploaded/{}", file.filename),
+        file_count: 1,
+        file_types: vec![file.file_type.clone()],
+        total_size: format_file_size(file.file_size),
+        structure: json!({
+            "root": {
+                "files": [{
+                    "name": file.filename,
+                    "size": file.file_size,
+                    "type": file.file_type,
+                    "profile": profile,
+                }]
+            }
+        }),
+        data_patterns: match profile {
+            Some(p) => p.patterns.clone(),
+            None => detect_data_patterns(&file.filename, &file.file_type),
+        },
+        suggested_api_structure: None,
+        best_model: None,
+        model_reasoning: None,
+    }
+}
+
+// Guesses from the name and type, for files that do not parse.
+fn detect_data_patterns(filename: &str, file_type: &str) -> Vec<String> {
+    let mut patterns = Vec::new();
+    let name_lower = filename.to_lowercase();
+
+    if name_lower.contains("csv") || file_type.contains("csv") {
+        patterns.push("Structured Data (CSV)".to_string());
+    }
+    if name_lower.contains("json") || file_type.contains("json") {
+        patterns.push("JSON Data".to_string());
+    }
+    if name_lower.contains("log") {
+        patterns.push("Log Files".to_string());
+    }
+    if name_lower.contains("config") || name_lower.contains("settings") {
+        patterns.push("Configuration Files".to_string());
+    }
+    if name_lower.contains("image") || name_lower.contains("photo") {
+        patterns.push("Image Data".to_string());
+    }
+
+    if patterns.is_empty() {
+        patterns.push("Mixed Data Types".to_string());
+    }
+
+    patterns
+}
+
+fn format_file_size(bytes: u64) -> String {
+    if bytes == 0 {
+        return "0 Bytes".to_string();
+    }
+    let k = 1024;
+    let sizes = ["Bytes", "KB", "MB", "GB"];
+    let i = (bytes as f64).log(k as f64).floor() as usize;
+    let i = i.min(sizes.len() - 1);
+    format!("{:.2} {}", bytes as f64 / (k as f64).powi(i as i32), sizes[i])
+}
+
+pub async fn generate_spec(
+    llm: &dyn LlmProvider,
+    analysis: &DirectoryAnalysis,
+    model: Option<String>,
+) -> Result<Value, LlmError> {
+    let prompt = format!(
+        "Create a complete API specification based on this analysis:
+        
+        Path: {}
+        File Types: {:?}
+        Data Patterns: {:?}
+        Suggested Structure: {}
+        
+        Generate a complete API specification including:
+        - OpenAPI 3.0 spec
+        - Authentication methods
+        - Rate limiting
+        - Pricing tiers (Free, Premium, Enterprise)
+        - Error handling
+        - Documentation
+        - SDK examples
+        
+        Return as JSON.",
+        analysis.path,
+        analysis.file_types,
+        analysis.data_patterns,
+        serde_json::to_string_pretty(&analysis.suggested_api_structure).unwrap_or_default()
+    );
+
+    let req = ChatRequest {
+        messages: vec![
+            ChatMessage::system("You are an expert API architect. Create complete API specifications based on data analysis."),
+            ChatMessage::user(prompt),
+        ],
+        max_tokens: 6000,
+        temperature: 0.2,
+        model,
+    };
+
+    // the spec has no fixed shape, so it is asked for as plain text and parsed;
+    // a minimal spec stands in when the reply is not JSON
+    match llm::parse_json(&llm.chat(&req).await?) {
+        Err(LlmError::InvalidJson(_)) => {}
+        result => return result,
+    }
+    Ok(json!({
+        "openapi": "3.0.0",
+        "info": {
+            "title": format!("API for {}", analysis.path),
+            "version": "1.0.0",
+            "description": "Generated API specification"
+        },
+        "servers": [
+            {"url": "https://api.minam.com/v1", "description": "Production server"}
+        ],
+        "paths": {
+            "/data": {
+                "get": {
+                    "summary": "Retrieve processed data",
+                    "parameters": [
+                        {"name": "format", "in": "query", "schema": {"type": "string", "default": "json"}},
+                        {"name": "limit", "in": "query", "schema": {"type": "integer", "default": 100}}
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Successful response",
+                            "content": {
+                                "application/json": {
+                                    "schema": {"type": "object"}
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "apiKey": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "X-API-Key"
+                }
+            }
+        },
+        "security": [{"apiKey": []}]
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::future::BoxFuture;
+    use crate::llm::MockLlm;
+    use super::*;
+
+    fn csv(name: &str, content: String) -> FileInfo {
+        FileInfo { filename: name.into(), file_size: content.len() as u64, file_type: "text/csv".into(), content: content.into_bytes() }
+    }
+
+    // MockLlm that keeps the prompts it was sent.
+    #[derive(Default)]
+    struct Recording {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl LlmProvider for Recording {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn default_model(&self) -> &str {
+            MockLlm.default_model()
+        }
+
+        fn chat<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<String, LlmError>> {
+            MockLlm.chat(req)
+        }
+
+        fn chat_json<'a>(&'a self, req: &'a ChatRequest, schema: Option<&'a JsonSchema>) -> BoxFuture<'a, Result<Value, LlmError>> {
+            let prompt = req.messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.clone());
+            self.prompts.lock().unwrap().extend(prompt);
+            MockLlm.chat_json(req, schema)
+        }
+    }
+
+    #[tokio::test]
+    async fn analyze_fills_the_analysis_from_the_reply() {
+        let file = csv("prices.csv", "ts,symbol,price\n2024-01-01T00:00:00Z,BTC,42000.5\n2024-01-01T01:00:00Z,ETH,2300\n".into());
+        let a = analyze(&MockLlm, &file, None, DEFAULT_PROMPT_TOKENS).await.unwrap();
+        assert_eq!(a.path, "uploaded/prices.csv");
+        // the reply's patterns follow the detected ones
+        assert_eq!(a.data_patterns.last().map(String::as_str), Some("mock"));
+        assert!(a.data_patterns.len() > 1);
+        let api = a.suggested_api_structure.unwrap();
+        assert_eq!(api["endpoints"][0]["path"], "/mock");
+        assert_eq!(api["rate_limits"]["requests_per_minute"], 1);
+        assert_eq!(a.best_model.unwrap().id, "mock");
+        assert_eq!(a.model_reasoning.as_deref(), Some("mock"));
+    }
+
+    #[tokio::test]
+    async fn analyze_keeps_a_wide_file_within_the_budget() {
+        let header: Vec<String> = (0..300).map(|c| format!("column_{c}")).collect();
+        let rows: Vec<String> = (0..50).map(|r| (0..300).map(|c| (r * c).to_string()).collect::<Vec<_>>().join(",")).collect();
+        let file = csv("wide.csv", format!("{}\n{}\n", header.join(","), rows.join("\n")));
+        let llm = Recording::default();
+        analyze(&llm, &file, None, MIN_PROMPT_TOKENS).await.unwrap();
+        let prompts = llm.prompts.lock().unwrap();
+        let prompt = &prompts[0];
+        assert!(tokens(prompt) <= MIN_PROMPT_TOKENS, "{} tokens", tokens(prompt));
+        assert!(prompt.contains("Format: csv, 50 rows, 300 columns"));
+        // columns past the budget are counted instead of listed
+        assert!(prompt.contains("more columns: "));
+        assert!(!prompt.contains("column_299 "));
+        assert!(prompt.ends_with(INSTRUCTIONS));
+    }
+
+    #[tokio::test]
+    async fn generate_spec_falls_back_when_the_reply_is_not_json() {
+        let file = csv("prices.csv", "symbol,price\nBTC,1\n".into());
+        let a = base(&file, profile(&file).as_ref());
+        // the mock's plain chat reply is prose
+        let spec = generate_spec(&MockLlm, &a, None).await.unwrap();
+        assert_eq!(spec["info"]["title"], "API for uploaded/prices.csv");
+    }
+
+    #[test]
+    fn prompt_never_goes_below_the_minimum_budget() {
+        let file = csv("small.csv", "a,b\n1,x\n2,y\n".into());
+        let p = profile(&file);
+        assert_eq!(prompt(&file, p.as_ref(), 0), prompt(&file, p.as_ref(), MIN_PROMPT_TOKENS));
+    }
+}
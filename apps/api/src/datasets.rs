@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use uuid::Uuid;
+use crate::models::*;
+use crate::table::{ChunkedTable, Table};
+
+// Rows of each kind kept in a diff.
+const DIFF_SAMPLES: usize = 5;
+
+// Makes `table` the dataset's next version.
+fn commit(ds: &mut Dataset, operation: &str, table: ChunkedTable, diff: DiffSummary, ingested: Option<IngestCounts>) -> DatasetVersion {
+    let mut v = DatasetVersion::new(ds.version + 1, operation, table.clone(), diff);
+    v.ingested = ingested;
+    ds.version = v.version;
+    ds.rows = table;
+    ds.versions.push(v.clone());
+    v
+}
+
+// A new dataset. With a primary key, rows repeating a key collapse into the last.
+pub fn create(provider_id: Uuid, name: String, description: String, rows: &[Value], primary_key: Vec<String>) -> Result<Dataset, String> {
+    let (rows, counts) = if primary_key.is_empty() {
+        (rows.to_vec(), IngestCounts { inserted: rows.len(), ..Default::default() })
+    } else {
+        check_key(rows, &primary_key)?;
+        merge(rows, &primary_key)
+    };
+    let mut ds = Dataset::new(provider_id, name, description, &rows);
+    ds.key_index = index(&ds.rows.table(), &primary_key);
+    ds.primary_key = primary_key;
+    ds.versions[0].ingested = Some(counts);
+    Ok(ds)
+}
+
+// Adds rows as a new version; a dataset with a primary key upserts them instead.
+// Returns the version and the rows that were written.
+pub fn append(ds: &mut Dataset, rows: &[Value]) -> Result<(DatasetVersion, Vec<Value>), String> {
+    if !ds.primary_key.is_empty() {
+        return upsert(ds, rows, &[]);
+    }
+    let before = ds.rows.len();
+    // shares every chunk with the previous version but the merged tail
+    let mut table = ds.rows.clone();
+    table.append(rows);
+    let diff = DiffSummary { added: rows.len(), unchanged: before, ..Default::default() };
+    let counts = IngestCounts { inserted: rows.len(), ..Default::default() };
+    Ok((commit(ds, "append", table, diff, Some(counts)), rows.to_vec()))
+}
+
+pub fn replace(ds: &mut Dataset, rows: &[Value], key: &[String]) -> Result<DatasetVersion, String> {
+    let key = key_for(ds, key)?;
+    let table = if ds.primary_key.is_empty() {
+        Table::from_rows(rows)
+    } else {
+        check_key(rows, &key)?;
+        Table::from_rows(&merge(rows, &key).0)
+    };
+    let d = diff(&ds.rows.table(), &table, &key);
+    if !ds.primary_key.is_empty() { ds.key_index = index(&table, &key); }
+    Ok(commit(ds, "replace", ChunkedTable::new(table), d.summary, None))
+}
+
+// Inserts rows with a new key and overwrites rows whose key exists; identical
+// rows are left alone. Rows are found through the dataset's key index and
+// patched in place. Returns the new version and the rows that were written.
+pub fn upsert(ds: &mut Dataset, rows: &[Value], key: &[String]) -> Result<(DatasetVersion, Vec<Value>), String> {
+    let key = key_for(ds, key)?;
+    if key.is_empty() {
+        return Err("UPSERT_KEY_REQUIRED".into());
+    }
+    check_key(rows, &key)?;
+    // a key other than the primary key gets an index for this call only
+    let own = key == ds.primary_key;
+    let mut at = if own { std::mem::take(&mut ds.key_index) } else { index(&ds.rows.table(), &key) };
+    let mut table = ds.rows.clone();
+    let before = table.len();
+    // new rows, appended after the loop; a key repeated in the batch points here
+    let mut pending: Vec<Value> = Vec::new();
+    let mut counts = IngestCounts::default();
+    let mut written = Vec::new();
+    for row in rows {
+        let k = key_of(row, &key);
+        match at.get(&k).copied() {
+            Some(i) => {
+                let current = if i < before { table.row(i) } else { pending[i - before].clone() };
+                if canonical(&current) == canonical(row) {
+                    counts.unchanged += 1;
+                    continue;
+                }
+                if i < before { table.set_row(i, row); } else { pending[i - before] = row.clone(); }
+                counts.updated += 1;
+            }
+            None => {
+                at.insert(k, before + pending.len());
+                pending.push(row.clone());
+                counts.inserted += 1;
+            }
+        }
+        written.push(row.clone());
+    }
+    if !pending.is_empty() { table.append(&pending); }
+    if own { ds.key_index = at; }
+    // a key repeated in one batch counts once per write; the totals still add up
+    let diff = DiffSummary {
+        added: counts.inserted,
+        changed: counts.updated,
+        unchanged: table.len().saturating_sub(counts.inserted + counts.updated),
+        removed: 0,
+    };
+    let v = commit(ds, "upsert", table, diff, Some(counts));
+    Ok((v, written))
+}
+
+// Key value → row id; the last row wins when a key repeats.
+fn index(table: &Table, key: &[String]) -> HashMap<String, usize> {
+    if key.is_empty() { return HashMap::new(); }
+    (0..table.len()).map(|i| (key_of(&table.row(i), key), i)).collect()
+}
+
+// The requested key, else the dataset's own. A dataset with a primary key only
+// takes changes keyed by it, so the key stays unique.
+fn key_for(ds: &Dataset, requested: &[String]) -> Result<Vec<String>, String> {
+    match (requested.is_empty(), ds.primary_key.is_empty()) {
+        (true, _) => Ok(ds.primary_key.clone()),
+        (false, false) if requested != ds.primary_key => Err(format!("PRIMARY_KEY_MISMATCH:{}", ds.primary_key.join(","))),
+        _ => Ok(requested.to_vec()),
+    }
+}
+
+fn check_key(rows: &[Value], key: &[String]) -> Result<(), String> {
+    match key.iter().find(|k| rows.iter().any(|r| r.get(k.as_str()).is_none_or(Value::is_null))) {
+        Some(col) => Err(format!("ROW_MISSING_KEY:{col}")),
+        None => Ok(()),
+    }
+}
+
+// Collapses rows repeating a key into the last one, kept at the first one's position.
+fn merge(rows: &[Value], key: &[String]) -> (Vec<Value>, IngestCounts) {
+    let mut out: Vec<Value> = Vec::new();
+    let mut at: HashMap<String, usize> = HashMap::new();
+    let mut counts = IngestCounts::default();
+    for row in rows {
+        match at.get(&key_of(row, key)) {
+            Some(i) if canonical(&out[*i]) == canonical(row) => counts.unchanged += 1,
+            Some(i) => {
+                out[*i] = row.clone();
+                counts.updated += 1;
+            }
+            None => {
+                at.insert(key_of(row, key), out.len());
+                out.push(row.clone());
+                counts.inserted += 1;
+            }
+        }
+    }
+    (out, counts)
+}
+
+pub struct Diff {
+    pub summary: DiffSummary,
+    pub added: Vec<Value>,
+    pub removed: Vec<Value>,
+    pub changed: Vec<RowChange>,
+}
+
+// Row-level comparison. With a key, rows pair up by key (the last row wins when
+// a key repeats) and differing pairs count as changed; without one, rows pair up
+// by identical content.
+pub fn diff(from: &Table, to: &Table, key: &[String]) -> Diff {
+    let (a, b): (Vec<Value>, Vec<Value>) = (from.rows().collect(), to.rows().collect());
+    let mut d = Diff { summary: DiffSummary::default(), added: Vec::new(), removed: Vec::new(), changed: Vec::new() };
+    let sample = |out: &mut Vec<Value>, row: &Value| if out.len() < DIFF_SAMPLES { out.push(row.clone()) };
+    if key.is_empty() {
+        let mut left: HashMap<String, usize> = HashMap::new();
+        for r in a.iter() { *left.entry(canonical(r)).or_default() += 1; }
+        for r in b.iter() {
+            match left.get_mut(&canonical(r)) {
+                Some(n) if *n > 0 => { *n -= 1; d.summary.unchanged += 1; }
+                _ => { d.summary.added += 1; sample(&mut d.added, r); }
+            }
+        }
+        for r in a.iter() {
+            if let Some(n) = left.get_mut(&canonical(r)).filter(|n| **n > 0) {
+                *n -= 1;
+                d.summary.removed += 1;
+                sample(&mut d.removed, r);
+            }
+        }
+        return d;
+    }
+    let last = |rows: &[Value]| -> HashMap<String, usize> { rows.iter().enumerate().map(|(i, r)| (key_of(r, key), i)).collect() };
+    let (ka, kb) = (last(&a), last(&b));
+    for (i, r) in b.iter().enumerate() {
+        let k = key_of(r, key);
+        if kb[&k] != i { continue; }
+        match ka.get(&k) {
+            None => { d.summary.added += 1; sample(&mut d.added, r); }
+            Some(j) if canonical(&a[*j]) == canonical(r) => d.summary.unchanged += 1,
+            Some(j) => {
+                d.summary.changed += 1;
+                if d.changed.len() < DIFF_SAMPLES {
+                    let key_value = Value::Array(key.iter().map(|c| r.get(c).cloned().unwrap_or(Value::Null)).collect());
+                    d.changed.push(RowChange { key: key_value, before: a[*j].clone(), after: r.clone() });
+                }
+            }
+        }
+    }
+    for (i, r) in a.iter().enumerate() {
+        let k = key_of(r, key);
+        if ka[&k] == i && !kb.contains_key(&k) {
+            d.summary.removed += 1;
+            sample(&mut d.removed, r);
+        }
+    }
+    d
+}
+
+fn key_of(row: &Value, key: &[String]) -> String {
+    key.iter().map(|k| canonical(row.get(k).unwrap_or(&Value::Null))).collect::<Vec<_>>().join("\u{1f}")
+}
+
+// Text that is equal for rows that mean the same: keys sorted, nulls dropped and
+// numbers compared by value, so 1 and 1.0 match while large integers stay exact.
+fn canonical(v: &Value) -> String {
+    match v {
+        Value::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(x), _, _) => x.to_string(),
+            (_, Some(x), _) => x.to_string(),
+            // a float with an exact integer value reads as that integer
+            (_, _, Some(x)) if x.fract() == 0.0 && x.abs() < 9_007_199_254_740_992.0 => (x as i64).to_string(),
+            _ => n.to_string(),
+        },
+        Value::Object(o) => {
+            let mut fields: Vec<(&String, String)> = o.iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k, canonical(v))).collect();
+            fields.sort();
+            let inner: Vec<String> = fields.into_iter().map(|(k, v)| format!("{k:?}:{v}")).collect();
+            format!("{{{}}}", inner.join(","))
+        }
+        Value::Array(xs) => format!("[{}]", xs.iter().map(canonical).collect::<Vec<_>>().join(",")),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn dataset(rows: Value, key: &[&str]) -> Dataset {
+        let rows = rows.as_array().unwrap().clone();
+        create(Uuid::new_v4(), "d".into(), "d".into(), &rows, key.iter().map(|k| k.to_string()).collect()).unwrap()
+    }
+
+    fn rows(t: &ChunkedTable) -> Vec<Value> {
+        (0..t.len()).map(|i| t.row(i)).collect()
+    }
+
+    fn summary(d: &DiffSummary) -> (usize, usize, usize, usize) {
+        (d.added, d.removed, d.changed, d.unchanged)
+    }
+
+    #[test]
+    fn append_adds_a_version_and_keeps_the_earlier_ones() {
+        let mut ds = dataset(json!([{"a": 1}]), &[]);
+        let (v, written) = append(&mut ds, &[json!({"a": 2}), json!({"a": 2})]).unwrap();
+        assert_eq!((v.version, v.operation.as_str(), v.row_count), (2, "append", 3));
+        assert_eq!(summary(&v.diff), (2, 0, 0, 1));
+        assert_eq!(written.len(), 2);
+        assert_eq!(rows(&ds.at(1).unwrap().rows), vec![json!({"a": 1})]);
+        assert_eq!(rows(&ds.rows), vec![json!({"a": 1}), json!({"a": 2}), json!({"a": 2})]);
+    }
+
+    #[test]
+    fn replace_diffs_against_the_previous_rows() {
+        let mut ds = dataset(json!([{"a": 1}, {"a": 2}, {"a": 2}]), &[]);
+        let v = replace(&mut ds, &[json!({"a": 2}), json!({"a": 3})], &[]).unwrap();
+        assert_eq!(summary(&v.diff), (1, 2, 0, 1));
+        assert_eq!(ds.at(1).unwrap().row_count, 3);
+        assert_eq!(rows(&ds.rows), vec![json!({"a": 2}), json!({"a": 3})]);
+    }
+
+    #[test]
+    fn diff_without_a_key_pairs_identical_rows() {
+        let from = Table::from_rows(&[json!({"a": 1, "b": null}), json!({"a": 1}), json!({"a": 2})]);
+        let to = Table::from_rows(&[json!({"a": 1.0}), json!({"a": 3})]);
+        let d = diff(&from, &to, &[]);
+        assert_eq!(summary(&d.summary), (1, 2, 0, 1));
+        assert_eq!(d.added, vec![json!({"a": 3})]);
+        assert_eq!(d.removed, vec![json!({"a": 1}), json!({"a": 2})]);
+    }
+
+    #[test]
+    fn diff_with_a_key_reports_changed_rows() {
+        let key = vec!["id".to_string()];
+        let from = Table::from_rows(&[json!({"id": 1, "v": "a"}), json!({"id": 2, "v": "b"}), json!({"id": 3, "v": "c"})]);
+        let to = Table::from_rows(&[json!({"id": 1, "v": "a"}), json!({"id": 2, "v": "B"}), json!({"id": 4, "v": "d"})]);
+        let d = diff(&from, &to, &key);
+        assert_eq!(summary(&d.summary), (1, 1, 1, 1));
+        assert_eq!(d.changed.len(), 1);
+        assert_eq!(d.changed[0].key, json!([2]));
+        assert_eq!(d.changed[0].before, json!({"id": 2, "v": "b"}));
+        assert_eq!(d.changed[0].after, json!({"id": 2, "v": "B"}));
+        assert_eq!(d.removed, vec![json!({"id": 3, "v": "c"})]);
+    }
+
+    #[test]
+    fn diff_keeps_only_a_few_samples() {
+        let rows: Vec<Value> = (0..20).map(|i| json!({"a": i})).collect();
+        let d = diff(&Table::default(), &Table::from_rows(&rows), &[]);
+        assert_eq!(d.summary.added, 20);
+        assert_eq!(d.added.len(), DIFF_SAMPLES);
+    }
+
+    fn counts(c: &IngestCounts) -> (usize, usize, usize) {
+        (c.inserted, c.updated, c.unchanged)
+    }
+
+    #[test]
+    fn create_with_a_key_collapses_repeats_into_the_last_row() {
+        let ds = dataset(json!([{"id": 1, "v": "a"}, {"id": 2, "v": "b"}, {"id": 1, "v": "c"}, {"id": 2, "v": "b"}]), &["id"]);
+        assert_eq!(rows(&ds.rows), vec![json!({"id": 1, "v": "c"}), json!({"id": 2, "v": "b"})]);
+        assert_eq!(counts(ds.versions[0].ingested.as_ref().unwrap()), (2, 1, 1));
+        assert_eq!(ds.key_index.len(), 2);
+    }
+
+    #[test]
+    fn create_rejects_rows_without_the_key() {
+        let rows = [json!({"id": 1}), json!({"id": null})];
+        let err = create(Uuid::new_v4(), "d".into(), "d".into(), &rows, vec!["id".into()]).err();
+        assert_eq!(err.as_deref(), Some("ROW_MISSING_KEY:id"));
+    }
+
+    #[test]
+    fn append_with_a_primary_key_upserts() {
+        let mut ds = dataset(json!([{"id": 1, "v": 1}, {"id": 2, "v": 2}]), &["id"]);
+        let (v, written) = append(&mut ds, &[json!({"id": 1, "v": 1.0}), json!({"id": 2, "v": 5}), json!({"id": 3, "v": 3})]).unwrap();
+        assert_eq!(v.operation, "upsert");
+        assert_eq!(counts(v.ingested.as_ref().unwrap()), (1, 1, 1));
+        assert_eq!(summary(&v.diff), (1, 0, 1, 1));
+        assert_eq!(written, vec![json!({"id": 2, "v": 5}), json!({"id": 3, "v": 3})]);
+        assert_eq!(rows(&ds.rows), vec![json!({"id": 1, "v": 1}), json!({"id": 2, "v": 5}), json!({"id": 3, "v": 3})]);
+        assert_eq!(rows(&ds.at(1).unwrap().rows), vec![json!({"id": 1, "v": 1}), json!({"id": 2, "v": 2})]);
+    }
+
+    #[test]
+    fn upsert_keeps_the_key_index_in_step() {
+        let mut ds = dataset(json!([{"id": "a", "v": 1}]), &["id"]);
+        upsert(&mut ds, &[json!({"id": "b", "v": 2}), json!({"id": "b", "v": 3})], &[]).unwrap();
+        assert_eq!(rows(&ds.rows), vec![json!({"id": "a", "v": 1}), json!({"id": "b", "v": 3})]);
+        let (v, _) = upsert(&mut ds, &[json!({"id": "b", "v": 4})], &[]).unwrap();
+        assert_eq!(counts(v.ingested.as_ref().unwrap()), (0, 1, 0));
+        assert_eq!(ds.rows.len(), 2);
+        assert_eq!(ds.rows.row(1), json!({"id": "b", "v": 4}));
+    }
+
+    #[test]
+    fn changes_must_use_the_primary_key() {
+        let mut ds = dataset(json!([{"id": 1, "v": 1}]), &["id"]);
+        let err = upsert(&mut ds, &[json!({"id": 1, "v": 2})], &["v".to_string()]).err();
+        assert_eq!(err.as_deref(), Some("PRIMARY_KEY_MISMATCH:id"));
+        let mut plain = dataset(json!([{"id": 1}]), &[]);
+        assert_eq!(upsert(&mut plain, &[json!({"id": 1})], &[]).err().as_deref(), Some("UPSERT_KEY_REQUIRED"));
+    }
+
+    #[test]
+    fn upsert_by_a_requested_key_on_a_dataset_without_one() {
+        let mut ds = dataset(json!([{"sym": "A", "p": 1}, {"sym": "B", "p": 2}]), &[]);
+        let (v, _) = upsert(&mut ds, &[json!({"sym": "B", "p": 3})], &["sym".to_string()]).unwrap();
+        assert_eq!(counts(v.ingested.as_ref().unwrap()), (0, 1, 0));
+        assert!(ds.key_index.is_empty());
+        assert_eq!(ds.rows.row(1), json!({"sym": "B", "p": 3}));
+    }
+
+    #[test]
+    fn canonical_matches_rows_that_mean_the_same() {
+        assert_eq!(canonical(&json!({"b": 1, "a": null, "c": [1.0]})), canonical(&json!({"c": [1], "b": 1.0})));
+        assert_ne!(canonical(&json!(9007199254740993u64)), canonical(&json!(9007199254740992u64)));
+        assert_ne!(canonical(&json!(1.5)), canonical(&json!(1)));
+        assert_ne!(canonical(&json!("1")), canonical(&json!(1)));
+        assert_eq!(key_of(&json!({"a": 1, "b": "x"}), &["a".into(), "b".into()]), key_of(&json!({"b": "x", "a": 1.0}), &["a".into(), "b".into()]));
+    }
+}
@@ -0,0 +1,34 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+// Error surfaced by handlers that answer with a real status code. The body keeps
+// the `{"error": "CODE"}` shape the other endpoints use.
+#[derive(Debug, thiserror::Error)]
+#[error("{code}")]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: impl Into<String>) -> Self {
+        Self { status, code: code.into() }
+    }
+
+    pub fn bad_request(code: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code)
+    }
+
+    pub fn not_found(code: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.code }))).into_response()
+    }
+}
@@ -0,0 +1,744 @@
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+use serde_json::Value;
+use crate::models::*;
+use crate::table::{format_micros, Cell, Column, Table};
+
+// Offending rows kept per feature in eval reports.
+const SAMPLE_ROWS: usize = 5;
+// Values a rolling window needs before it judges the next one.
+pub const MIN_HISTORY: usize = 5;
+pub const DEFAULT_WINDOW: usize = 20;
+// Findings of each kind kept in a time-series report.
+const MAX_FINDINGS: usize = 20;
+
+// Whether a non-null value matches a declared dtype. Strings never pass as
+// numbers; datetimes may be stored natively or as RFC 3339 text. Unknown dtypes
+// accept anything.
+pub fn conforms(cell: Cell, dtype: &str) -> bool {
+    match dtype {
+        "number" | "float" | "double" => matches!(cell, Cell::Integer(_) | Cell::Number(_)) || matches!(cell, Cell::Json(v) if v.is_number()),
+        "integer" | "int" => match cell {
+            Cell::Integer(_) => true,
+            Cell::Number(x) => x.fract() == 0.0,
+            Cell::Json(v) => v.is_i64() || v.is_u64() || v.as_f64().is_some_and(|x| x.fract() == 0.0),
+            _ => false,
+        },
+        "bool" | "boolean" => matches!(cell, Cell::Bool(_)) || matches!(cell, Cell::Json(v) if v.is_boolean()),
+        "datetime" | "timestamp" => cell.as_micros().is_some(),
+        "string" => cell.as_str().is_some() || matches!(cell, Cell::Datetime(_)),
+        _ => true,
+    }
+}
+
+pub fn conformance(feat: &FeatureSpec, col: &Column) -> FeatureConformance {
+    let (mut checked, mut bad, mut samples) = (0, 0, Vec::new());
+    for (row, cell) in col.iter().enumerate().filter(|(_, c)| !c.is_null()) {
+        checked += 1;
+        if conforms(cell, &feat.dtype) { continue; }
+        bad += 1;
+        if samples.len() < SAMPLE_ROWS {
+            samples.push(OffendingRow { row, value: cell.to_value() });
+        }
+    }
+    FeatureConformance {
+        name: feat.name.clone(),
+        dtype: feat.dtype.clone(),
+        rate: if checked > 0 { (checked - bad) as f64 / checked as f64 } else { 1.0 },
+        checked,
+        nonconforming: bad,
+        samples,
+    }
+}
+
+// Problems with the constraints themselves, caught when a profile is created.
+pub fn check_spec(feat: &FeatureSpec) -> Result<(), String> {
+    if let (Some(lo), Some(hi)) = (feat.min, feat.max) {
+        if lo > hi { return Err(format!("{}: min is greater than max", feat.name)); }
+    }
+    if let Some(p) = &feat.pattern {
+        Regex::new(p).map_err(|e| format!("{}: invalid pattern: {e}", feat.name))?;
+    }
+    Ok(())
+}
+
+// One report per constraint the feature declares.
+pub fn constraints(feat: &FeatureSpec, col: &Column) -> Result<Vec<ConstraintReport>, String> {
+    let mut reports = Vec::new();
+    let non_null = || col.iter().enumerate().filter(|(_, c)| !c.is_null());
+    if feat.nullable == Some(false) {
+        reports.push(report(feat, "nullable", col.len(), col.iter().enumerate().filter(|(_, c)| c.is_null())));
+    }
+    if let Some(lo) = feat.min {
+        let checked = non_null().filter(|(_, c)| c.as_f64().is_some()).count();
+        reports.push(report(feat, "min", checked, non_null().filter(|(_, c)| c.as_f64().is_some_and(|x| x < lo))));
+    }
+    if let Some(hi) = feat.max {
+        let checked = non_null().filter(|(_, c)| c.as_f64().is_some()).count();
+        reports.push(report(feat, "max", checked, non_null().filter(|(_, c)| c.as_f64().is_some_and(|x| x > hi))));
+    }
+    if let Some(allowed) = &feat.allowed_values {
+        let ok = |c: Cell| {
+            let v = c.to_value();
+            allowed.iter().any(|a| *a == v || a.as_f64().zip(v.as_f64()).is_some_and(|(x, y)| x == y))
+        };
+        reports.push(report(feat, "allowed_values", col.non_null(), non_null().filter(|(_, c)| !ok(*c))));
+    }
+    if let Some(p) = &feat.pattern {
+        let re = Regex::new(p).map_err(|e| format!("INVALID_CONSTRAINT:{}: {e}", feat.name))?;
+        let matches = |c: Cell| match c.to_value() {
+            Value::String(s) => re.is_match(&s),
+            v => re.is_match(&v.to_string()),
+        };
+        reports.push(report(feat, "pattern", col.non_null(), non_null().filter(|(_, c)| !matches(*c))));
+    }
+    if feat.unique == Some(true) {
+        // every repeat after the first occurrence is a violation
+        let mut seen = HashSet::new();
+        let dupes: Vec<(usize, Cell)> = non_null().filter(|(_, c)| !seen.insert(c.to_value().to_string())).collect();
+        reports.push(report(feat, "unique", col.non_null(), dupes.into_iter()));
+    }
+    if let Some(order) = feat.monotonic {
+        let key = |c: Cell| c.as_micros().map(|m| m as f64).or(c.as_f64());
+        let mut prev: Option<f64> = None;
+        let mut breaks = Vec::new();
+        for (i, c) in non_null() {
+            let Some(k) = key(c) else { continue };
+            if let Some(p) = prev {
+                let ok = match order {
+                    Monotonic::Increasing => k >= p,
+                    Monotonic::StrictlyIncreasing => k > p,
+                    Monotonic::Decreasing => k <= p,
+                    Monotonic::StrictlyDecreasing => k < p,
+                };
+                if !ok { breaks.push((i, c)); }
+            }
+            prev = Some(k);
+        }
+        reports.push(report(feat, "monotonic", col.non_null(), breaks.into_iter()));
+    }
+    Ok(reports)
+}
+
+// The profile's primary key as a constraint over the whole row: a key with a
+// null part or seen before is a violation. Reported under the joined feature
+// names, e.g. `symbol+ts`.
+pub fn primary_key(key: &[String], table: &Table) -> ConstraintReport {
+    let cols: Vec<Option<&Column>> = key.iter().map(|k| table.column(k)).collect();
+    let mut seen = HashSet::new();
+    let (mut violations, mut samples) = (0, Vec::new());
+    for row in 0..table.len() {
+        let parts: Vec<Value> = cols.iter().map(|c| c.map(|c| c.get(row).to_value()).unwrap_or(Value::Null)).collect();
+        if !parts.iter().any(Value::is_null) && seen.insert(Value::Array(parts.clone()).to_string()) {
+            continue;
+        }
+        violations += 1;
+        if samples.len() < SAMPLE_ROWS {
+            samples.push(OffendingRow { row, value: Value::Array(parts) });
+        }
+    }
+    ConstraintReport { feature: key.join("+"), constraint: "primary_key".into(), checked: table.len(), violations, samples }
+}
+
+fn report<'a>(feat: &FeatureSpec, constraint: &str, checked: usize, violations: impl Iterator<Item = (usize, Cell<'a>)>) -> ConstraintReport {
+    let mut count = 0;
+    let mut samples = Vec::new();
+    for (row, cell) in violations {
+        count += 1;
+        if samples.len() < SAMPLE_ROWS {
+            samples.push(OffendingRow { row, value: cell.to_value() });
+        }
+    }
+    ConstraintReport { feature: feat.name.clone(), constraint: constraint.into(), checked, violations: count, samples }
+}
+
+// Compares a feature's current values with the same-named reference column.
+// Numbers get PSI and KS, strings and bools category shift; datetimes only the
+// null-rate change, since new data is expected to be later.
+pub fn drift(feat: &FeatureSpec, current: &Column, reference: &Column, limits: &DriftThresholds) -> FeatureDrift {
+    let null_rate = |c: &Column| if c.len() > 0 { 1.0 - c.non_null() as f64 / c.len() as f64 } else { 0.0 };
+    let (null_rate_reference, null_rate_current) = (null_rate(reference), null_rate(current));
+    let numeric = |c: &Column| -> Vec<f64> { c.iter().filter_map(|x| x.as_f64()).collect() };
+    let mut d = FeatureDrift {
+        feature: feat.name.clone(),
+        kind: "other".into(),
+        psi: None,
+        ks: None,
+        category_shift: None,
+        top_shifts: Vec::new(),
+        null_rate_reference,
+        null_rate_current,
+        drifted: false,
+        exceeded: Vec::new(),
+    };
+    match feat.dtype.as_str() {
+        "number" | "float" | "double" | "integer" | "int" => {
+            d.kind = "numeric".into();
+            let (mut cur, mut refr) = (numeric(current), numeric(reference));
+            if !cur.is_empty() && !refr.is_empty() {
+                cur.sort_by(f64::total_cmp);
+                refr.sort_by(f64::total_cmp);
+                d.psi = Some(psi(&refr, &cur));
+                d.ks = Some(ks(&refr, &cur));
+            }
+        }
+        "datetime" | "timestamp" => {}
+        _ => {
+            d.kind = "categorical".into();
+            let (cur, refr) = (frequencies(current), frequencies(reference));
+            if !cur.is_empty() && !refr.is_empty() {
+                let keys: HashSet<&String> = cur.keys().chain(refr.keys()).collect();
+                let mut shifts: Vec<CategoryShift> = keys.into_iter().map(|k| CategoryShift {
+                    value: k.clone(),
+                    reference: refr.get(k).copied().unwrap_or(0.0),
+                    current: cur.get(k).copied().unwrap_or(0.0),
+                }).collect();
+                d.category_shift = Some(0.5 * shifts.iter().map(|s| (s.current - s.reference).abs()).sum::<f64>());
+                shifts.sort_by(|a, b| (b.current - b.reference).abs().total_cmp(&(a.current - a.reference).abs()).then(a.value.cmp(&b.value)));
+                shifts.truncate(5);
+                d.top_shifts = shifts;
+            }
+        }
+    }
+    if d.psi.is_some_and(|x| x > limits.psi) { d.exceeded.push("psi".into()); }
+    if d.ks.is_some_and(|x| x > limits.ks) { d.exceeded.push("ks".into()); }
+    if d.category_shift.is_some_and(|x| x > limits.category_shift) { d.exceeded.push("category_shift".into()); }
+    if (null_rate_current - null_rate_reference).abs() > limits.null_rate_change { d.exceeded.push("null_rate_change".into()); }
+    d.drifted = !d.exceeded.is_empty();
+    d
+}
+
+// Strings as-is, anything else as JSON text.
+fn label(cell: Cell) -> String {
+    match cell.to_value() {
+        Value::String(s) => s,
+        v => v.to_string(),
+    }
+}
+
+// Share of non-null values per distinct value.
+fn frequencies(col: &Column) -> HashMap<String, f64> {
+    let mut counts: HashMap<String, f64> = HashMap::new();
+    for c in col.iter().filter(|c| !c.is_null()) {
+        *counts.entry(label(c)).or_default() += 1.0;
+    }
+    let n = col.non_null().max(1) as f64;
+    counts.values_mut().for_each(|v| *v /= n);
+    counts
+}
+
+// PSI over ten bins cut at the reference deciles. Both inputs are sorted.
+fn psi(reference: &[f64], current: &[f64]) -> f64 {
+    const BINS: usize = 10;
+    const EPS: f64 = 1e-4;
+    let mut edges: Vec<f64> = (1..BINS).map(|i| reference[(i * reference.len() / BINS).min(reference.len() - 1)]).collect();
+    edges.dedup();
+    let shares = |xs: &[f64]| -> Vec<f64> {
+        let mut counts = vec![0usize; edges.len() + 1];
+        for x in xs {
+            counts[edges.partition_point(|e| e <= x)] += 1;
+        }
+        counts.iter().map(|c| (*c as f64 / xs.len() as f64).max(EPS)).collect()
+    };
+    let (r, c) = (shares(reference), shares(current));
+    r.iter().zip(c.iter()).map(|(r, c)| (c - r) * (c / r).ln()).sum()
+}
+
+// Largest gap between the two empirical CDFs. Both inputs are sorted.
+fn ks(a: &[f64], b: &[f64]) -> f64 {
+    let (mut i, mut j, mut d) = (0, 0, 0.0f64);
+    while i < a.len() && j < b.len() {
+        let x = a[i].min(b[j]);
+        while i < a.len() && a[i] <= x { i += 1; }
+        while j < b.len() && b[j] <= x { j += 1; }
+        d = d.max((i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs());
+    }
+    d
+}
+
+pub fn default_outlier_threshold(method: OutlierMethod) -> f64 {
+    match method {
+        OutlierMethod::Zscore | OutlierMethod::Rolling => 3.0,
+        OutlierMethod::Iqr => 1.5,
+    }
+}
+
+// Scores a numeric feature within each group of row ids (already ordered for the
+// rolling method) and returns the report plus the flagged rows.
+pub fn outliers(
+    feat: &FeatureSpec,
+    table: &Table,
+    groups: &[Vec<usize>],
+    group_by: Option<&str>,
+    method: OutlierMethod,
+    threshold: f64,
+    window: usize,
+) -> (OutlierReport, Vec<usize>) {
+    let Some(col) = table.column(&feat.name) else {
+        return (outlier_report(feat, method, threshold, 0, Vec::new()), Vec::new());
+    };
+    let (mut checked, mut flagged) = (0, Vec::new());
+    for group in groups {
+        let points: Vec<(usize, f64)> = group.iter().filter_map(|i| col.get(*i).as_f64().map(|x| (*i, x))).collect();
+        let scores = match method {
+            OutlierMethod::Zscore => zscores(&points),
+            OutlierMethod::Iqr => iqr_scores(&points),
+            OutlierMethod::Rolling => rolling_scores(&points, window),
+        };
+        checked += scores.len();
+        flagged.extend(scores.into_iter().filter(|(_, score)| *score > threshold));
+    }
+    flagged.sort_by_key(|(i, _)| *i);
+    let samples = flagged.iter().take(SAMPLE_ROWS).map(|(i, score)| OutlierSample {
+        row: *i,
+        value: col.get(*i).to_value(),
+        group: group_by.map(|g| label(table.cell(g, *i))),
+        score: (score * 1000.0).round() / 1000.0,
+    }).collect();
+    let mut report = outlier_report(feat, method, threshold, checked, samples);
+    report.count = flagged.len();
+    report.rate = if checked > 0 { flagged.len() as f64 / checked as f64 } else { 0.0 };
+    (report, flagged.into_iter().map(|(i, _)| i).collect())
+}
+
+fn outlier_report(feat: &FeatureSpec, method: OutlierMethod, threshold: f64, checked: usize, samples: Vec<OutlierSample>) -> OutlierReport {
+    OutlierReport {
+        feature: feat.name.clone(),
+        method: method.name().into(),
+        threshold,
+        checked,
+        count: 0,
+        rate: 0.0,
+        samples,
+        quarantined: false,
+    }
+}
+
+// |x - mean| / std; a constant group has no outliers.
+fn zscores(points: &[(usize, f64)]) -> Vec<(usize, f64)> {
+    let n = points.len() as f64;
+    let mean = points.iter().map(|(_, x)| x).sum::<f64>() / n;
+    let std = (points.iter().map(|(_, x)| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+    points.iter().map(|(i, x)| (*i, if std > 0.0 { (x - mean).abs() / std } else { 0.0 })).collect()
+}
+
+// Distance beyond the nearer quartile in IQRs, so `threshold` is the usual fence multiplier.
+fn iqr_scores(points: &[(usize, f64)]) -> Vec<(usize, f64)> {
+    let mut xs: Vec<f64> = points.iter().map(|(_, x)| *x).collect();
+    xs.sort_by(f64::total_cmp);
+    let (q1, q3) = (quantile(&xs, 0.25), quantile(&xs, 0.75));
+    let iqr = q3 - q1;
+    points.iter().map(|(i, x)| {
+        let beyond = (q1 - x).max(x - q3).max(0.0);
+        (*i, if iqr > 0.0 { beyond / iqr } else { 0.0 })
+    }).collect()
+}
+
+// Robust z of each value against the median and MAD of the values before it.
+// The first MIN_HISTORY values of a group are not judged.
+fn rolling_scores(points: &[(usize, f64)], window: usize) -> Vec<(usize, f64)> {
+    let mut scores = Vec::new();
+    for (k, (i, x)) in points.iter().enumerate().skip(MIN_HISTORY) {
+        let mut prior: Vec<f64> = points[k.saturating_sub(window)..k].iter().map(|(_, x)| *x).collect();
+        prior.sort_by(f64::total_cmp);
+        let median = quantile(&prior, 0.5);
+        let mut dev: Vec<f64> = prior.iter().map(|p| (p - median).abs()).collect();
+        dev.sort_by(f64::total_cmp);
+        // a flat window still needs a finite scale; any move off it scores high
+        let scale = (1.4826 * quantile(&dev, 0.5)).max(1e-9 * median.abs().max(1.0));
+        scores.push((*i, (x - median).abs() / scale));
+    }
+    scores
+}
+
+// Linear interpolation between closest ranks. Input is sorted and non-empty.
+fn quantile(xs: &[f64], q: f64) -> f64 {
+    let pos = q * (xs.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    xs[lo] + (xs[hi] - xs[lo]) * (pos - lo as f64)
+}
+
+// Seconds in a cadence or staleness such as 30s, 1m, 5min, 1h, 1d, or hourly.
+pub fn parse_duration(s: &str) -> Option<i64> {
+    let s = s.trim().to_lowercase();
+    match s.as_str() {
+        "minute" | "minutely" => return Some(60),
+        "hour" | "hourly" => return Some(3600),
+        "day" | "daily" => return Some(86_400),
+        _ => {}
+    }
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n: i64 = s[..split].parse().ok().filter(|n| *n > 0)?;
+    let unit = match s[split..].trim() {
+        "" | "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 3600,
+        "d" | "day" | "days" => 86_400,
+        "w" | "week" | "weeks" => 604_800,
+        _ => return None,
+    };
+    // the series checks work in microseconds, so those must fit too
+    n.checked_mul(unit).filter(|secs| secs.checked_mul(1_000_000).is_some())
+}
+
+// Time-series limits, in seconds
+pub struct SeriesLimits {
+    pub cadence: Option<i64>,
+    pub max_staleness: Option<i64>,
+    pub max_gap_rate: f64,
+    pub allow_unordered: bool,
+}
+
+// Checks each entity's series separately: out-of-order pairs in row order, then
+// duplicate timestamps and gaps once sorted. A gap is a step of more than 1.5
+// cadences and counts the whole cadences missing from it. Finding rows are table
+// rows; `now` is epoch microseconds.
+pub fn timeseries(table: &Table, ts: &str, entity: Option<&str>, limits: &SeriesLimits, now: i64) -> TimeseriesReport {
+    let mut r = TimeseriesReport {
+        ts: ts.into(),
+        entity: entity.map(String::from),
+        cadence_secs: limits.cadence,
+        max_staleness_secs: limits.max_staleness,
+        entities: 0,
+        rows: table.len(),
+        missing_ts: 0,
+        duplicates: 0,
+        out_of_order: 0,
+        gaps: 0,
+        missing_intervals: 0,
+        gap_rate: 0.0,
+        latest: None,
+        staleness_secs: None,
+        stale_entities: 0,
+        findings: Vec::new(),
+        pass: true,
+    };
+    let cadence = limits.cadence.and_then(|c| c.checked_mul(1_000_000));
+    let (mut expected, mut latest) = (0usize, None::<i64>);
+    let finding = |kind: &str, name: &Option<String>, rows: Vec<usize>, from: i64, to: i64, missing: Option<usize>| TimeseriesFinding {
+        kind: kind.into(),
+        entity: name.clone(),
+        rows,
+        from: format_micros(from),
+        to: format_micros(to),
+        missing,
+    };
+    for group in table.groups(None, entity) {
+        r.entities += 1;
+        let name = entity.map(|e| label(table.cell(e, group[0])));
+        let mut points: Vec<(usize, i64)> = group.iter().filter_map(|i| table.cell(ts, *i).as_micros().map(|m| (*i, m))).collect();
+        r.missing_ts += group.len() - points.len();
+        for w in points.windows(2) {
+            if w[1].1 >= w[0].1 { continue; }
+            r.out_of_order += 1;
+            if r.out_of_order <= MAX_FINDINGS {
+                r.findings.push(finding("out_of_order", &name, vec![w[0].0, w[1].0], w[0].1, w[1].1, None));
+            }
+        }
+        points.sort_by_key(|(_, m)| *m);
+        for w in points.windows(2) {
+            let step = w[1].1 - w[0].1;
+            if step == 0 {
+                r.duplicates += 1;
+                if r.duplicates <= MAX_FINDINGS {
+                    r.findings.push(finding("duplicate_key", &name, vec![w[0].0, w[1].0], w[0].1, w[1].1, None));
+                }
+            } else if let Some(c) = cadence.filter(|c| 2 * step as i128 > 3 * *c as i128) {
+                let missing = ((step as f64 / c as f64).round() as usize).saturating_sub(1).max(1);
+                r.gaps += 1;
+                r.missing_intervals += missing;
+                if r.gaps <= MAX_FINDINGS {
+                    r.findings.push(finding("gap", &name, vec![w[0].0, w[1].0], w[0].1, w[1].1, Some(missing)));
+                }
+            }
+        }
+        let (Some(first), Some(last)) = (points.first(), points.last()) else { continue };
+        if let Some(c) = cadence {
+            expected += ((last.1 - first.1) as f64 / c as f64).round() as usize + 1;
+        }
+        latest = latest.max(Some(last.1));
+        let max_staleness = limits.max_staleness.and_then(|max| max.checked_mul(1_000_000));
+        if max_staleness.is_some_and(|max| now.saturating_sub(last.1) > max) {
+            r.stale_entities += 1;
+            if r.stale_entities <= MAX_FINDINGS {
+                r.findings.push(finding("stale", &name, vec![last.0], last.1, now, None));
+            }
+        }
+    }
+    r.gap_rate = if expected > 0 { (r.missing_intervals as f64 / expected as f64).min(1.0) } else { 0.0 };
+    r.latest = latest.map(format_micros);
+    r.staleness_secs = latest.map(|l| (now - l) / 1_000_000);
+    r.pass = r.duplicates == 0
+        && (limits.allow_unordered || r.out_of_order == 0)
+        && r.gap_rate <= limits.max_gap_rate
+        && r.stale_entities == 0;
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::table::parse_micros;
+    use super::*;
+
+    fn feature(spec: Value) -> FeatureSpec {
+        serde_json::from_value(spec).unwrap()
+    }
+
+    fn table(rows: Value) -> Table {
+        Table::from_rows(rows.as_array().unwrap())
+    }
+
+    fn column(values: Value) -> Column {
+        Column::from_values("f", values.as_array().unwrap().iter().map(Some))
+    }
+
+    #[test]
+    fn conforms_checks_the_declared_dtype() {
+        let ok = |v: Value, dtype: &str| conforms(column(json!([v])).get(0), dtype);
+        assert!(ok(json!(1.5), "number") && ok(json!(2), "number"));
+        assert!(!ok(json!("1.5"), "number"));
+        assert!(ok(json!(3), "integer") && !ok(json!(3.5), "integer"));
+        assert!(ok(json!(true), "bool") && !ok(json!("true"), "bool"));
+        assert!(ok(json!("2024-01-01T00:00:00Z"), "datetime") && ok(json!("2024-01-01 00:00:00Z"), "datetime"));
+        assert!(!ok(json!("yesterday"), "datetime"));
+        assert!(ok(json!("2024-01-01T00:00:00Z"), "string") && !ok(json!(1), "string"));
+        assert!(ok(json!({"a": 1}), "whatever"));
+        // a mixed column stores JSON; the check still looks at each value
+        let mixed = column(json!([1, "x", 2.0, 2.5]));
+        assert_eq!(mixed.iter().map(|c| conforms(c, "integer")).collect::<Vec<_>>(), vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn conformance_rates_non_null_values_and_samples_offenders() {
+        let r = conformance(&feature(json!({"name": "f", "dtype": "number"})), &column(json!([1, null, "x", 2.5, "y"])));
+        assert_eq!((r.checked, r.nonconforming), (4, 2));
+        assert_eq!(r.rate, 0.5);
+        assert_eq!(r.samples.iter().map(|s| (s.row, s.value.clone())).collect::<Vec<_>>(), vec![(2, json!("x")), (4, json!("y"))]);
+        let empty = conformance(&feature(json!({"name": "f", "dtype": "number"})), &column(json!([null])));
+        assert_eq!((empty.checked, empty.rate), (0, 1.0));
+    }
+
+    // (constraint, checked, violations, sampled rows) per report
+    fn violations(spec: Value, values: Value) -> Vec<(String, usize, usize, Vec<usize>)> {
+        constraints(&feature(spec), &column(values)).unwrap().into_iter()
+            .map(|r| (r.constraint, r.checked, r.violations, r.samples.iter().map(|s| s.row).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn check_spec_rejects_impossible_constraints() {
+        assert!(check_spec(&feature(json!({"name": "f", "dtype": "number", "min": 1, "max": 0}))).is_err());
+        assert!(check_spec(&feature(json!({"name": "f", "dtype": "string", "pattern": "("}))).is_err());
+        assert!(check_spec(&feature(json!({"name": "f", "dtype": "number", "min": 0, "max": 0, "pattern": "^[0-9]+$"}))).is_ok());
+    }
+
+    #[test]
+    fn ranges_check_only_numeric_values() {
+        let spec = json!({"name": "f", "dtype": "number", "nullable": false, "min": 0, "max": 10});
+        assert_eq!(violations(spec, json!([-1, 5, null, "x", 11, 10])), vec![
+            ("nullable".to_string(), 6, 1, vec![2]),
+            ("min".to_string(), 4, 1, vec![0]),
+            ("max".to_string(), 4, 1, vec![4]),
+        ]);
+    }
+
+    #[test]
+    fn allowed_values_compare_numbers_by_value() {
+        let spec = json!({"name": "f", "dtype": "number", "allowed_values": [1, 2.5, "a"]});
+        assert_eq!(violations(spec, json!([1.0, 2.5, "a", 3, null])), vec![("allowed_values".to_string(), 4, 1, vec![3])]);
+    }
+
+    #[test]
+    fn patterns_match_the_value_text() {
+        let spec = json!({"name": "f", "dtype": "string", "pattern": "^[A-Z]+-USD$"});
+        assert_eq!(violations(spec, json!(["BTC-USD", "eth-usd", null, "SOL-USD"])), vec![("pattern".to_string(), 3, 1, vec![1])]);
+        let numbers = json!({"name": "f", "dtype": "number", "pattern": "^\\d+$"});
+        assert_eq!(violations(numbers, json!([12, 1.5])), vec![("pattern".to_string(), 2, 1, vec![1])]);
+    }
+
+    #[test]
+    fn unique_counts_every_repeat_after_the_first() {
+        let spec = json!({"name": "f", "dtype": "string", "unique": true});
+        assert_eq!(violations(spec, json!(["a", "b", "a", null, null, "a"])), vec![("unique".to_string(), 4, 2, vec![2, 5])]);
+    }
+
+    #[test]
+    fn monotonic_flags_each_break_in_row_order() {
+        let strict = json!({"name": "f", "dtype": "number", "monotonic": "strictly_increasing"});
+        assert_eq!(violations(strict, json!([1, 2, 2, null, 3, 1])), vec![("monotonic".to_string(), 5, 2, vec![2, 5])]);
+        let loose = json!({"name": "f", "dtype": "datetime", "monotonic": "increasing"});
+        let ts = json!(["2024-01-01T00:00:00Z", "2024-01-01T00:00:00Z", "2023-12-31T00:00:00Z"]);
+        assert_eq!(violations(loose, ts), vec![("monotonic".to_string(), 3, 1, vec![2])]);
+    }
+
+    #[test]
+    fn primary_key_flags_repeats_and_partial_keys() {
+        let t = table(json!([
+            {"symbol": "A", "ts": 1},
+            {"symbol": "A", "ts": 2},
+            {"symbol": "A", "ts": 1},
+            {"symbol": null, "ts": 3},
+            {"symbol": "B", "ts": 1},
+        ]));
+        let r = primary_key(&["symbol".into(), "ts".into()], &t);
+        assert_eq!((r.feature.as_str(), r.checked, r.violations), ("symbol+ts", 5, 2));
+        assert_eq!(r.samples.iter().map(|s| (s.row, s.value.clone())).collect::<Vec<_>>(), vec![(2, json!(["A", 1])), (3, json!([null, 3]))]);
+    }
+
+    fn drift_of(dtype: &str, current: Value, reference: Value) -> FeatureDrift {
+        drift(&feature(json!({"name": "f", "dtype": dtype})), &column(current), &column(reference), &DriftThresholds::default())
+    }
+
+    #[test]
+    fn identical_numbers_do_not_drift() {
+        let xs: Vec<f64> = (0..100).map(f64::from).collect();
+        let d = drift_of("number", json!(xs), json!(xs));
+        assert_eq!(d.kind, "numeric");
+        assert!(d.psi.unwrap().abs() < 1e-12);
+        assert_eq!(d.ks, Some(0.0));
+        assert!(!d.drifted);
+    }
+
+    #[test]
+    fn shifted_numbers_drift_on_psi_and_ks() {
+        let reference: Vec<f64> = (0..100).map(f64::from).collect();
+        let current: Vec<f64> = (50..150).map(f64::from).collect();
+        let d = drift_of("integer", json!(current), json!(reference));
+        assert_eq!(d.ks, Some(0.5));
+        assert!(d.psi.unwrap() > 0.25);
+        assert_eq!(d.exceeded, vec!["psi", "ks"]);
+        assert!(d.drifted);
+    }
+
+    #[test]
+    fn ks_is_the_largest_gap_between_the_cdfs() {
+        assert_eq!(ks(&[1.0, 2.0, 3.0, 4.0], &[1.0, 2.0, 3.0, 4.0]), 0.0);
+        assert_eq!(ks(&[1.0, 2.0], &[3.0, 4.0]), 1.0);
+        assert_eq!(ks(&[1.0, 2.0, 3.0, 4.0], &[3.0, 4.0]), 0.5);
+    }
+
+    #[test]
+    fn categories_drift_on_total_variation() {
+        let d = drift_of("string", json!(["a", "a", "a", "b"]), json!(["a", "b", "b", "b"]));
+        assert_eq!(d.kind, "categorical");
+        assert_eq!(d.category_shift, Some(0.5));
+        assert_eq!(d.top_shifts.iter().map(|s| (s.value.as_str(), s.reference, s.current)).collect::<Vec<_>>(), vec![("a", 0.25, 0.75), ("b", 0.75, 0.25)]);
+        assert!(d.drifted);
+    }
+
+    #[test]
+    fn datetimes_only_compare_null_rates() {
+        let d = drift_of("datetime", json!(["2025-01-01T00:00:00Z", null]), json!(["2024-01-01T00:00:00Z", "2024-01-02T00:00:00Z"]));
+        assert_eq!((d.psi, d.ks, d.category_shift), (None, None, None));
+        assert_eq!((d.null_rate_reference, d.null_rate_current), (0.0, 0.5));
+        assert_eq!(d.exceeded, vec!["null_rate_change"]);
+    }
+
+    fn flagged(rows: Value, method: OutlierMethod, group_by: Option<&str>) -> (OutlierReport, Vec<usize>) {
+        let t = table(rows);
+        let groups = t.groups(Some("ts"), group_by);
+        let feat = feature(json!({"name": "x", "dtype": "number"}));
+        outliers(&feat, &t, &groups, group_by, method, default_outlier_threshold(method), DEFAULT_WINDOW)
+    }
+
+    fn series(xs: &[f64]) -> Value {
+        json!(xs.iter().enumerate().map(|(ts, x)| json!({"ts": ts, "x": x})).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn zscore_flags_values_far_from_the_mean() {
+        let mut xs = vec![1.0; 19];
+        xs.push(100.0);
+        let (r, rows) = flagged(series(&xs), OutlierMethod::Zscore, None);
+        assert_eq!(rows, vec![19]);
+        assert_eq!((r.checked, r.count, r.rate), (20, 1, 0.05));
+        assert_eq!(r.samples[0].score, 4.359);
+        // a constant series has nothing to flag
+        assert!(flagged(series(&[5.0; 10]), OutlierMethod::Zscore, None).1.is_empty());
+    }
+
+    #[test]
+    fn iqr_scores_distance_past_the_quartiles() {
+        let (r, rows) = flagged(series(&[1.0, 2.0, 3.0, 4.0, 100.0, -20.0]), OutlierMethod::Iqr, None);
+        assert_eq!(rows, vec![4, 5]);
+        assert_eq!(r.threshold, 1.5);
+    }
+
+    #[test]
+    fn rolling_judges_each_value_against_the_ones_before_it() {
+        let (r, rows) = flagged(series(&[10.0, 11.0, 10.0, 9.0, 10.0, 10.0, 50.0, 10.0]), OutlierMethod::Rolling, None);
+        assert_eq!(rows, vec![6]);
+        // the first MIN_HISTORY values are not judged
+        assert_eq!(r.checked, 3);
+        let (_, rows) = flagged(series(&[100.0, 10.0, 10.0, 10.0, 10.0, 10.0]), OutlierMethod::Rolling, None);
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn groups_are_scored_separately() {
+        let mut rows = Vec::new();
+        for ts in 0..10 {
+            rows.push(json!({"ts": ts, "sym": "A", "x": 1000 + ts % 2}));
+            rows.push(json!({"ts": ts, "sym": "B", "x": 1 + ts % 2}));
+        }
+        rows.push(json!({"ts": 10, "sym": "B", "x": 40}));
+        let (r, flagged_rows) = flagged(json!(rows), OutlierMethod::Zscore, Some("sym"));
+        assert_eq!(flagged_rows, vec![20]);
+        assert_eq!(r.samples[0].group.as_deref(), Some("B"));
+        assert_eq!(r.checked, 21);
+    }
+
+    #[test]
+    fn parse_duration_reads_cadences() {
+        assert_eq!(parse_duration("30s"), Some(30));
+        assert_eq!(parse_duration("5 min"), Some(300));
+        assert_eq!(parse_duration("1H"), Some(3600));
+        assert_eq!(parse_duration("hourly"), Some(3600));
+        assert_eq!(parse_duration("2w"), Some(1_209_600));
+        assert_eq!(parse_duration("45"), Some(45));
+        for bad in ["", "0s", "-1m", "5 fortnights", "m", "999999999999999d"] {
+            assert_eq!(parse_duration(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn timeseries_finds_gaps_duplicates_disorder_and_staleness() {
+        let at = |min: u32| format!("2024-01-01T00:{min:02}:00Z");
+        let t = table(json!([
+            {"sym": "A", "ts": at(0)},
+            {"sym": "B", "ts": at(2)},
+            {"sym": "A", "ts": at(1)},
+            {"sym": "B", "ts": at(1)},
+            {"sym": "A", "ts": at(4)},
+            {"sym": "A", "ts": at(4)},
+            {"sym": "A", "ts": null},
+        ]));
+        let limits = SeriesLimits { cadence: Some(60), max_staleness: Some(600), max_gap_rate: 0.5, allow_unordered: false };
+        let now = parse_micros(&at(13)).unwrap();
+        let r = timeseries(&t, "ts", Some("sym"), &limits, now);
+        assert_eq!((r.entities, r.rows, r.missing_ts), (2, 7, 1));
+        assert_eq!((r.duplicates, r.out_of_order, r.gaps, r.missing_intervals), (1, 1, 1, 2));
+        assert!((r.gap_rate - 2.0 / 7.0).abs() < 1e-12);
+        assert_eq!((r.latest.as_deref(), r.staleness_secs, r.stale_entities), (Some("2024-01-01T00:04:00Z"), Some(540), 1));
+        let kinds: Vec<(&str, Option<&str>, &[usize])> = r.findings.iter().map(|f| (f.kind.as_str(), f.entity.as_deref(), f.rows.as_slice())).collect();
+        assert_eq!(kinds, vec![
+            ("gap", Some("A"), &[2, 4][..]),
+            ("duplicate_key", Some("A"), &[4, 5][..]),
+            ("out_of_order", Some("B"), &[1, 3][..]),
+            ("stale", Some("B"), &[1][..]),
+        ]);
+        assert_eq!(r.findings[0].missing, Some(2));
+        assert!(!r.pass);
+    }
+
+    #[test]
+    fn timeseries_passes_a_clean_series() {
+        let t = table(json!([{"ts": "2024-01-01T00:00:00Z"}, {"ts": "2024-01-01T00:01:00Z"}, {"ts": "2024-01-01T00:02:00Z"}]));
+        let limits = SeriesLimits { cadence: Some(60), max_staleness: Some(60), max_gap_rate: 0.0, allow_unordered: false };
+        let r = timeseries(&t, "ts", None, &limits, parse_micros("2024-01-01T00:03:00Z").unwrap());
+        assert!(r.pass && r.findings.is_empty());
+        assert_eq!((r.entities, r.missing_ts, r.gap_rate, r.staleness_secs), (1, 0, 0.0, Some(60)));
+    }
+}
@@ -0,0 +1,356 @@
+use std::sync::Arc;
+
+use arrow_array::builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder, TimestampMicrosecondBuilder};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use crate::models::FeatureSpec;
+use crate::table::Table;
+
+// Wire formats a consumer can ask for on the query endpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    Ndjson,
+    Csv,
+    Arrow,
+}
+
+impl ResponseFormat {
+    pub fn from_param(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "ndjson" | "jsonl" | "jsonlines" => Some(Self::Ndjson),
+            "csv" => Some(Self::Csv),
+            "arrow" | "ipc" | "arrow-ipc" => Some(Self::Arrow),
+            _ => None,
+        }
+    }
+
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "application/json" | "*/*" | "application/*" => Some(Self::Json),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" => Some(Self::Ndjson),
+            "text/csv" | "text/*" => Some(Self::Csv),
+            "application/vnd.apache.arrow.stream" | "application/vnd.apache.arrow.file" => Some(Self::Arrow),
+            _ => None,
+        }
+    }
+
+    // Picks the format from an Accept header, honouring q-values.
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        let mut best: Option<(f32, Self)> = None;
+        for part in accept.split(',') {
+            let mut pieces = part.split(';');
+            let mime = pieces.next().unwrap_or("").trim().to_ascii_lowercase();
+            let q = pieces
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if q <= 0.0 { continue; }
+            if let Some(fmt) = Self::from_mime(&mime) {
+                if best.map(|(bq, _)| q > bq).unwrap_or(true) {
+                    best = Some((q, fmt));
+                }
+            }
+        }
+        best.map(|(_, fmt)| fmt)
+    }
+
+    // An explicit `format` parameter wins over the Accept header; JSON is the default.
+    pub fn negotiate(param: Option<&str>, headers: &HeaderMap) -> Result<Self, String> {
+        if let Some(p) = param {
+            return Self::from_param(p).ok_or_else(|| format!("UNSUPPORTED_FORMAT:{p}"));
+        }
+        let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Ok(Self::Json);
+        };
+        if accept.trim().is_empty() {
+            return Ok(Self::Json);
+        }
+        Self::from_accept(accept).ok_or_else(|| "NOT_ACCEPTABLE".to_string())
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Arrow => "application/vnd.apache.arrow.stream",
+        }
+    }
+}
+
+// Columns used for CSV headers and Arrow schemas. Falls back to the dataset's own
+// columns when the product has no profile to describe it.
+pub fn columns_for(features: Option<&[FeatureSpec]>, table: &Table) -> Vec<FeatureSpec> {
+    if let Some(f) = features {
+        if !f.is_empty() { return f.to_vec(); }
+    }
+    table
+        .columns()
+        .iter()
+        .map(|c| FeatureSpec { name: c.name.clone(), dtype: c.dtype().into(), ..Default::default() })
+        .collect()
+}
+
+// Wraps an already-encoded body with the content type for `format`.
+pub fn respond(format: ResponseFormat, body: Body) -> Response {
+    let mut res = Response::new(body);
+    res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    res
+}
+
+fn csv_field(v: Option<&serde_json::Value>) -> String {
+    match v {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn arrow_type(dtype: &str) -> DataType {
+    match dtype {
+        "number" | "float" | "double" => DataType::Float64,
+        "integer" | "int" => DataType::Int64,
+        "bool" | "boolean" => DataType::Boolean,
+        "datetime" | "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        _ => DataType::Utf8,
+    }
+}
+
+fn arrow_column(col: &FeatureSpec, rows: &[serde_json::Value]) -> ArrayRef {
+    let values = rows.iter().map(|r| r.get(&col.name).filter(|v| !v.is_null()));
+    match arrow_type(&col.dtype) {
+        DataType::Float64 => {
+            let mut b = Float64Builder::with_capacity(rows.len());
+            values.for_each(|v| b.append_option(v.and_then(|v| v.as_f64())));
+            Arc::new(b.finish())
+        }
+        DataType::Int64 => {
+            let mut b = Int64Builder::with_capacity(rows.len());
+            values.for_each(|v| b.append_option(v.and_then(|v| v.as_i64())));
+            Arc::new(b.finish())
+        }
+        DataType::Boolean => {
+            let mut b = BooleanBuilder::with_capacity(rows.len());
+            values.for_each(|v| b.append_option(v.and_then(|v| v.as_bool())));
+            Arc::new(b.finish())
+        }
+        DataType::Timestamp(..) => {
+            let mut b = TimestampMicrosecondBuilder::with_capacity(rows.len()).with_timezone("UTC");
+            values.for_each(|v| {
+                let ts = v
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| s.parse::<chrono::DateTime<chrono::Utc>>().ok())
+                    .map(|t| t.timestamp_micros());
+                b.append_option(ts);
+            });
+            Arc::new(b.finish())
+        }
+        _ => {
+            let mut b = StringBuilder::with_capacity(rows.len(), rows.len() * 8);
+            values.for_each(|v| b.append_option(v.map(|v| csv_field(Some(v)))));
+            Arc::new(b.finish())
+        }
+    }
+}
+
+pub fn arrow_schema(columns: &[FeatureSpec]) -> Arc<Schema> {
+    Arc::new(Schema::new(
+        columns
+            .iter()
+            .map(|c| Field::new(&c.name, arrow_type(&c.dtype), true))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+// Incremental encoder so result sets can be written chunk by chunk without
+// ever holding the whole response in memory.
+pub enum Encoder {
+    Json { first: bool },
+    Ndjson,
+    Csv { columns: Vec<FeatureSpec> },
+    Arrow { columns: Vec<FeatureSpec>, schema: Arc<Schema>, writer: StreamWriter<Vec<u8>> },
+}
+
+impl Encoder {
+    pub fn new(format: ResponseFormat, columns: Vec<FeatureSpec>) -> Result<Self, String> {
+        Ok(match format {
+            ResponseFormat::Json => Self::Json { first: true },
+            ResponseFormat::Ndjson => Self::Ndjson,
+            ResponseFormat::Csv => Self::Csv { columns },
+            ResponseFormat::Arrow => {
+                let schema = arrow_schema(&columns);
+                let writer = StreamWriter::try_new(Vec::new(), &schema).map_err(|e| e.to_string())?;
+                Self::Arrow { columns, schema, writer }
+            }
+        })
+    }
+
+    // Bytes that open the response: JSON bracket, CSV header row or Arrow schema message.
+    pub fn begin(&mut self) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json { .. } => Ok(b"[".to_vec()),
+            Self::Ndjson => Ok(Vec::new()),
+            Self::Csv { columns } => {
+                let mut w = csv::Writer::from_writer(Vec::new());
+                w.write_record(columns.iter().map(|c| c.name.as_str())).map_err(|e| e.to_string())?;
+                w.into_inner().map_err(|e| e.to_string())
+            }
+            Self::Arrow { writer, .. } => Ok(std::mem::take(writer.get_mut())),
+        }
+    }
+
+    pub fn encode(&mut self, rows: &[serde_json::Value]) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json { first } => {
+                let mut out = Vec::new();
+                for row in rows {
+                    if !*first { out.push(b','); }
+                    *first = false;
+                    serde_json::to_writer(&mut out, row).map_err(|e| e.to_string())?;
+                }
+                Ok(out)
+            }
+            Self::Ndjson => {
+                let mut out = Vec::new();
+                for row in rows {
+                    serde_json::to_writer(&mut out, row).map_err(|e| e.to_string())?;
+                    out.push(b'\n');
+                }
+                Ok(out)
+            }
+            Self::Csv { columns } => {
+                let mut w = csv::Writer::from_writer(Vec::new());
+                for row in rows {
+                    w.write_record(columns.iter().map(|c| csv_field(row.get(&c.name)))).map_err(|e| e.to_string())?;
+                }
+                w.into_inner().map_err(|e| e.to_string())
+            }
+            Self::Arrow { columns, schema, writer } => {
+                if rows.is_empty() { return Ok(Vec::new()); }
+                let arrays: Vec<ArrayRef> = columns.iter().map(|c| arrow_column(c, rows)).collect();
+                let opts = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+                let batch = RecordBatch::try_new_with_options(schema.clone(), arrays, &opts)
+                    .map_err(|e| e.to_string())?;
+                writer.write(&batch).map_err(|e| e.to_string())?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json { .. } => Ok(b"]".to_vec()),
+            Self::Ndjson | Self::Csv { .. } => Ok(Vec::new()),
+            Self::Arrow { mut writer, .. } => {
+                writer.finish().map_err(|e| e.to_string())?;
+                writer.into_inner().map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Array, BooleanArray, Float64Array, Int64Array, StringArray, TimestampMicrosecondArray};
+    use arrow_ipc::reader::StreamReader;
+    use serde_json::json;
+    use super::*;
+
+    fn columns() -> Vec<FeatureSpec> {
+        [("symbol", "string"), ("ts", "datetime"), ("price", "number"), ("n", "integer"), ("ok", "bool")]
+            .map(|(name, dtype)| FeatureSpec { name: name.into(), dtype: dtype.into(), ..Default::default() })
+            .to_vec()
+    }
+
+    fn rows() -> Vec<serde_json::Value> {
+        vec![
+            json!({"symbol": "A,1", "ts": "2024-01-01T00:00:00Z", "price": 1.5, "n": 1, "ok": true}),
+            json!({"symbol": "B", "ts": null, "price": 2, "ok": false, "extra": "x"}),
+        ]
+    }
+
+    // every byte an encoder writes for `rows`, sent in chunks of one row
+    fn encode(format: ResponseFormat, rows: &[serde_json::Value]) -> Vec<u8> {
+        let mut enc = Encoder::new(format, columns()).unwrap();
+        let mut out = enc.begin().unwrap();
+        for row in rows.chunks(1) {
+            out.extend(enc.encode(row).unwrap());
+        }
+        out.extend(enc.finish().unwrap());
+        out
+    }
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        h
+    }
+
+    #[test]
+    fn the_format_parameter_wins_over_accept() {
+        assert_eq!(ResponseFormat::negotiate(Some("CSV"), &accept("application/x-ndjson")), Ok(ResponseFormat::Csv));
+        assert_eq!(ResponseFormat::negotiate(Some("jsonl"), &HeaderMap::new()), Ok(ResponseFormat::Ndjson));
+        assert_eq!(ResponseFormat::negotiate(Some("xml"), &HeaderMap::new()), Err("UNSUPPORTED_FORMAT:xml".into()));
+    }
+
+    #[test]
+    fn accept_headers_are_ranked_by_q_value() {
+        let negotiate = |h: &str| ResponseFormat::negotiate(None, &accept(h));
+        assert_eq!(ResponseFormat::negotiate(None, &HeaderMap::new()), Ok(ResponseFormat::Json));
+        assert_eq!(negotiate(" "), Ok(ResponseFormat::Json));
+        assert_eq!(negotiate("text/csv;q=0.5, application/vnd.apache.arrow.stream"), Ok(ResponseFormat::Arrow));
+        assert_eq!(negotiate("application/json;q=0.2, text/csv;q=0.9"), Ok(ResponseFormat::Csv));
+        assert_eq!(negotiate("text/csv;q=0, */*;q=0.1"), Ok(ResponseFormat::Json));
+        assert_eq!(negotiate("application/xml"), Err("NOT_ACCEPTABLE".into()));
+    }
+
+    #[test]
+    fn json_and_ndjson_survive_chunking() {
+        let json = encode(ResponseFormat::Json, &rows());
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&json).unwrap(), json!(rows()));
+        assert_eq!(encode(ResponseFormat::Json, &[]), b"[]");
+        let ndjson = String::from_utf8(encode(ResponseFormat::Ndjson, &rows())).unwrap();
+        let lines: Vec<serde_json::Value> = ndjson.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines, rows());
+    }
+
+    #[test]
+    fn csv_writes_the_profile_columns_with_quoting() {
+        let csv = String::from_utf8(encode(ResponseFormat::Csv, &rows())).unwrap();
+        assert_eq!(csv, "symbol,ts,price,n,ok\n\"A,1\",2024-01-01T00:00:00Z,1.5,1,true\nB,,2,,false\n");
+    }
+
+    #[test]
+    fn arrow_streams_typed_batches() {
+        let bytes = encode(ResponseFormat::Arrow, &rows());
+        let reader = StreamReader::try_new(std::io::Cursor::new(bytes), None).unwrap();
+        assert_eq!(reader.schema(), arrow_schema(&columns()));
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(), vec![1, 1]);
+        let b = &batches[1];
+        let col = |name: &str| b.column_by_name(name).unwrap().clone();
+        assert_eq!(col("symbol").as_any().downcast_ref::<StringArray>().unwrap().value(0), "B");
+        assert!(col("ts").as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap().is_null(0));
+        assert_eq!(col("price").as_any().downcast_ref::<Float64Array>().unwrap().value(0), 2.0);
+        assert!(col("n").as_any().downcast_ref::<Int64Array>().unwrap().is_null(0));
+        assert!(!col("ok").as_any().downcast_ref::<BooleanArray>().unwrap().value(0));
+        let ts = batches[0].column_by_name("ts").unwrap().as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap().value(0);
+        assert_eq!(ts, 1_704_067_200_000_000);
+    }
+
+    #[test]
+    fn columns_fall_back_to_the_table() {
+        let table = Table::from_rows(&[json!({"a": 1, "b": "x"})]);
+        let cols = columns_for(Some(&[]), &table);
+        assert_eq!(cols.iter().map(|c| (c.name.as_str(), c.dtype.as_str())).collect::<Vec<_>>(), vec![("a", "integer"), ("b", "string")]);
+        assert_eq!(columns_for(Some(&columns()), &table).len(), 5);
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value;
+use crate::models::{Imputation, ImputationReport};
+use crate::table::{Column, Table};
+
+// Applies per-feature strategies to the mapped table. Fills run first, then rows
+// still null in a `drop` feature are removed. `progress` is told the share of
+// features done and may abort the run. Also returns the ids of the rows kept.
+pub fn impute(
+    table: &Table,
+    strategies: &BTreeMap<String, Imputation>,
+    default: Option<&Imputation>,
+    mut progress: impl FnMut(f64) -> Result<(), String>,
+) -> Result<(Table, Vec<ImputationReport>, Vec<usize>), String> {
+    if let Some(name) = strategies.keys().find(|k| table.column(k).is_none()) {
+        return Err(format!("IMPUTE_UNKNOWN_FEATURE:{name}"));
+    }
+    let total = table.columns().len().max(1) as f64;
+    let mut reports = Vec::new();
+    let mut columns = Vec::with_capacity(table.columns().len());
+    let mut drop_features = Vec::new();
+    for (n, col) in table.columns().iter().enumerate() {
+        progress(n as f64 / total)?;
+        let Some(strategy) = strategies.get(&col.name).or(default) else {
+            columns.push(col.clone());
+            continue;
+        };
+        if matches!(strategy, Imputation::Drop) || col.non_null() == col.len() {
+            if matches!(strategy, Imputation::Drop) { drop_features.push(columns.len()); }
+            columns.push(col.clone());
+            reports.push(ImputationReport { feature: col.name.clone(), strategy: strategy.name().into(), filled: 0, dropped: 0 });
+            continue;
+        }
+        let mut values: Vec<Value> = col.iter().map(|c| c.to_value()).collect();
+        let filled = fill(table, &mut values, strategy, col.dtype() == "integer")?;
+        columns.push(Column::from_values(&col.name, values.iter().map(Some)));
+        reports.push(ImputationReport { feature: col.name.clone(), strategy: strategy.name().into(), filled, dropped: 0 });
+    }
+    let imputed = Table::from_columns(table.len(), columns);
+    if drop_features.is_empty() {
+        return Ok((imputed, reports, (0..table.len()).collect()));
+    }
+    let keep: Vec<usize> = (0..imputed.len())
+        .filter(|i| drop_features.iter().all(|c| !imputed.columns()[*c].get(*i).is_null()))
+        .collect();
+    for c in drop_features.iter() {
+        let col = &imputed.columns()[*c];
+        if let Some(r) = reports.iter_mut().find(|r| r.feature == col.name) {
+            r.dropped = col.len() - col.non_null();
+        }
+    }
+    Ok((imputed.take(&keep), reports, keep))
+}
+
+// Fills nulls in place and returns how many were filled. Computed fills (mean,
+// median, interpolation) are rounded when the column holds integers.
+fn fill(table: &Table, values: &mut [Value], strategy: &Imputation, integer: bool) -> Result<usize, String> {
+    let computed = |x: f64| if integer { Value::from(x.round() as i64) } else { number(x) };
+    let before = values.iter().filter(|v| v.is_null()).count();
+    match strategy {
+        Imputation::Drop => {}
+        Imputation::Constant { value } => fill_with(values, value),
+        Imputation::Mean => {
+            let xs = numbers(values);
+            if !xs.is_empty() {
+                fill_with(values, &computed(xs.iter().sum::<f64>() / xs.len() as f64));
+            }
+        }
+        Imputation::Median => {
+            let mut xs = numbers(values);
+            if !xs.is_empty() {
+                xs.sort_by(f64::total_cmp);
+                let mid = xs.len() / 2;
+                let m = if xs.len().is_multiple_of(2) { (xs[mid - 1] + xs[mid]) / 2.0 } else { xs[mid] };
+                fill_with(values, &computed(m));
+            }
+        }
+        Imputation::Mode => {
+            // most frequent value; ties go to the one seen first
+            let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+            for (i, v) in values.iter().enumerate().filter(|(_, v)| !v.is_null()) {
+                counts.entry(v.to_string()).or_insert((0, i)).0 += 1;
+            }
+            let best = counts.values().max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1))).map(|(_, i)| values[*i].clone());
+            if let Some(mode) = best { fill_with(values, &mode); }
+        }
+        Imputation::Ffill { sort_by, partition_by } | Imputation::Bfill { sort_by, partition_by } => {
+            let forward = matches!(strategy, Imputation::Ffill { .. });
+            for mut group in groups(table, sort_by.as_deref(), partition_by.as_deref())? {
+                if !forward { group.reverse(); }
+                let mut last: Option<Value> = None;
+                for i in group {
+                    match (&values[i], &last) {
+                        (Value::Null, Some(v)) => values[i] = v.clone(),
+                        (Value::Null, None) => {}
+                        (v, _) => last = Some(v.clone()),
+                    }
+                }
+            }
+        }
+        Imputation::Interpolate { sort_by, partition_by } => {
+            for group in groups(table, sort_by.as_deref(), partition_by.as_deref())? {
+                // (position along the key, value) of every known numeric point
+                let xs: Vec<Option<f64>> = group.iter().enumerate()
+                    .map(|(pos, i)| match sort_by.as_deref() {
+                        Some(k) => table.sort_key(k, *i),
+                        None => Some(pos as f64),
+                    })
+                    .collect();
+                let known: Vec<(f64, f64)> = group.iter().zip(xs.iter())
+                    .filter_map(|(i, x)| Some((*x)?).zip(values[*i].as_f64()))
+                    .collect();
+                for (i, x) in group.iter().zip(xs.iter()) {
+                    let Some(x) = x else { continue };
+                    if !values[*i].is_null() { continue; }
+                    let after = known.partition_point(|(kx, _)| kx < x);
+                    if after == 0 || after == known.len() { continue; }
+                    let ((x0, y0), (x1, y1)) = (known[after - 1], known[after]);
+                    let y = if x1 == x0 { y0 } else { y0 + (y1 - y0) * (x - x0) / (x1 - x0) };
+                    values[*i] = computed(y);
+                }
+            }
+        }
+    }
+    Ok(before - values.iter().filter(|v| v.is_null()).count())
+}
+
+fn fill_with(values: &mut [Value], with: &Value) {
+    values.iter_mut().filter(|v| v.is_null()).for_each(|v| *v = with.clone());
+}
+
+fn numbers(values: &[Value]) -> Vec<f64> {
+    values.iter().filter_map(Value::as_f64).collect()
+}
+
+fn number(x: f64) -> Value {
+    serde_json::Number::from_f64(x).map(Value::Number).unwrap_or(Value::Null)
+}
+
+// Row groups for ordered strategies; the named columns must exist.
+fn groups(table: &Table, sort_by: Option<&str>, partition_by: Option<&str>) -> Result<Vec<Vec<usize>>, String> {
+    for name in sort_by.iter().chain(partition_by.iter()) {
+        if table.column(name).is_none() {
+            return Err(format!("IMPUTE_UNKNOWN_COLUMN:{name}"));
+        }
+    }
+    Ok(table.groups(sort_by, partition_by))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn strategy(s: Value) -> Imputation {
+        serde_json::from_value(s).unwrap()
+    }
+
+    // the imputed `x` column, and the reports
+    fn run(rows: Value, s: Value) -> (Vec<Value>, Vec<ImputationReport>) {
+        let table = Table::from_rows(rows.as_array().unwrap());
+        let strategies = [("x".to_string(), strategy(s))].into();
+        let (out, reports, _) = impute(&table, &strategies, None, |_| Ok(())).unwrap();
+        ((0..out.len()).map(|i| out.cell("x", i).to_value()).collect(), reports)
+    }
+
+    fn xs(values: Value) -> Value {
+        json!(values.as_array().unwrap().iter().enumerate().map(|(i, x)| json!({"i": i, "x": x})).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn summary_fills_use_the_known_values() {
+        let rows = xs(json!([1.0, null, 2.0, null, 6.0]));
+        assert_eq!(run(rows.clone(), json!({"strategy": "mean"})).0, vec![json!(1.0), json!(3.0), json!(2.0), json!(3.0), json!(6.0)]);
+        assert_eq!(run(rows.clone(), json!({"strategy": "median"})).0[1], json!(2.0));
+        assert_eq!(run(rows.clone(), json!({"strategy": "constant", "value": 0})).0[3], json!(0));
+        let (values, reports) = run(xs(json!(["a", null, "b", "b", "a"])), json!({"strategy": "mode"}));
+        assert_eq!(values[1], json!("a"));
+        assert_eq!((reports[0].strategy.as_str(), reports[0].filled), ("mode", 1));
+    }
+
+    #[test]
+    fn integer_features_get_whole_numbers() {
+        let rows = xs(json!([1, null, 2, null, 5]));
+        assert_eq!(run(rows.clone(), json!({"strategy": "mean"})).0[1], json!(3));
+        assert_eq!(run(xs(json!([1, null, 2])), json!({"strategy": "median"})).0[1], json!(2));
+        assert_eq!(run(rows, json!({"strategy": "interpolate"})).0, vec![json!(1), json!(2), json!(2), json!(4), json!(5)]);
+    }
+
+    #[test]
+    fn directional_fills_run_within_partitions_along_the_sort_key() {
+        let rows = json!([
+            {"sym": "A", "ts": 2, "x": null},
+            {"sym": "B", "ts": 1, "x": 10},
+            {"sym": "A", "ts": 1, "x": 1},
+            {"sym": "B", "ts": 2, "x": null},
+            {"sym": "A", "ts": 3, "x": 3},
+            {"sym": "A", "ts": 0, "x": null},
+        ]);
+        let ffill = json!({"strategy": "ffill", "sort_by": "ts", "partition_by": "sym"});
+        assert_eq!(run(rows.clone(), ffill).0, vec![json!(1), json!(10), json!(1), json!(10), json!(3), json!(null)]);
+        let bfill = json!({"strategy": "bfill", "sort_by": "ts", "partition_by": "sym"});
+        assert_eq!(run(rows.clone(), bfill).0, vec![json!(3), json!(10), json!(1), json!(null), json!(3), json!(1)]);
+        let interpolate = json!({"strategy": "interpolate", "sort_by": "ts", "partition_by": "sym"});
+        assert_eq!(run(rows, interpolate).0, vec![json!(2), json!(10), json!(1), json!(null), json!(3), json!(null)]);
+    }
+
+    #[test]
+    fn drop_removes_rows_still_null_after_the_fills() {
+        let table = Table::from_rows(&[json!({"x": 1, "y": null}), json!({"x": null, "y": 2}), json!({"x": null, "y": null})]);
+        let strategies = [("x".to_string(), strategy(json!({"strategy": "drop"})))].into();
+        let default = strategy(json!({"strategy": "constant", "value": 0}));
+        let (out, reports, kept) = impute(&table, &strategies, Some(&default), |_| Ok(())).unwrap();
+        assert_eq!(kept, vec![0]);
+        assert_eq!(out.rows().collect::<Vec<_>>(), vec![json!({"x": 1, "y": 0})]);
+        let by_feature: Vec<(&str, usize, usize)> = reports.iter().map(|r| (r.feature.as_str(), r.filled, r.dropped)).collect();
+        assert_eq!(by_feature, vec![("x", 0, 2), ("y", 2, 0)]);
+    }
+
+    #[test]
+    fn unknown_features_and_columns_are_errors() {
+        let table = Table::from_rows(&[json!({"x": null})]);
+        let strategies = [("nope".to_string(), strategy(json!({"strategy": "mean"})))].into();
+        assert_eq!(impute(&table, &strategies, None, |_| Ok(())).err().as_deref(), Some("IMPUTE_UNKNOWN_FEATURE:nope"));
+        let strategies = [("x".to_string(), strategy(json!({"strategy": "ffill", "sort_by": "ts"})))].into();
+        assert_eq!(impute(&table, &strategies, None, |_| Ok(())).err().as_deref(), Some("IMPUTE_UNKNOWN_COLUMN:ts"));
+    }
+}
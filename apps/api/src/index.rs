@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use crate::table::{Cell, Column, ColumnData, Table};
+
+// Equality lookups on a low-cardinality (dictionary encoded) string column.
+#[derive(Default)]
+pub struct HashIndex {
+    pub buckets: HashMap<String, Vec<u32>>,
+    // null or missing rows; the symbol filter lets these through
+    pub missing: Vec<u32>,
+}
+
+// Range lookups on a datetime or numeric column; entries are sorted by key.
+pub struct SortedIndex<K> {
+    pub entries: Vec<(K, u32)>,
+    // rows whose value is absent or null
+    pub unkeyed: Vec<u32>,
+}
+
+impl<K: PartialOrd + Copy> SortedIndex<K> {
+    fn build(mut entries: Vec<(K, u32)>, unkeyed: Vec<u32>) -> Self {
+        entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Self { entries, unkeyed }
+    }
+
+    // Row ids with lo <= key <= hi, found by binary search.
+    pub fn range(&self, lo: Option<K>, hi: Option<K>) -> impl Iterator<Item = u32> + '_ {
+        let from = lo.map(|lo| self.entries.partition_point(|(k, _)| *k < lo)).unwrap_or(0);
+        let to = hi.map(|hi| self.entries.partition_point(|(k, _)| *k <= hi)).unwrap_or(self.entries.len());
+        self.entries[from..to.max(from)].iter().map(|(_, id)| *id)
+    }
+}
+
+pub enum ColumnIndex {
+    Hash(HashIndex),
+    // microseconds since the epoch
+    Datetime(SortedIndex<i64>),
+    Number(SortedIndex<f64>),
+}
+
+// Secondary indexes over the rows an API version serves, built once at publish
+#[derive(Default)]
+pub struct DatasetIndexes {
+    pub columns: HashMap<String, ColumnIndex>,
+}
+
+impl DatasetIndexes {
+    pub fn build(table: &Table) -> Self {
+        let columns = table
+            .columns()
+            .iter()
+            .filter_map(|col| index_column(col).map(|idx| (col.name.clone(), idx)))
+            .collect();
+        Self { columns }
+    }
+
+    pub fn get(&self, column: &str) -> Option<&ColumnIndex> {
+        self.columns.get(column)
+    }
+}
+
+fn sorted<K: PartialOrd + Copy>(col: &Column, key: impl Fn(Cell) -> Option<K>) -> SortedIndex<K> {
+    let mut entries = Vec::with_capacity(col.non_null());
+    let mut unkeyed = Vec::new();
+    for (id, cell) in col.iter().enumerate() {
+        match key(cell) {
+            Some(k) => entries.push((k, id as u32)),
+            None => unkeyed.push(id as u32),
+        }
+    }
+    SortedIndex::build(entries, unkeyed)
+}
+
+// The column's storage type decides the index: dictionary strings get a hash index,
+// datetimes and numbers a sorted one. Strings that all parse as timestamps are
+// indexed as datetimes even when their text is not canonical.
+fn index_column(col: &Column) -> Option<ColumnIndex> {
+    match &col.data {
+        ColumnData::Datetime(_) => Some(ColumnIndex::Datetime(sorted(col, |c| c.as_micros()))),
+        ColumnData::Integer(_) | ColumnData::Number { .. } => Some(ColumnIndex::Number(sorted(col, |c| c.as_f64()))),
+        ColumnData::Dict { .. } | ColumnData::Utf8 { .. }
+            if col.iter().all(|c| c.is_null() || c.as_micros().is_some()) =>
+        {
+            Some(ColumnIndex::Datetime(sorted(col, |c| c.as_micros())))
+        }
+        ColumnData::Dict { values, codes, .. } => {
+            let mut idx = HashIndex::default();
+            for (id, code) in codes.iter().enumerate() {
+                if col.validity.get(id) {
+                    idx.buckets.entry(values[*code as usize].clone()).or_default().push(id as u32);
+                } else {
+                    idx.missing.push(id as u32);
+                }
+            }
+            Some(ColumnIndex::Hash(idx))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn indexes(rows: serde_json::Value) -> DatasetIndexes {
+        DatasetIndexes::build(&Table::from_rows(rows.as_array().unwrap()))
+    }
+
+    #[test]
+    fn storage_type_picks_the_index() {
+        let idx = indexes(json!([
+            {"symbol": "A", "ts": "2024-01-01T00:00:00Z", "loose": "2024-01-01 00:00:00Z", "price": 1.5, "n": 1, "ok": true},
+            {"symbol": "B", "ts": "2024-01-02T00:00:00Z", "loose": null, "price": 2, "n": 2, "ok": false},
+        ]));
+        assert!(matches!(idx.get("symbol"), Some(ColumnIndex::Hash(_))));
+        assert!(matches!(idx.get("ts"), Some(ColumnIndex::Datetime(_))));
+        assert!(matches!(idx.get("loose"), Some(ColumnIndex::Datetime(_))));
+        assert!(matches!(idx.get("price"), Some(ColumnIndex::Number(_))));
+        assert!(matches!(idx.get("n"), Some(ColumnIndex::Number(_))));
+        assert!(idx.get("ok").is_none());
+    }
+
+    #[test]
+    fn hash_index_buckets_rows_and_keeps_missing_ones_apart() {
+        let idx = indexes(json!([{"symbol": "A"}, {"symbol": "B"}, {"x": 1}, {"symbol": "A"}]));
+        let Some(ColumnIndex::Hash(h)) = idx.get("symbol") else { panic!("no hash index") };
+        assert_eq!(h.buckets["A"], vec![0, 3]);
+        assert_eq!(h.buckets["B"], vec![1]);
+        assert_eq!(h.missing, vec![2]);
+    }
+
+    #[test]
+    fn sorted_range_is_inclusive_and_open_ended() {
+        let idx = indexes(json!([{"p": 3}, {"p": 1}, {"p": null}, {"p": 2}, {"p": 2}]));
+        let Some(ColumnIndex::Number(s)) = idx.get("p") else { panic!("no sorted index") };
+        let range = |lo, hi| { let mut ids: Vec<u32> = s.range(lo, hi).collect(); ids.sort(); ids };
+        assert_eq!(range(Some(2.0), Some(2.0)), vec![3, 4]);
+        assert_eq!(range(Some(2.0), None), vec![0, 3, 4]);
+        assert_eq!(range(None, Some(1.5)), vec![1]);
+        assert_eq!(range(Some(3.0), Some(1.0)), Vec::<u32>::new());
+        assert_eq!(s.unkeyed, vec![2]);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::state::FileInfo;
+
+// Reads an uploaded file into row objects. CSV cells are typed on the way in
+// (integers, floats, booleans, empty = null); JSON may be an array of objects,
+// an object with a `rows` array, or newline-delimited objects.
+pub fn parse_rows(file: &FileInfo) -> Result<Vec<serde_json::Value>, String> {
+    let name = file.filename.to_lowercase();
+    if name.ends_with(".csv") || file.file_type.contains("csv") {
+        return parse_csv(&file.content);
+    }
+    if name.ends_with(".json") || name.ends_with(".ndjson") || name.ends_with(".jsonl") || file.file_type.contains("json") {
+        return parse_json(&file.content);
+    }
+    Err(format!("UNSUPPORTED_FILE_TYPE:{}", file.file_type))
+}
+
+pub fn parse_csv(content: &[u8]) -> Result<Vec<serde_json::Value>, String> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).from_reader(content);
+    let headers = rdr.headers().map_err(|e| e.to_string())?.clone();
+    let mut rows = Vec::new();
+    for rec in rdr.records() {
+        let rec = rec.map_err(|e| e.to_string())?;
+        let row: serde_json::Map<String, serde_json::Value> = headers
+            .iter()
+            .zip(rec.iter())
+            .map(|(h, v)| (h.trim().to_string(), csv_value(v)))
+            .collect();
+        rows.push(serde_json::Value::Object(row));
+    }
+    Ok(rows)
+}
+
+pub fn csv_value(raw: &str) -> serde_json::Value {
+    let v = raw.trim();
+    if v.is_empty() { return serde_json::Value::Null; }
+    if let Ok(i) = v.parse::<i64>() { return i.into(); }
+    if let Ok(x) = v.parse::<f64>() {
+        if let Some(n) = serde_json::Number::from_f64(x) { return serde_json::Value::Number(n); }
+    }
+    match v {
+        "true" | "TRUE" | "True" => serde_json::Value::Bool(true),
+        "false" | "FALSE" | "False" => serde_json::Value::Bool(false),
+        _ => serde_json::Value::String(v.to_string()),
+    }
+}
+
+pub fn parse_json(content: &[u8]) -> Result<Vec<serde_json::Value>, String> {
+    match serde_json::from_slice::<serde_json::Value>(content) {
+        Ok(serde_json::Value::Array(rows)) => Ok(rows),
+        Ok(serde_json::Value::Object(mut obj)) => match obj.remove("rows") {
+            Some(serde_json::Value::Array(rows)) => Ok(rows),
+            _ => Ok(vec![serde_json::Value::Object(obj)]),
+        },
+        Ok(_) => Err("JSON_NOT_TABULAR".into()),
+        // not a single document: try one object per line
+        Err(_) => content
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+            .map(|line| serde_json::from_slice(line).map_err(|e| e.to_string()))
+            .collect(),
+    }
+}
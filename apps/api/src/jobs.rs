@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+use crate::models::*;
+use crate::pipeline;
+use crate::state::{AppState, Store};
+
+// Jobs waiting for a worker before new submissions are turned away.
+const QUEUE_CAPACITY: usize = 64;
+const DEFAULT_WORKERS: usize = 4;
+
+pub const CANCELLED: &str = "CANCELLED";
+
+// Pending job ids shared by the worker pool
+#[derive(Clone)]
+pub struct JobQueue {
+    tx: mpsc::Sender<Uuid>,
+    rx: Arc<Mutex<mpsc::Receiver<Uuid>>>,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        Self { tx, rx: Arc::new(Mutex::new(rx)) }
+    }
+}
+
+// Starts the workers; PIPELINE_WORKERS overrides the pool size.
+pub fn spawn_workers(state: &AppState) {
+    let workers = std::env::var("PIPELINE_WORKERS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_WORKERS);
+    for _ in 0..workers {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let next = state.queue.rx.lock().await.recv().await;
+                let Some(id) = next else { break };
+                run_job(state.store.clone(), id).await;
+            }
+        });
+    }
+}
+
+// Validates the recipe and queues it. Returns the new job.
+pub fn enqueue(state: &AppState, def: PipelineDef) -> Result<PipelineJob, String> {
+    let plan = pipeline::validate(&def)?;
+    let job = PipelineJob {
+        id: Uuid::new_v4(),
+        status: JobStatus::Queued,
+        progress: 0.0,
+        steps: plan.iter().map(StepReport::pending).collect(),
+        proposal_id: None,
+        result: None,
+        error: None,
+        created_at: chrono::Utc::now(),
+        started_at: None,
+        finished_at: None,
+        definition: def,
+        plan,
+        cancel: Arc::new(AtomicBool::new(false)),
+    };
+    state.store.jobs.insert(job.id, job.clone());
+    if state.queue.tx.try_send(job.id).is_err() {
+        state.store.jobs.remove(&job.id);
+        return Err("PIPELINE_QUEUE_FULL".into());
+    }
+    Ok(job)
+}
+
+// Flags a job for cancellation. Queued jobs stop immediately; running ones stop at
+// their next progress check.
+pub fn cancel(store: &Store, id: Uuid) -> Result<PipelineJob, String> {
+    let mut job = store.jobs.get_mut(&id).ok_or("JOB_NOT_FOUND")?;
+    if job.status.is_finished() {
+        return Err("JOB_ALREADY_FINISHED".into());
+    }
+    job.cancel.store(true, Ordering::Relaxed);
+    if job.status == JobStatus::Queued {
+        job.status = JobStatus::Cancelled;
+        job.finished_at = Some(chrono::Utc::now());
+        job.steps.iter_mut().for_each(|s| s.status = StepStatus::Skipped);
+    }
+    Ok(job.clone())
+}
+
+async fn run_job(store: Store, id: Uuid) {
+    let (def, plan, cancel) = {
+        let Some(mut job) = store.jobs.get_mut(&id) else { return };
+        if job.status != JobStatus::Queued { return; }
+        job.status = JobStatus::Running;
+        job.started_at = Some(chrono::Utc::now());
+        (job.definition.clone(), job.plan.clone(), job.cancel.clone())
+    };
+    let ctl = JobCtl { id, store: store.clone(), cancel };
+    let worker_store = store.clone();
+    let outcome = tokio::task::spawn_blocking(move || pipeline::run(&worker_store, &def, &plan, &ctl)).await;
+    let Some(mut job) = store.jobs.get_mut(&id) else { return };
+    job.finished_at = Some(chrono::Utc::now());
+    match outcome {
+        Ok(res) => {
+            job.status = match res.error.as_deref() {
+                None => JobStatus::Succeeded,
+                Some(CANCELLED) => JobStatus::Cancelled,
+                Some(_) => JobStatus::Failed,
+            };
+            if job.status == JobStatus::Succeeded { job.progress = 1.0; }
+            job.proposal_id = res.proposal_id;
+            job.result = res.result;
+            job.error = res.error;
+        }
+        Err(e) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(format!("WORKER_PANICKED:{e}"));
+        }
+    }
+}
+
+// Handle a running pipeline uses to report progress and observe cancellation
+pub struct JobCtl {
+    id: Uuid,
+    store: Store,
+    cancel: Arc<AtomicBool>,
+}
+
+impl JobCtl {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn update(&self, step: usize, status: StepStatus, progress: f64, detail: Option<String>) {
+        let Some(mut job) = self.store.jobs.get_mut(&self.id) else { return };
+        if let Some(s) = job.steps.get_mut(step) {
+            s.status = status;
+            s.progress = progress.clamp(0.0, 1.0);
+            if detail.is_some() { s.detail = detail; }
+        }
+        let n = job.steps.len().max(1) as f64;
+        job.progress = job.steps.iter().map(|s| s.progress).sum::<f64>() / n;
+    }
+
+    // Records progress within a running step; errors once the job has been cancelled.
+    pub fn progress(&self, step: usize, progress: f64) -> Result<(), String> {
+        if self.is_cancelled() { return Err(CANCELLED.into()); }
+        self.update(step, StepStatus::Running, progress, None);
+        Ok(())
+    }
+}
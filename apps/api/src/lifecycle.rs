@@ -0,0 +1,157 @@
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use chrono::{DateTime, Utc};
+use crate::error::ApiError;
+use crate::models::*;
+
+fn allowed(from: ApiStatus, to: ApiStatus) -> bool {
+    use ApiStatus::*;
+    matches!(
+        (from, to),
+        (Draft, Live | Retired) | (Live, Paused | Deprecated) | (Paused, Live | Deprecated | Retired) | (Deprecated, Live | Retired)
+    )
+}
+
+pub fn event(from: Option<ApiStatus>, to: ApiStatus, reason: Option<String>) -> LifecycleEvent {
+    LifecycleEvent { at: Utc::now(), from, to, reason }
+}
+
+// Moves a product to another state. Deprecation needs a future sunset date; any
+// other state clears it.
+pub fn transition(api: &mut ApiProduct, req: ApiStatusChange) -> Result<(), String> {
+    if req.provider_id != api.provider_id {
+        return Err("PROVIDER_MISMATCH".into());
+    }
+    let from = api.status;
+    if !allowed(from, req.status) {
+        return Err(format!("INVALID_TRANSITION:{}->{}", from.name(), req.status.name()));
+    }
+    api.sunset_at = match (req.status, req.sunset_at) {
+        (ApiStatus::Deprecated, None) => return Err("SUNSET_REQUIRED".into()),
+        (ApiStatus::Deprecated, Some(at)) if at <= Utc::now() => return Err("SUNSET_IN_PAST".into()),
+        (ApiStatus::Deprecated, at) => at,
+        _ => None,
+    };
+    api.status = req.status;
+    let reason = req.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    api.lifecycle.push(event(Some(from), req.status, reason));
+    Ok(())
+}
+
+// Whether consumers may query or subscribe. A deprecated product past its
+// sunset answers like a retired one.
+pub fn serving(api: &ApiProduct, now: DateTime<Utc>) -> Result<(), ApiError> {
+    match api.status {
+        ApiStatus::Live => Ok(()),
+        ApiStatus::Deprecated if api.sunset_at.is_none_or(|s| now < s) => Ok(()),
+        ApiStatus::Draft => Err(ApiError::not_found("API_NOT_PUBLISHED")),
+        ApiStatus::Paused => Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "API_PAUSED")),
+        ApiStatus::Deprecated | ApiStatus::Retired => Err(ApiError::new(StatusCode::GONE, "API_RETIRED")),
+    }
+}
+
+// Deprecation (RFC 9745) and Sunset (RFC 8594) headers for deprecated products.
+pub fn headers(api: &ApiProduct) -> HeaderMap {
+    let mut out = HeaderMap::new();
+    if api.status != ApiStatus::Deprecated {
+        return out;
+    }
+    let since = api.lifecycle.iter().rev().find(|e| e.to == ApiStatus::Deprecated).map(|e| e.at);
+    if let Some(Ok(v)) = since.map(|at| HeaderValue::from_str(&format!("@{}", at.timestamp()))) {
+        out.insert("deprecation", v);
+    }
+    if let Some(Ok(v)) = api.sunset_at.map(|at| HeaderValue::from_str(&at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())) {
+        out.insert("sunset", v);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::json;
+    use uuid::Uuid;
+    use super::*;
+
+    const ALL: [ApiStatus; 5] = [ApiStatus::Draft, ApiStatus::Live, ApiStatus::Paused, ApiStatus::Deprecated, ApiStatus::Retired];
+
+    fn product(status: ApiStatus) -> ApiProduct {
+        serde_json::from_value(json!({
+            "id": Uuid::new_v4(), "name": "p", "pricing": "free", "provider_id": Uuid::nil(),
+            "dataset_id": Uuid::new_v4(), "model_profile_id": Uuid::new_v4(), "version": "1",
+            "status": status, "human_approval_note": "",
+        })).unwrap()
+    }
+
+    fn change(status: ApiStatus, sunset_at: Option<DateTime<Utc>>) -> ApiStatusChange {
+        ApiStatusChange { provider_id: Uuid::nil(), status, sunset_at, reason: Some("  planned  ".into()) }
+    }
+
+    #[test]
+    fn transitions_follow_the_table() {
+        let mut allowed_pairs = Vec::new();
+        for from in ALL {
+            for to in ALL {
+                let mut api = product(from);
+                if transition(&mut api, change(to, Some(Utc::now() + Duration::days(30)))).is_ok() {
+                    allowed_pairs.push((from.name(), to.name()));
+                }
+            }
+        }
+        assert_eq!(allowed_pairs, vec![
+            ("draft", "live"), ("draft", "retired"),
+            ("live", "paused"), ("live", "deprecated"),
+            ("paused", "live"), ("paused", "deprecated"), ("paused", "retired"),
+            ("deprecated", "live"), ("deprecated", "retired"),
+        ]);
+        let err = transition(&mut product(ApiStatus::Retired), change(ApiStatus::Live, None));
+        assert_eq!(err, Err("INVALID_TRANSITION:retired->live".into()));
+    }
+
+    #[test]
+    fn only_the_provider_moves_a_product() {
+        let mut api = product(ApiStatus::Draft);
+        let req = ApiStatusChange { provider_id: Uuid::new_v4(), ..change(ApiStatus::Live, None) };
+        assert_eq!(transition(&mut api, req), Err("PROVIDER_MISMATCH".into()));
+        assert_eq!(api.status, ApiStatus::Draft);
+    }
+
+    #[test]
+    fn deprecation_needs_a_future_sunset_and_leaving_it_clears_the_sunset() {
+        let mut api = product(ApiStatus::Live);
+        assert_eq!(transition(&mut api, change(ApiStatus::Deprecated, None)), Err("SUNSET_REQUIRED".into()));
+        assert_eq!(transition(&mut api, change(ApiStatus::Deprecated, Some(Utc::now() - Duration::hours(1)))), Err("SUNSET_IN_PAST".into()));
+        let sunset = Utc::now() + Duration::days(7);
+        transition(&mut api, change(ApiStatus::Deprecated, Some(sunset))).unwrap();
+        assert_eq!((api.status, api.sunset_at), (ApiStatus::Deprecated, Some(sunset)));
+        transition(&mut api, change(ApiStatus::Live, Some(sunset))).unwrap();
+        assert_eq!(api.sunset_at, None);
+        let last = api.lifecycle.last().unwrap();
+        assert_eq!((last.from, last.to, last.reason.as_deref()), (Some(ApiStatus::Deprecated), ApiStatus::Live, Some("planned")));
+    }
+
+    #[test]
+    fn serving_depends_on_status_and_sunset() {
+        let now = Utc::now();
+        let code = |api: &ApiProduct, at| serving(api, at).err().map(|e| (e.status.as_u16(), e.code));
+        assert_eq!(code(&product(ApiStatus::Live), now), None);
+        assert_eq!(code(&product(ApiStatus::Draft), now), Some((404, "API_NOT_PUBLISHED".into())));
+        assert_eq!(code(&product(ApiStatus::Paused), now), Some((503, "API_PAUSED".into())));
+        assert_eq!(code(&product(ApiStatus::Retired), now), Some((410, "API_RETIRED".into())));
+        let mut deprecated = product(ApiStatus::Deprecated);
+        deprecated.sunset_at = Some(now + Duration::days(1));
+        assert_eq!(code(&deprecated, now), None);
+        assert_eq!(code(&deprecated, now + Duration::days(2)), Some((410, "API_RETIRED".into())));
+    }
+
+    #[test]
+    fn deprecated_products_carry_deprecation_and_sunset_headers() {
+        let mut api = product(ApiStatus::Live);
+        assert!(headers(&api).is_empty());
+        let sunset = "2031-05-06T07:08:09Z".parse::<DateTime<Utc>>().unwrap();
+        transition(&mut api, change(ApiStatus::Deprecated, Some(sunset))).unwrap();
+        let h = headers(&api);
+        let since = api.lifecycle.last().unwrap().at.timestamp();
+        assert_eq!(h["deprecation"], format!("@{since}").as_str());
+        assert_eq!(h["sunset"], "Tue, 06 May 2031 07:08:09 GMT");
+    }
+}
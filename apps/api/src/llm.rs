@@ -0,0 +1,471 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use futures::future::BoxFuture;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::error::ApiError;
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o";
+const TIMEOUT: Duration = Duration::from_secs(120);
+// Schema problems listed in a repair prompt.
+const MAX_PROBLEMS: usize = 10;
+
+#[derive(Clone, Serialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".into(), content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".into(), content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: "assistant".into(), content: content.into() }
+    }
+}
+
+pub struct ChatRequest {
+    // provider default when absent
+    pub model: Option<String>,
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+}
+
+// JSON schema the reply must follow, named as OpenAI's `response_format` wants.
+pub struct JsonSchema {
+    pub name: String,
+    pub schema: Value,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LlmError {
+    #[error("LLM_NOT_CONFIGURED")]
+    NotConfigured,
+    #[error("LLM_UPSTREAM_ERROR:{0}")]
+    Upstream(String),
+    #[error("LLM_BAD_RESPONSE")]
+    BadResponse,
+    // carries the reply, so it can be shown back to the model
+    #[error("LLM_INVALID_JSON")]
+    InvalidJson(String),
+    #[error("LLM_SCHEMA_MISMATCH:{0}")]
+    SchemaMismatch(String),
+}
+
+impl From<LlmError> for ApiError {
+    fn from(e: LlmError) -> Self {
+        let status = match e {
+            LlmError::NotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        ApiError::new(status, e.to_string())
+    }
+}
+
+// A chat model behind the analysis endpoints. Boxed futures keep the trait
+// object-safe so the provider can be picked at startup.
+pub trait LlmProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Model used when a request names none.
+    fn default_model(&self) -> &str;
+
+    // The assistant's reply text, with no response format requested, so servers
+    // that do not support one still answer.
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<String, LlmError>>;
+
+    // The reply parsed as JSON. With a schema the provider is asked to follow
+    // it; without one any JSON object is accepted.
+    fn chat_json<'a>(&'a self, req: &'a ChatRequest, schema: Option<&'a JsonSchema>) -> BoxFuture<'a, Result<Value, LlmError>>;
+}
+
+// LLM_PROVIDER picks `openai` (default) or `mock`. The OpenAI-compatible client
+// reads LLM_BASE_URL, LLM_MODEL and LLM_API_KEY (else OPENAI_API_KEY).
+pub fn from_env() -> Arc<dyn LlmProvider> {
+    let var = |name: &str| std::env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    if var("LLM_PROVIDER").as_deref() == Some("mock") {
+        return Arc::new(MockLlm);
+    }
+    Arc::new(OpenAiCompatible::new(
+        var("LLM_BASE_URL").unwrap_or_else(|| OPENAI_BASE_URL.into()),
+        var("LLM_API_KEY").or_else(|| var("OPENAI_API_KEY")),
+        var("LLM_MODEL").unwrap_or_else(|| DEFAULT_MODEL.into()),
+    ))
+}
+
+// Any server speaking OpenAI's chat completions API, including local ones.
+pub struct OpenAiCompatible {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiCompatible {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        let client = reqwest::Client::builder().timeout(TIMEOUT).build().unwrap_or_default();
+        Self { client, base_url: base_url.trim_end_matches('/').to_string(), api_key, model }
+    }
+
+    async fn complete(&self, req: &ChatRequest, response_format: Option<Value>) -> Result<String, LlmError> {
+        // only OpenAI itself insists on a key; local servers usually take none
+        if self.api_key.is_none() && self.base_url == OPENAI_BASE_URL {
+            return Err(LlmError::NotConfigured);
+        }
+        let mut body = json!({
+            "model": req.model.as_deref().unwrap_or(self.default_model()),
+            "messages": req.messages,
+            "max_tokens": req.max_tokens,
+            "temperature": req.temperature,
+        });
+        if let Some(f) = response_format {
+            body["response_format"] = f;
+        }
+        let mut call = self.client.post(format!("{}/chat/completions", self.base_url)).json(&body);
+        if let Some(key) = &self.api_key {
+            call = call.bearer_auth(key);
+        }
+        let response = call.send().await.map_err(|e| LlmError::Upstream(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(LlmError::Upstream(response.status().to_string()));
+        }
+        let result: Value = response.json().await.map_err(|_| LlmError::BadResponse)?;
+        result["choices"][0]["message"]["content"].as_str().map(String::from).ok_or(LlmError::BadResponse)
+    }
+}
+
+impl LlmProvider for OpenAiCompatible {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<String, LlmError>> {
+        Box::pin(self.complete(req, None))
+    }
+
+    fn chat_json<'a>(&'a self, req: &'a ChatRequest, schema: Option<&'a JsonSchema>) -> BoxFuture<'a, Result<Value, LlmError>> {
+        Box::pin(async move {
+            let format = match schema {
+                Some(s) => json!({ "type": "json_schema", "json_schema": { "name": s.name, "schema": s.schema } }),
+                None => json!({ "type": "json_object" }),
+            };
+            let text = self.complete(req, Some(format)).await?;
+            parse_json(&text)
+        })
+    }
+}
+
+// Models sometimes wrap JSON in a markdown fence despite the response format.
+pub fn parse_json(text: &str) -> Result<Value, LlmError> {
+    let t = text.trim();
+    let t = t.strip_prefix("```json").or_else(|| t.strip_prefix("```")).map_or(t, |s| s.trim_end().trim_end_matches("```"));
+    serde_json::from_str(t.trim()).map_err(|_| LlmError::InvalidJson(text.to_string()))
+}
+
+// Asks for JSON matching `schema` and checks the reply against it and `T`.
+// While attempts remain, an invalid reply goes back to the model with what is
+// wrong with it and a request to answer again.
+pub async fn structured<T: DeserializeOwned>(
+    llm: &dyn LlmProvider,
+    mut req: ChatRequest,
+    schema: &JsonSchema,
+    attempts: usize,
+) -> Result<T, LlmError> {
+    let mut problems = Vec::new();
+    for _ in 0..attempts.max(1) {
+        let reply = match llm.chat_json(&req, Some(schema)).await {
+            Ok(v) => {
+                problems = validate(&schema.schema, &v);
+                if problems.is_empty() {
+                    match serde_json::from_value::<T>(v.clone()) {
+                        Ok(t) => return Ok(t),
+                        Err(e) => problems.push(e.to_string()),
+                    }
+                }
+                v.to_string()
+            }
+            Err(LlmError::InvalidJson(text)) => {
+                problems = vec!["the reply is not valid JSON".into()];
+                text
+            }
+            Err(e) => return Err(e),
+        };
+        req.messages.push(ChatMessage::assistant(reply));
+        req.messages.push(ChatMessage::user(format!(
+            "That reply does not match the schema:\n- {}\nReply again with only a JSON object that matches the schema.",
+            problems.iter().take(MAX_PROBLEMS).cloned().collect::<Vec<_>>().join("\n- ")
+        )));
+    }
+    Err(LlmError::SchemaMismatch(problems.into_iter().next().unwrap_or_default()))
+}
+
+// Checks `value` against the part of JSON Schema the structured prompts use:
+// type, enum, minimum, required, properties, additionalProperties: false,
+// items, minItems and pattern. One message per problem, with its JSON path.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    check(schema, value, "$", &mut problems);
+    problems
+}
+
+fn check(schema: &Value, v: &Value, at: &str, problems: &mut Vec<String>) {
+    if let Some(ty) = schema["type"].as_str() {
+        let ok = match ty {
+            "object" => v.is_object(),
+            "array" => v.is_array(),
+            "string" => v.is_string(),
+            "integer" => v.is_i64() || v.is_u64(),
+            "number" => v.is_number(),
+            "boolean" => v.is_boolean(),
+            "null" => v.is_null(),
+            _ => true,
+        };
+        if !ok {
+            problems.push(format!("{at}: expected {ty}"));
+            return;
+        }
+    }
+    if let Some(allowed) = schema["enum"].as_array().filter(|a| !a.contains(v)) {
+        problems.push(format!("{at}: must be one of {}", Value::Array(allowed.clone())));
+    }
+    if let (Some(min), Some(x)) = (schema["minimum"].as_f64(), v.as_f64()) {
+        if x < min {
+            problems.push(format!("{at}: must be at least {min}"));
+        }
+    }
+    if let (Some(p), Some(s)) = (schema["pattern"].as_str(), v.as_str()) {
+        if Regex::new(p).is_ok_and(|re| !re.is_match(s)) {
+            problems.push(format!("{at}: must match {p}"));
+        }
+    }
+    if let Some(o) = v.as_object() {
+        for key in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            if !o.contains_key(key) {
+                problems.push(format!("{at}.{key}: missing"));
+            }
+        }
+        for (k, x) in o {
+            match schema["properties"].get(k) {
+                Some(s) => check(s, x, &format!("{at}.{k}"), problems),
+                None if schema["additionalProperties"] == Value::Bool(false) => problems.push(format!("{at}.{k}: not allowed")),
+                None => {}
+            }
+        }
+    }
+    if let Some(xs) = v.as_array() {
+        if schema["minItems"].as_u64().is_some_and(|n| (xs.len() as u64) < n) {
+            problems.push(format!("{at}: needs at least {} items", schema["minItems"]));
+        }
+        for (i, x) in xs.iter().enumerate() {
+            check(&schema["items"], x, &format!("{at}[{i}]"), problems);
+        }
+    }
+}
+
+// Offline stand-in: the same request always gets the same answer, and JSON
+// replies are built from the schema so they have the requested shape.
+pub struct MockLlm;
+
+impl LlmProvider for MockLlm {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn default_model(&self) -> &str {
+        "mock"
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<String, LlmError>> {
+        let prompt = req.messages.iter().rev().find(|m| m.role == "user").map(|m| m.content.as_str()).unwrap_or_default();
+        let first_line = prompt.lines().next().unwrap_or_default();
+        let reply = format!("Mock reply to {} messages ({} prompt chars): {}", req.messages.len(), prompt.len(), first_line);
+        Box::pin(async move { Ok(reply) })
+    }
+
+    fn chat_json<'a>(&'a self, req: &'a ChatRequest, schema: Option<&'a JsonSchema>) -> BoxFuture<'a, Result<Value, LlmError>> {
+        let reply = match schema {
+            Some(s) => example(&s.schema),
+            None => json!({ "mock": true, "messages": req.messages.len() }),
+        };
+        Box::pin(async move { Ok(reply) })
+    }
+}
+
+// Smallest value of the schema's shape: first enum value, minimum for numbers,
+// one item for arrays and every property for objects.
+fn example(schema: &Value) -> Value {
+    if let Some(v) = schema.get("enum").and_then(|e| e.get(0)) {
+        return v.clone();
+    }
+    let ty = match &schema["type"] {
+        Value::Array(types) => types.iter().filter_map(Value::as_str).find(|t| *t != "null").unwrap_or("null"),
+        t => t.as_str().unwrap_or("object"),
+    };
+    match ty {
+        "object" => {
+            let props = schema["properties"].as_object().cloned().unwrap_or_default();
+            Value::Object(props.iter().map(|(k, s)| (k.clone(), example(s))).collect())
+        }
+        "array" => json!([example(&schema["items"])]),
+        "string" => json!("mock"),
+        "integer" => json!(schema["minimum"].as_i64().unwrap_or(0)),
+        "number" => json!(schema["minimum"].as_f64().unwrap_or(0.0)),
+        "boolean" => json!(false),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Reply {
+        kind: String,
+        n: i64,
+    }
+
+    fn schema() -> JsonSchema {
+        JsonSchema {
+            name: "reply".into(),
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "kind": { "type": "string", "enum": ["a", "b"] },
+                    "n": { "type": "integer", "minimum": 2 },
+                },
+                "required": ["kind", "n"],
+                "additionalProperties": false,
+            }),
+        }
+    }
+
+    fn request() -> ChatRequest {
+        ChatRequest { model: None, messages: vec![ChatMessage::user("go")], max_tokens: 100, temperature: 0.0 }
+    }
+
+    // Replies from a script, in order, and keeps every request it was sent.
+    struct Scripted {
+        replies: Mutex<Vec<Result<Value, LlmError>>>,
+        seen: Mutex<Vec<Vec<ChatMessage>>>,
+    }
+
+    impl Scripted {
+        fn new(mut replies: Vec<Result<Value, LlmError>>) -> Self {
+            replies.reverse();
+            Self { replies: Mutex::new(replies), seen: Mutex::new(Vec::new()) }
+        }
+    }
+
+    impl LlmProvider for Scripted {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        fn default_model(&self) -> &str {
+            "scripted"
+        }
+
+        fn chat<'a>(&'a self, req: &'a ChatRequest) -> BoxFuture<'a, Result<String, LlmError>> {
+            let reply = self.chat_json(req, None);
+            Box::pin(async move { reply.await.map(|v| v.to_string()) })
+        }
+
+        fn chat_json<'a>(&'a self, req: &'a ChatRequest, _: Option<&'a JsonSchema>) -> BoxFuture<'a, Result<Value, LlmError>> {
+            self.seen.lock().unwrap().push(req.messages.clone());
+            let reply = self.replies.lock().unwrap().pop().expect("script ran out");
+            Box::pin(async move { reply })
+        }
+    }
+
+    #[test]
+    fn validate_lists_every_problem_with_its_path() {
+        let s = json!({
+            "type": "object",
+            "properties": {
+                "kind": { "enum": ["a", "b"] },
+                "n": { "type": "number", "minimum": 2 },
+                "id": { "type": "string", "pattern": "^[a-z]+$" },
+                "tags": { "type": "array", "minItems": 2, "items": { "type": "string" } },
+            },
+            "required": ["kind", "n"],
+            "additionalProperties": false,
+        });
+        let problems = validate(&s, &json!({ "kind": "c", "id": "A1", "tags": [1], "extra": true }));
+        assert_eq!(problems, vec![
+            "$.n: missing",
+            "$.extra: not allowed",
+            "$.id: must match ^[a-z]+$",
+            r#"$.kind: must be one of ["a","b"]"#,
+            "$.tags: needs at least 2 items",
+            "$.tags[0]: expected string",
+        ]);
+        assert!(validate(&s, &json!({ "kind": "a", "n": 2.5, "tags": ["x", "y"] })).is_empty());
+        assert_eq!(validate(&json!({ "type": "integer" }), &json!(1.5)), vec!["$: expected integer"]);
+    }
+
+    #[tokio::test]
+    async fn structured_takes_the_mock_reply() {
+        let reply: Reply = structured(&MockLlm, request(), &schema(), 1).await.unwrap();
+        assert_eq!(reply, Reply { kind: "a".into(), n: 2 });
+    }
+
+    #[tokio::test]
+    async fn structured_repairs_an_invalid_reply() {
+        let llm = Scripted::new(vec![
+            Err(LlmError::InvalidJson("sure, here it is".into())),
+            Ok(json!({ "kind": "a", "n": 1 })),
+            Ok(json!({ "kind": "b", "n": 3 })),
+        ]);
+        let reply: Reply = structured(&llm, request(), &schema(), 3).await.unwrap();
+        assert_eq!(reply, Reply { kind: "b".into(), n: 3 });
+        let seen = llm.seen.lock().unwrap();
+        assert_eq!(seen.iter().map(Vec::len).collect::<Vec<_>>(), vec![1, 3, 5]);
+        // each bad reply goes back to the model with what was wrong with it
+        assert_eq!(seen[1][1].content, "sure, here it is");
+        assert!(seen[1][2].content.contains("- the reply is not valid JSON"));
+        assert_eq!(seen[2][3].content, r#"{"kind":"a","n":1}"#);
+        assert!(seen[2][4].content.contains("- $.n: must be at least 2"));
+    }
+
+    #[tokio::test]
+    async fn structured_gives_up_after_the_last_attempt() {
+        let llm = Scripted::new(vec![Ok(json!({ "kind": "z", "n": 2 })), Ok(json!({ "kind": "a" }))]);
+        let err = structured::<Reply>(&llm, request(), &schema(), 2).await.unwrap_err();
+        assert_eq!(err.to_string(), "LLM_SCHEMA_MISMATCH:$.n: missing");
+        assert_eq!(llm.seen.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn structured_passes_upstream_errors_through() {
+        let llm = Scripted::new(vec![Err(LlmError::NotConfigured)]);
+        let err = structured::<Reply>(&llm, request(), &schema(), 3).await.unwrap_err();
+        assert!(matches!(err, LlmError::NotConfigured));
+    }
+
+    #[test]
+    fn parse_json_strips_a_markdown_fence() {
+        assert_eq!(parse_json("```json\n{\"a\": 1}\n```").unwrap(), json!({ "a": 1 }));
+        assert_eq!(parse_json(" {\"a\": 1} ").unwrap(), json!({ "a": 1 }));
+        assert!(matches!(parse_json("{\"a\":"), Err(LlmError::InvalidJson(_))));
+    }
+}
@@ -1,6 +1,27 @@
 mod routes;
 mod state;
 mod models;
+mod formats;
+mod query;
+mod index;
+mod table;
+mod realtime;
+mod error;
+mod ingest;
+mod pipeline;
+mod jobs;
+mod mapping;
+mod suggest;
+mod impute;
+mod evals;
+mod spec;
+mod report;
+mod review;
+mod versions;
+mod lifecycle;
+mod datasets;
+mod llm;
+mod analysis;
 
 use axum::serve;
 use std::net::SocketAddr;
@@ -10,6 +31,8 @@ use state::AppState;
 #[tokio::main]
 async fn main() {
     let state = AppState::default();
+    println!("LLM provider: {}", state.llm.name());
+    jobs::spawn_workers(&state);
     let app = app(state);
     let addr = SocketAddr::from(([0,0,0,0], 8787));
     println!("Minam API running on http://{}/", addr);
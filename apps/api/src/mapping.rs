@@ -0,0 +1,572 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use crate::models::{FeatureMapping, FeatureSpec, UnitConversion};
+use crate::table::{format_micros, parse_micros, Column, Table};
+
+// Everything that can be checked without the data: expression syntax, units,
+// and that a mapping has at most one source.
+pub fn check(m: &FeatureMapping) -> Result<(), String> {
+    compile(m).map(|_| ())
+}
+
+// A feature mapping ready to run against a source table
+pub struct Mapper {
+    source: Source,
+    scale: Option<f64>,
+    format: Option<String>,
+    default: Option<Value>,
+}
+
+enum Source {
+    Path(String),
+    Expr(Expr),
+}
+
+pub fn compile(m: &FeatureMapping) -> Result<Mapper, String> {
+    let source = match (&m.from, &m.expr) {
+        (Some(_), Some(_)) => return Err("set either from or expr, not both".into()),
+        (_, Some(expr)) => Source::Expr(Parser::parse(expr)?),
+        (Some(path), None) => Source::Path(path.clone()),
+        (None, None) => return Err("from or expr is required".into()),
+    };
+    let scale = m.unit.as_ref().map(unit_scale).transpose()?;
+    Ok(Mapper { source, scale, format: m.format.clone(), default: m.default.clone() })
+}
+
+impl Mapper {
+    // Builds the feature column. Also returns how many present values could not
+    // be cast to the feature's dtype (those become the default, or null).
+    pub fn column(&self, feat: &FeatureSpec, source: &Table) -> Result<(Column, usize), String> {
+        let default = self.default_for(feat)?;
+        let mut failed = 0;
+        let values: Vec<Value> = (0..source.len())
+            .map(|i| {
+                let raw = match &self.source {
+                    Source::Path(path) => lookup(source, path, i),
+                    Source::Expr(expr) => expr.eval(source, i),
+                };
+                let scaled = match self.scale {
+                    Some(k) => number(&raw).map(|x| to_number(x * k)).unwrap_or(raw),
+                    None => raw,
+                };
+                let cast = cast(&scaled, &feat.dtype, self.format.as_deref());
+                if cast.is_null() && !scaled.is_null() { failed += 1; }
+                match cast {
+                    Value::Null => default.clone(),
+                    cast => cast,
+                }
+            })
+            .collect();
+        Ok((Column::from_values(&feat.name, values.iter().map(Some)), failed))
+    }
+
+    // The default goes through the same cast as a source value; one that does not
+    // cast to the feature's dtype is a mistake in the recipe.
+    fn default_for(&self, feat: &FeatureSpec) -> Result<Value, String> {
+        let Some(d) = self.default.as_ref().filter(|d| !d.is_null()) else { return Ok(Value::Null) };
+        match cast(d, &feat.dtype, self.format.as_deref()) {
+            Value::Null => Err(format!("default for {} is not a valid {}", feat.name, feat.dtype)),
+            v => Ok(v),
+        }
+    }
+}
+
+pub fn compile_all(mappings: &BTreeMap<String, FeatureMapping>) -> Result<BTreeMap<String, Mapper>, String> {
+    mappings.iter().map(|(f, m)| Ok((f.clone(), compile(m)?))).collect()
+}
+
+// Maps `source` onto `features`: a feature with a mapper runs it, one without takes
+// the same-named column, and one with neither is all null. `each` hears, per
+// feature, how many values failed to cast and whether the feature had no source.
+pub fn apply(
+    features: &[FeatureSpec],
+    mappers: &BTreeMap<String, Mapper>,
+    source: &Table,
+    mut each: impl FnMut(usize, &FeatureSpec, usize, bool) -> Result<(), String>,
+) -> Result<Table, String> {
+    let mut columns = Vec::with_capacity(features.len());
+    for (i, feat) in features.iter().enumerate() {
+        let (col, failed, missing) = match (mappers.get(&feat.name), source.column(&feat.name)) {
+            (Some(m), _) => {
+                let (col, n) = m.column(feat, source)?;
+                (col, n, false)
+            }
+            (None, Some(col)) => (Column { name: feat.name.clone(), ..col.clone() }, 0, false),
+            (None, None) => (Column::nulls(&feat.name, source.len()), 0, true),
+        };
+        each(i, feat, failed, missing)?;
+        columns.push(col);
+    }
+    Ok(Table::from_columns(source.len(), columns))
+}
+
+// Column value by name, or by a dotted path whose first segment is a column and
+// whose remaining segments walk object keys and array indexes.
+fn lookup(source: &Table, path: &str, i: usize) -> Value {
+    let path = path.strip_prefix("$.").unwrap_or(path);
+    if source.column(path).is_some() {
+        return source.cell(path, i).to_value();
+    }
+    let mut parts = path.split('.');
+    let Some(col) = parts.next().and_then(|c| source.column(c)) else { return Value::Null };
+    let mut v = col.get(i).to_value();
+    for part in parts {
+        // JSON kept as text (e.g. a CSV cell) is parsed on the way down
+        if let Value::String(s) = &v {
+            v = serde_json::from_str(s).unwrap_or(Value::Null);
+        }
+        v = match v {
+            Value::Object(mut o) => o.remove(part).unwrap_or(Value::Null),
+            Value::Array(mut a) => match part.parse::<usize>() {
+                Ok(k) if k < a.len() => a.swap_remove(k),
+                _ => Value::Null,
+            },
+            _ => Value::Null,
+        };
+    }
+    v
+}
+
+// Factor that converts a value in `from` units into `to` units.
+fn unit_scale(u: &UnitConversion) -> Result<f64, String> {
+    let (from_dim, from) = unit(&u.from)?;
+    let (to_dim, to) = unit(&u.to)?;
+    if from_dim != to_dim {
+        return Err(format!("cannot convert {} to {}", u.from, u.to));
+    }
+    Ok(from / to)
+}
+
+// (dimension, size in the dimension's base unit)
+fn unit(name: &str) -> Result<(&'static str, f64), String> {
+    Ok(match name {
+        "ns" => ("time", 1e-9),
+        "us" => ("time", 1e-6),
+        "ms" => ("time", 1e-3),
+        "s" => ("time", 1.0),
+        "min" => ("time", 60.0),
+        "h" => ("time", 3600.0),
+        "d" => ("time", 86400.0),
+        "fraction" => ("ratio", 1.0),
+        "percent" => ("ratio", 1e-2),
+        "bps" => ("ratio", 1e-4),
+        "units" => ("count", 1.0),
+        "thousands" => ("count", 1e3),
+        "millions" => ("count", 1e6),
+        "billions" => ("count", 1e9),
+        _ => return Err(format!("unknown unit {name}")),
+    })
+}
+
+fn number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn to_number(x: f64) -> Value {
+    serde_json::Number::from_f64(x).map(Value::Number).unwrap_or(Value::Null)
+}
+
+// Converts a value to the feature dtype. Null means it could not be converted;
+// unknown dtypes pass values through unchanged.
+pub fn cast(v: &Value, dtype: &str, format: Option<&str>) -> Value {
+    if v.is_null() { return Value::Null; }
+    match dtype {
+        "number" | "float" | "double" => number(v).map(to_number).unwrap_or(Value::Null),
+        "integer" | "int" => match v {
+            Value::Number(n) if n.is_i64() => v.clone(),
+            _ => number(v)
+                .filter(|x| x.fract() == 0.0 && x.abs() < 9.0e15)
+                .map(|x| Value::from(x as i64))
+                .unwrap_or(Value::Null),
+        },
+        "bool" | "boolean" => match v {
+            Value::Bool(_) => v.clone(),
+            Value::Number(n) => match n.as_f64() {
+                Some(0.0) => Value::Bool(false),
+                Some(1.0) => Value::Bool(true),
+                _ => Value::Null,
+            },
+            Value::String(s) => match s.trim().to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Value::Bool(true),
+                "false" | "no" | "n" | "0" => Value::Bool(false),
+                _ => Value::Null,
+            },
+            _ => Value::Null,
+        },
+        "datetime" | "timestamp" => datetime(v, format).map(|m| Value::String(format_micros(m))).unwrap_or(Value::Null),
+        "string" => match v {
+            Value::String(_) => v.clone(),
+            Value::Number(_) | Value::Bool(_) => Value::String(v.to_string()),
+            _ => Value::String(serde_json::to_string(v).unwrap_or_default()),
+        },
+        _ => v.clone(),
+    }
+}
+
+// Microseconds since the epoch. Numbers are epoch seconds unless the format names
+// another epoch unit; strings use the format when given, else RFC 3339 and a few
+// common layouts. Naive times are taken as UTC. An epoch value outside the range
+// a timestamp can hold is not a datetime.
+fn datetime(v: &Value, format: Option<&str>) -> Option<i64> {
+    let epoch = match format {
+        Some("epoch_ns") => Some(1e-3),
+        Some("epoch_us") => Some(1.0),
+        Some("epoch_ms") => Some(1e3),
+        Some("epoch_s") => Some(1e6),
+        _ => None,
+    };
+    if let Some(k) = epoch.or(v.is_number().then_some(1e6)) {
+        if let Some(x) = number(v) {
+            let m = (x * k).round();
+            return (m.abs() < i64::MAX as f64)
+                .then_some(m as i64)
+                .filter(|m| DateTime::from_timestamp_micros(*m).is_some());
+        }
+    }
+    let s = v.as_str()?.trim();
+    match format {
+        Some(fmt) if epoch.is_none() => parse_with(s, fmt),
+        _ => parse_micros(s)
+            .or_else(|| parse_with(s, "%Y-%m-%d %H:%M:%S%.f"))
+            .or_else(|| parse_with(s, "%Y-%m-%dT%H:%M:%S%.f"))
+            .or_else(|| parse_with(s, "%Y-%m-%d")),
+    }
+}
+
+fn parse_with(s: &str, fmt: &str) -> Option<i64> {
+    if let Ok(t) = DateTime::parse_from_str(s, fmt) {
+        return Some(t.timestamp_micros());
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(s, fmt) {
+        return Some(t.and_utc().timestamp_micros());
+    }
+    NaiveDate::parse_from_str(s, fmt).ok().and_then(|d| d.and_hms_opt(0, 0, 0)).map(|t| t.and_utc().timestamp_micros())
+}
+
+// Derived expressions: + - * / % over numbers, parentheses, 'string' literals,
+// column names or paths (`backticks` for odd names), and the functions
+// concat(a, ...), coalesce(a, ...) and parse_date(value, format).
+enum Expr {
+    Lit(Value),
+    Col(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+#[derive(Clone, Copy)]
+enum Func {
+    Concat,
+    Coalesce,
+    ParseDate,
+}
+
+impl Expr {
+    fn eval(&self, source: &Table, i: usize) -> Value {
+        match self {
+            Expr::Lit(v) => v.clone(),
+            Expr::Col(path) => lookup(source, path, i),
+            Expr::Neg(e) => number(&e.eval(source, i)).map(|x| to_number(-x)).unwrap_or(Value::Null),
+            Expr::Bin(op, l, r) => {
+                let (Some(a), Some(b)) = (number(&l.eval(source, i)), number(&r.eval(source, i))) else {
+                    return Value::Null;
+                };
+                let x = match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' if b != 0.0 => a / b,
+                    '%' if b != 0.0 => a % b,
+                    _ => return Value::Null,
+                };
+                to_number(x)
+            }
+            Expr::Call(Func::Concat, args) => {
+                let parts: Vec<Value> = args.iter().map(|a| a.eval(source, i)).collect();
+                if parts.iter().any(Value::is_null) { return Value::Null; }
+                Value::String(parts.iter().map(|p| match p {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                }).collect())
+            }
+            Expr::Call(Func::Coalesce, args) => {
+                args.iter().map(|a| a.eval(source, i)).find(|v| !v.is_null()).unwrap_or(Value::Null)
+            }
+            Expr::Call(Func::ParseDate, args) => {
+                let v = args[0].eval(source, i);
+                let fmt = args[1].eval(source, i);
+                datetime(&v, fmt.as_str()).map(|m| Value::String(format_micros(m))).unwrap_or(Value::Null)
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    Ident(String),
+    Op(char),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse(src: &str) -> Result<Expr, String> {
+        let mut p = Parser { tokens: tokenize(src)?, pos: 0 };
+        let expr = p.sum()?;
+        match p.tokens.get(p.pos) {
+            None => Ok(expr),
+            Some(_) => Err(format!("unexpected input in expression: {src}")),
+        }
+    }
+
+    fn peek_op(&self, ops: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) if ops.contains(*c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: char) -> Result<(), String> {
+        if self.peek_op(&op.to_string()).is_none() {
+            return Err(format!("expected '{op}' in expression"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.product()?;
+        while let Some(op) = self.peek_op("+-") {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.product()?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_op("*/%") {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_op("-").is_some() {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        match token {
+            Token::Num(x) => Ok(Expr::Lit(to_number(x))),
+            Token::Str(s) => Ok(Expr::Lit(Value::String(s))),
+            Token::Op('(') => {
+                let e = self.sum()?;
+                self.expect(')')?;
+                Ok(e)
+            }
+            Token::Op(c) => Err(format!("unexpected '{c}' in expression")),
+            Token::Ident(name) if self.peek_op("(").is_some() => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                if self.peek_op(")").is_none() {
+                    args.push(self.sum()?);
+                    while self.peek_op(",").is_some() {
+                        self.pos += 1;
+                        args.push(self.sum()?);
+                    }
+                }
+                self.expect(')')?;
+                let func = match (name.as_str(), args.len()) {
+                    ("concat", n) if n > 0 => Func::Concat,
+                    ("coalesce", n) if n > 0 => Func::Coalesce,
+                    ("parse_date", 2) => Func::ParseDate,
+                    _ => return Err(format!("unknown function or wrong arguments: {name}")),
+                };
+                Ok(Expr::Call(func, args))
+            }
+            Token::Ident(name) => Ok(match name.as_str() {
+                "null" => Expr::Lit(Value::Null),
+                "true" => Expr::Lit(Value::Bool(true)),
+                "false" => Expr::Lit(Value::Bool(false)),
+                _ => Expr::Col(name),
+            }),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') { i += 1; }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Num(text.parse().map_err(|_| format!("bad number {text}"))?));
+        } else if c == '\'' || c == '"' || c == '`' {
+            let end = chars[i + 1..].iter().position(|x| *x == c).ok_or("unterminated quote in expression")?;
+            let text: String = chars[i + 1..i + 1 + end].iter().collect();
+            tokens.push(if c == '`' { Token::Ident(text) } else { Token::Str(text) });
+            i += end + 2;
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || "_.$".contains(chars[i])) { i += 1; }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "+-*/%(),".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected '{c}' in expression"));
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn feature(name: &str, dtype: &str) -> FeatureSpec {
+        FeatureSpec { name: name.into(), dtype: dtype.into(), ..Default::default() }
+    }
+
+    fn mapping(m: Value) -> FeatureMapping {
+        serde_json::from_value(m).unwrap()
+    }
+
+    // (values, failed casts) of one feature mapped over `rows`
+    fn run(m: Value, dtype: &str, rows: Value) -> (Vec<Value>, usize) {
+        let source = Table::from_rows(rows.as_array().unwrap());
+        let (col, failed) = compile(&mapping(m)).unwrap().column(&feature("f", dtype), &source).unwrap();
+        (col.iter().map(|c| c.to_value()).collect(), failed)
+    }
+
+    fn eval(expr: &str, row: Value) -> Value {
+        let Source::Expr(e) = compile(&mapping(json!({"expr": expr}))).unwrap().source else { unreachable!() };
+        e.eval(&Table::from_rows(&[row]), 0)
+    }
+
+    #[test]
+    fn expressions_follow_operator_precedence() {
+        assert_eq!(eval("1 + 2 * 3", json!({})), json!(7.0));
+        assert_eq!(eval("(1 + 2) * 3", json!({})), json!(9.0));
+        assert_eq!(eval("-a - -2", json!({"a": 5})), json!(-3.0));
+        assert_eq!(eval("10 % 4 / 2", json!({})), json!(1.0));
+        assert_eq!(eval("bid / 0", json!({"bid": 1})), json!(null));
+        assert_eq!(eval("a + b", json!({"a": 1})), json!(null));
+    }
+
+    #[test]
+    fn expressions_read_columns_paths_and_functions() {
+        let row = json!({"base": "BTC", "quote": "USD", "odd name": 3, "meta": {"tags": ["x", "y"]}, "d": "02/01/2024"});
+        assert_eq!(eval("concat(base, '-', quote)", row.clone()), json!("BTC-USD"));
+        assert_eq!(eval("`odd name` * 2", row.clone()), json!(6.0));
+        assert_eq!(eval("meta.tags.1", row.clone()), json!("y"));
+        assert_eq!(eval("coalesce(missing, null, base)", row.clone()), json!("BTC"));
+        assert_eq!(eval("parse_date(d, '%d/%m/%Y')", row.clone()), json!("2024-01-02T00:00:00Z"));
+        assert_eq!(eval("concat(base, missing)", row), json!(null));
+    }
+
+    #[test]
+    fn bad_expressions_and_recipes_are_rejected() {
+        for expr in ["1 +", "(1", "1 2", "frob(a)", "parse_date(a)", "'open", "a # b"] {
+            assert!(check(&mapping(json!({"expr": expr}))).is_err(), "{expr}");
+        }
+        assert!(check(&mapping(json!({"from": "a", "expr": "a"}))).is_err());
+        assert!(check(&mapping(json!({}))).is_err());
+        assert!(check(&mapping(json!({"from": "a", "unit": {"from": "ms", "to": "percent"}}))).is_err());
+        assert!(check(&mapping(json!({"from": "a", "unit": {"from": "ms", "to": "furlong"}}))).is_err());
+    }
+
+    #[test]
+    fn casts_convert_to_the_feature_dtype() {
+        assert_eq!(cast(&json!("1.5"), "number", None), json!(1.5));
+        assert_eq!(cast(&json!("7"), "integer", None), json!(7));
+        assert_eq!(cast(&json!(7.0), "integer", None), json!(7));
+        assert_eq!(cast(&json!(7.5), "integer", None), json!(null));
+        assert_eq!(cast(&json!("Yes"), "bool", None), json!(true));
+        assert_eq!(cast(&json!(2), "bool", None), json!(null));
+        assert_eq!(cast(&json!(3), "string", None), json!("3"));
+        assert_eq!(cast(&json!({"a": 1}), "string", None), json!("{\"a\":1}"));
+        assert_eq!(cast(&json!("x"), "json", None), json!("x"));
+    }
+
+    #[test]
+    fn datetimes_take_epochs_formats_and_common_layouts() {
+        assert_eq!(cast(&json!(1700000000), "datetime", None), json!("2023-11-14T22:13:20Z"));
+        assert_eq!(cast(&json!(1700000000123i64), "datetime", Some("epoch_ms")), json!("2023-11-14T22:13:20.123Z"));
+        assert_eq!(cast(&json!("1700000000"), "datetime", Some("epoch_s")), json!("2023-11-14T22:13:20Z"));
+        assert_eq!(cast(&json!("2024-01-02 03:04:05"), "datetime", None), json!("2024-01-02T03:04:05Z"));
+        assert_eq!(cast(&json!("2024-01-02T03:04:05+01:00"), "datetime", None), json!("2024-01-02T02:04:05Z"));
+        assert_eq!(cast(&json!("02/01/2024"), "datetime", Some("%d/%m/%Y")), json!("2024-01-02T00:00:00Z"));
+        assert_eq!(cast(&json!("soon"), "datetime", None), json!(null));
+    }
+
+    #[test]
+    fn out_of_range_epochs_do_not_cast() {
+        assert_eq!(cast(&json!(1e30), "datetime", None), json!(null));
+        assert_eq!(cast(&json!(1e17), "datetime", None), json!(null));
+        assert_eq!(run(json!({"from": "t"}), "datetime", json!([{"t": 1e30}, {"t": 0}])), (vec![json!(null), json!("1970-01-01T00:00:00Z")], 1));
+    }
+
+    #[test]
+    fn units_scale_before_the_cast() {
+        let (values, _) = run(json!({"from": "ms", "unit": {"from": "ms", "to": "s"}}), "number", json!([{"ms": 1500}]));
+        assert_eq!(values, vec![json!(1.5)]);
+        let (values, _) = run(json!({"from": "p", "unit": {"from": "percent", "to": "bps"}}), "number", json!([{"p": "2"}]));
+        assert_eq!(values, vec![json!(200.0)]);
+    }
+
+    #[test]
+    fn failed_casts_are_counted_and_take_the_default() {
+        let rows = json!([{"p": "1"}, {"p": "x"}, {"p": null}]);
+        assert_eq!(run(json!({"from": "p"}), "number", rows.clone()), (vec![json!(1.0), json!(null), json!(null)], 1));
+        assert_eq!(run(json!({"from": "p", "default": "0"}), "number", rows), (vec![json!(1.0), json!(0.0), json!(0.0)], 1));
+    }
+
+    #[test]
+    fn a_default_that_does_not_cast_is_an_error() {
+        let source = Table::from_rows(&[json!({"p": 1})]);
+        let m = compile(&mapping(json!({"from": "p", "default": "none"}))).unwrap();
+        assert_eq!(m.column(&feature("p", "number"), &source).err().as_deref(), Some("default for p is not a valid number"));
+        let m = compile(&mapping(json!({"from": "p", "default": null}))).unwrap();
+        assert!(m.column(&feature("p", "number"), &source).is_ok());
+    }
+
+    #[test]
+    fn apply_maps_renames_passes_through_and_fills_missing_features() {
+        let source = Table::from_rows(&[json!({"px": "2", "symbol": "A"})]);
+        let mappers = compile_all(&[("price".to_string(), mapping(json!({"from": "px"})))].into()).unwrap();
+        let features = [feature("price", "number"), feature("symbol", "string"), feature("volume", "number")];
+        let mut heard = Vec::new();
+        let t = apply(&features, &mappers, &source, |_, f, failed, missing| {
+            heard.push((f.name.clone(), failed, missing));
+            Ok(())
+        }).unwrap();
+        assert_eq!(t.row_dense(0), json!({"price": 2.0, "symbol": "A", "volume": null}));
+        assert_eq!(heard, vec![("price".into(), 0, false), ("symbol".into(), 0, false), ("volume".into(), 0, true)]);
+    }
+}
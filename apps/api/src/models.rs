@@ -1,5 +1,10 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use crate::index::DatasetIndexes;
+use crate::table::{ChunkedTable, Table};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Provider {
@@ -21,6 +26,10 @@ pub struct ModelProfile {
     pub version: String,
     pub description: String,
     pub features: Vec<FeatureSpec>,
+    // features that identify a row, e.g. ["symbol", "ts"]; checked as a unique
+    // constraint and used as the key of datasets ingested for this profile
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub primary_key: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -29,21 +38,177 @@ pub struct ModelProfileCreate {
     pub version: String,
     pub description: String,
     pub features: Vec<FeatureSpec>,
+    #[serde(default)]
+    pub primary_key: Vec<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+// A feature in a model profile. Everything past `dtype` is an optional constraint,
+// checked by the `constraints` eval and published in the generated API spec.
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct FeatureSpec {
     pub name: String,
     pub dtype: String, // "string" | "number" | "datetime" | ...
+    // absent means nulls are allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nullable: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<serde_json::Value>>,
+    // regex the value's text must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unique: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monotonic: Option<Monotonic>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+// Order of non-null values along the dataset's row order
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Monotonic {
+    Increasing,
+    StrictlyIncreasing,
+    Decreasing,
+    StrictlyDecreasing,
+}
+
+#[derive(Clone, Serialize)]
 pub struct Dataset {
     pub id: Uuid,
     pub provider_id: Uuid,
     pub name: String,
     pub description: String,
+    // typed columns of the latest version; serialized back out as row objects
+    pub rows: ChunkedTable,
+    // columns that identify a row; when set, appends upsert by it
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub primary_key: Vec<String>,
+    // primary key value → row of `rows`, kept in step by upserts
+    #[serde(skip)]
+    pub key_index: HashMap<String, usize>,
+    pub version: u32,
+    // oldest first; versions[n - 1] is version n
+    pub versions: Vec<DatasetVersion>,
+}
+
+impl Dataset {
+    pub fn new(provider_id: Uuid, name: String, description: String, rows: &[serde_json::Value]) -> Self {
+        let table = ChunkedTable::new(Table::from_rows(rows));
+        let diff = DiffSummary { added: table.len(), ..Default::default() };
+        Self {
+            id: Uuid::new_v4(),
+            provider_id,
+            name,
+            description,
+            primary_key: Vec::new(),
+            key_index: HashMap::new(),
+            version: 1,
+            versions: vec![DatasetVersion::new(1, "create", table.clone(), diff)],
+            rows: table,
+        }
+    }
+
+    pub fn at(&self, version: u32) -> Option<&DatasetVersion> {
+        self.versions.get((version as usize).checked_sub(1)?)
+    }
+}
+
+// Immutable state of a dataset after one change. Versions share row chunks and a
+// change copies only the chunks it touches, so a version's rows never move.
+#[derive(Clone, Serialize)]
+pub struct DatasetVersion {
+    pub version: u32,
+    // create | append | replace | upsert
+    pub operation: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub row_count: usize,
+    // against the previous version
+    pub diff: DiffSummary,
+    // how the incoming rows landed; absent for a replace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ingested: Option<IngestCounts>,
+    #[serde(skip)]
+    pub rows: ChunkedTable,
+}
+
+impl DatasetVersion {
+    pub fn new(version: u32, operation: &str, rows: ChunkedTable, diff: DiffSummary) -> Self {
+        Self { version, operation: operation.into(), created_at: chrono::Utc::now(), row_count: rows.len(), diff, ingested: None, rows }
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+// Incoming rows by outcome. Without a key every row is inserted.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct IngestCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeMode {
+    Append,
+    Replace,
+    Upsert,
+}
+
+#[derive(Deserialize)]
+pub struct DatasetChange {
+    pub mode: ChangeMode,
     pub rows: Vec<serde_json::Value>,
+    // columns that identify a row, e.g. ["symbol", "ts"]; defaults to the
+    // dataset's primary key. Required for upsert, and lets a replace report
+    // changed rows instead of removed + added
+    #[serde(default)]
+    pub key: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    // defaults to the version before `to`
+    pub from: Option<u32>,
+    // defaults to the latest version
+    pub to: Option<u32>,
+    // comma-separated key columns; without one rows match by content and
+    // nothing counts as changed
+    pub key: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RowChange {
+    pub key: serde_json::Value,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Serialize)]
+pub struct DatasetDiff {
+    pub dataset_id: Uuid,
+    pub from: u32,
+    pub to: u32,
+    pub key: Vec<String>,
+    pub summary: DiffSummary,
+    // first few rows of each kind
+    pub added: Vec<serde_json::Value>,
+    pub removed: Vec<serde_json::Value>,
+    pub changed: Vec<RowChange>,
 }
 
 #[derive(Deserialize)]
@@ -52,6 +217,25 @@ pub struct DatasetCreate {
     pub name: String,
     pub description: String,
     pub rows: Vec<serde_json::Value>,
+    // rows repeating a key collapse into the last one
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DatasetAppend {
+    pub rows: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+pub struct DatasetAppendResult {
+    pub dataset_id: Uuid,
+    // rows written: inserted plus updated, never the unchanged ones
+    pub appended: usize,
+    pub total_rows: usize,
+    pub version: u32,
+    #[serde(flatten)]
+    pub counts: IngestCounts,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -60,21 +244,725 @@ pub struct FeatureCoverage {
     pub coverage: f64,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OffendingRow {
+    pub row: usize,
+    pub value: serde_json::Value,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FeatureConformance {
+    pub name: String,
+    pub dtype: String,
+    // conforming / non-null values; 1.0 when there is nothing to check
+    pub rate: f64,
+    pub checked: usize,
+    pub nonconforming: usize,
+    pub samples: Vec<OffendingRow>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConstraintReport {
+    pub feature: String,
+    // nullable | min | max | allowed_values | pattern | unique | monotonic
+    pub constraint: String,
+    pub checked: usize,
+    pub violations: usize,
+    pub samples: Vec<OffendingRow>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CategoryShift {
+    pub value: String,
+    // share of non-null values
+    pub reference: f64,
+    pub current: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FeatureDrift {
+    pub feature: String,
+    // numeric | categorical | other
+    pub kind: String,
+    pub psi: Option<f64>,
+    pub ks: Option<f64>,
+    pub category_shift: Option<f64>,
+    // largest per-category changes, for categorical features
+    pub top_shifts: Vec<CategoryShift>,
+    pub null_rate_reference: f64,
+    pub null_rate_current: f64,
+    pub drifted: bool,
+    // which thresholds were exceeded
+    pub exceeded: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutlierSample {
+    pub row: usize,
+    pub value: serde_json::Value,
+    pub group: Option<String>,
+    pub score: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutlierReport {
+    pub feature: String,
+    pub method: String,
+    pub threshold: f64,
+    pub checked: usize,
+    pub count: usize,
+    pub rate: f64,
+    pub samples: Vec<OutlierSample>,
+    pub quarantined: bool,
+}
+
+// One problem found by the time-series check. `rows` are dataset row ids: the
+// earlier and later row of a pair, or the latest row for staleness.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimeseriesFinding {
+    // duplicate_key | out_of_order | gap | stale
+    pub kind: String,
+    pub entity: Option<String>,
+    pub rows: Vec<usize>,
+    pub from: String,
+    pub to: String,
+    // gap only: intervals missing between from and to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missing: Option<usize>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimeseriesReport {
+    pub ts: String,
+    pub entity: Option<String>,
+    pub cadence_secs: Option<i64>,
+    pub max_staleness_secs: Option<i64>,
+    pub entities: usize,
+    pub rows: usize,
+    // rows whose timestamp is null or unparseable
+    pub missing_ts: usize,
+    pub duplicates: usize,
+    pub out_of_order: usize,
+    pub gaps: usize,
+    pub missing_intervals: usize,
+    pub gap_rate: f64,
+    pub latest: Option<String>,
+    pub staleness_secs: Option<i64>,
+    pub stale_entities: usize,
+    // first findings of each kind; the counts above are complete
+    pub findings: Vec<TimeseriesFinding>,
+    pub pass: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImputationReport {
+    pub feature: String,
+    pub strategy: String,
+    // nulls replaced with a value
+    pub filled: usize,
+    // rows removed because this feature was still null
+    pub dropped: usize,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ApiProposal {
+    #[serde(default)]
+    pub id: Uuid,
+    #[serde(default)]
+    pub status: ProposalStatus,
+    #[serde(default)]
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub history: Vec<ReviewEvent>,
     pub dataset_id: Uuid,
     pub model_profile_id: Uuid,
+    // the dataset version the evals ran on, and the one that gets published
+    #[serde(default)]
+    pub dataset_version: u32,
     pub sample: Vec<serde_json::Value>,
     pub coverage: Vec<FeatureCoverage>,
+    #[serde(default)]
+    pub conformance: Vec<FeatureConformance>,
+    #[serde(default)]
+    pub constraints: Vec<ConstraintReport>,
+    #[serde(default)]
+    pub drift: Vec<FeatureDrift>,
+    #[serde(default)]
+    pub outliers: Vec<OutlierReport>,
+    #[serde(default)]
+    pub timeseries: Vec<TimeseriesReport>,
+    #[serde(default)]
+    pub imputation: Vec<ImputationReport>,
+    // dataset row ids hidden from the published API
+    #[serde(default)]
+    pub quarantined_rows: Vec<u32>,
+    // dataset row ids the impute step dropped; also left out of the published API
+    #[serde(default)]
+    pub dropped_rows: Vec<u32>,
+    // profile of the rows that would be published
+    #[serde(default)]
+    pub stats: Vec<FeatureStats>,
+    // one entry per eval step, in recipe order
+    #[serde(default)]
+    pub checks: Vec<CheckResult>,
+    #[serde(default)]
+    pub findings: Vec<Finding>,
+    // failed checks a reviewer accepted anyway
+    #[serde(default)]
+    pub waivers: Vec<Waiver>,
     pub pass: bool,
     pub human_note_required: bool,
+    // set when the pipeline publishes the proposal
+    #[serde(skip)]
+    pub snapshot: Snapshot,
+}
+
+// The rows an API version serves: the pipeline's mapped table after imputation and
+// quarantine, indexed like a dataset. `mappings` are the ones the map step ran, so
+// live rows can be mapped the same way.
+#[derive(Clone, Default)]
+pub struct Snapshot {
+    pub rows: Arc<Table>,
+    pub indexes: Arc<DatasetIndexes>,
+    pub mappings: BTreeMap<String, FeatureMapping>,
+}
+
+impl Snapshot {
+    pub fn new(rows: Table, mappings: BTreeMap<String, FeatureMapping>) -> Self {
+        let indexes = Arc::new(DatasetIndexes::build(&rows));
+        Self { rows: Arc::new(rows), indexes, mappings }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Waiver {
+    pub id: Uuid,
+    // the waived entry in `checks`
+    pub step: usize,
+    pub check: String,
+    pub reviewer: String,
+    pub justification: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Waiver {
+    pub fn active(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        now < self.expires_at
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WaiverCreate {
+    pub reviewer: String,
+    pub step: usize,
+    pub justification: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+// pending → approved | rejected | changes_requested; changes_requested → pending on
+// resubmit; approved → published when an API is created from it
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    #[default]
+    Pending,
+    Approved,
+    Rejected,
+    ChangesRequested,
+    Published,
+}
+
+impl ProposalStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProposalStatus::Pending => "pending",
+            ProposalStatus::Approved => "approved",
+            ProposalStatus::Rejected => "rejected",
+            ProposalStatus::ChangesRequested => "changes_requested",
+            ProposalStatus::Published => "published",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewAction {
+    Approve,
+    Reject,
+    RequestChanges,
+    // back to pending after changes were requested
+    Resubmit,
+    // leaves the status alone
+    Comment,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    pub reviewer: String,
+    pub action: ReviewAction,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+// One entry in a proposal's history; comments are events that keep the status.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReviewEvent {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub actor: String,
+    // created | approved | rejected | changes_requested | resubmitted | commented | waived | published
+    pub action: String,
+    pub from: Option<ProposalStatus>,
+    pub to: ProposalStatus,
+    pub comment: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ProposalListQuery {
+    pub status: Option<ProposalStatus>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ValueCount {
+    pub value: serde_json::Value,
+    pub count: usize,
+}
+
+// Equal-width bin over [start, end); the last bin includes its end.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistogramBin {
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FeatureStats {
+    pub feature: String,
+    pub dtype: String,
+    pub count: usize,
+    pub null_count: usize,
+    pub distinct: usize,
+    // numbers and datetimes only
+    pub min: Option<serde_json::Value>,
+    pub max: Option<serde_json::Value>,
+    pub mean: Option<f64>,
+    pub top_values: Vec<ValueCount>,
+    pub histogram: Vec<HistogramBin>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    // fails the check it came from
+    Error,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub check: String,
+    pub feature: Option<String>,
+    pub message: String,
+    // dataset row ids, when the finding points at rows
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rows: Vec<usize>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    // position in the recipe
+    pub step: usize,
+    pub check: String,
+    // the limits the check was judged against, after defaults
+    pub thresholds: serde_json::Value,
+    pub passed: bool,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+// Pipeline recipe, accepted as JSON or YAML. Leaving out `steps` runs the
+// default recipe: map → eval coverage → eval constraints → hitl → publish.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PipelineDef {
+    // required unless the first step is `ingest`
+    #[serde(default)]
+    pub dataset_id: Option<Uuid>,
+    pub model_profile_id: Uuid,
+    // threshold for coverage checks that do not set their own `min`
+    #[serde(default)]
+    pub min_coverage: Option<f64>,
+    // threshold for conformance checks that do not set their own `min`
+    #[serde(default)]
+    pub min_conformance: Option<f64>,
+    // who is proposing; may not approve the resulting proposal
+    #[serde(default)]
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PipelineStep {
+    // load an uploaded CSV/JSON file into a new dataset
+    Ingest {
+        file_id: Uuid,
+        provider_id: Uuid,
+        #[serde(default)]
+        name: Option<String>,
+        // existing dataset to add the file to as a new version
+        #[serde(default)]
+        into: Option<Uuid>,
+        // key for a new dataset; defaults to the model profile's primary key
+        #[serde(default)]
+        primary_key: Vec<String>,
+    },
+    // columns -> features: by name, or per feature through `mappings`
+    Map {
+        #[serde(default)]
+        mappings: BTreeMap<String, FeatureMapping>,
+        // fill features that have no mapping and no same-named column from the
+        // suggested matches at or above min_confidence
+        #[serde(default)]
+        auto: bool,
+        #[serde(default)]
+        min_confidence: Option<f64>,
+    },
+    // fill nulls in mapped features, per feature
+    Impute {
+        #[serde(default)]
+        strategies: BTreeMap<String, Imputation>,
+        // applied to features without their own strategy
+        #[serde(default)]
+        default: Option<Imputation>,
+    },
+    Eval(EvalCheck),
+    Hitl {
+        #[serde(default = "default_true")]
+        required: bool,
+    },
+    // store the proposal so it can be published as an API
+    Publish,
+}
+
+fn default_true() -> bool { true }
+
+impl PipelineStep {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PipelineStep::Ingest { .. } => "ingest",
+            PipelineStep::Map { .. } => "map",
+            PipelineStep::Impute { .. } => "impute",
+            PipelineStep::Eval(_) => "eval",
+            PipelineStep::Hitl { .. } => "hitl",
+            PipelineStep::Publish => "publish",
+        }
+    }
+}
+
+// How one feature is filled from the source. `from` names a column or a dotted
+// path into a JSON column (`quote.bid`, `$.levels.0.px`); `expr` derives the value
+// instead. The result is cast to the feature's dtype, and `default` fills whatever
+// is still null.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FeatureMapping {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub expr: Option<String>,
+    // datetime input format: chrono strftime, or epoch_s / epoch_ms / epoch_us / epoch_ns
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub unit: Option<UnitConversion>,
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+}
+
+// e.g. { from: ms, to: s } or { from: percent, to: fraction }
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UnitConversion {
+    pub from: String,
+    pub to: String,
+}
+
+// How nulls in one feature are filled. Fills along `sort_by` (a mapped feature,
+// row order when absent) run separately within each `partition_by` value, e.g.
+// forward-fill prices by ts per symbol.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Imputation {
+    Drop,
+    Constant { value: serde_json::Value },
+    Ffill {
+        #[serde(default)]
+        sort_by: Option<String>,
+        #[serde(default)]
+        partition_by: Option<String>,
+    },
+    Bfill {
+        #[serde(default)]
+        sort_by: Option<String>,
+        #[serde(default)]
+        partition_by: Option<String>,
+    },
+    Mean,
+    Median,
+    Mode,
+    // linear in the sort key (time); leading and trailing nulls stay null
+    Interpolate {
+        #[serde(default)]
+        sort_by: Option<String>,
+        #[serde(default)]
+        partition_by: Option<String>,
+    },
+}
+
+impl Imputation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Imputation::Drop => "drop",
+            Imputation::Constant { .. } => "constant",
+            Imputation::Ffill { .. } => "ffill",
+            Imputation::Bfill { .. } => "bfill",
+            Imputation::Mean => "mean",
+            Imputation::Median => "median",
+            Imputation::Mode => "mode",
+            Imputation::Interpolate { .. } => "interpolate",
+        }
+    }
 }
 
 #[derive(Deserialize)]
-pub struct PipelineRunRequest {
+pub struct MappingSuggestRequest {
     pub dataset_id: Uuid,
     pub model_profile_id: Uuid,
-    pub min_coverage: f64,
+    #[serde(default)]
+    pub min_confidence: Option<f64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ColumnCandidate {
+    pub column: String,
+    // 0.0..=1.0
+    pub confidence: f64,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FeatureMatch {
+    pub feature: String,
+    // best column still free after stronger matches were assigned
+    pub matched: Option<ColumnCandidate>,
+    pub alternatives: Vec<ColumnCandidate>,
+}
+
+// Proposed column → feature matches. `mappings` drops straight into a map step.
+#[derive(Clone, Serialize)]
+pub struct MappingSuggestion {
+    pub dataset_id: Uuid,
+    pub model_profile_id: Uuid,
+    pub min_confidence: f64,
+    pub matches: Vec<FeatureMatch>,
+    pub mappings: BTreeMap<String, FeatureMapping>,
+    pub unmatched_features: Vec<String>,
+    pub unused_columns: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum EvalCheck {
+    // share of non-null values per feature
+    Coverage {
+        #[serde(default)]
+        min: Option<f64>,
+    },
+    // share of non-null values that match the feature's declared dtype
+    Conformance {
+        #[serde(default)]
+        min: Option<f64>,
+    },
+    // FeatureSpec constraints; `min` is the share of checked values that must
+    // satisfy each one (default 1.0)
+    Constraints {
+        #[serde(default)]
+        min: Option<f64>,
+    },
+    // distribution shift against a reference dataset, per feature
+    Drift {
+        reference: Uuid,
+        // a version of the reference dataset; its latest when absent
+        #[serde(default)]
+        reference_version: Option<u32>,
+        #[serde(default)]
+        thresholds: DriftThresholds,
+    },
+    // unusual values in numeric features, judged within each `group_by` value
+    Outliers {
+        #[serde(default)]
+        method: OutlierMethod,
+        // defaults to every numeric feature
+        #[serde(default)]
+        features: Option<Vec<String>>,
+        #[serde(default)]
+        group_by: Option<String>,
+        // rolling only: order within a group (row order when absent)
+        #[serde(default)]
+        sort_by: Option<String>,
+        // rolling only: trailing values compared against
+        #[serde(default)]
+        window: Option<usize>,
+        // z / robust z cutoff (default 3), or the IQR multiplier (default 1.5)
+        #[serde(default)]
+        threshold: Option<f64>,
+        // share of checked values allowed to be outliers (default 0)
+        #[serde(default)]
+        max_rate: Option<f64>,
+        // hide flagged rows from the published API instead of failing on them
+        #[serde(default)]
+        quarantine: bool,
+    },
+    // duplicate keys, ordering, gaps and freshness of a time-indexed dataset
+    Timeseries {
+        // datetime feature, "ts" when absent
+        #[serde(default)]
+        ts: Option<String>,
+        // series are checked per value of this column, e.g. symbol
+        #[serde(default)]
+        entity: Option<String>,
+        // expected spacing such as 1m, 5m, 1h or 1d; no gap check without it
+        #[serde(default)]
+        cadence: Option<String>,
+        // how old the latest timestamp may be, same format as cadence
+        #[serde(default)]
+        max_staleness: Option<String>,
+        // share of expected intervals allowed to be missing (default 0)
+        #[serde(default)]
+        max_gap_rate: Option<f64>,
+        // rows out of timestamp order are reported but do not fail the check
+        #[serde(default)]
+        allow_unordered: bool,
+    },
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlierMethod {
+    #[default]
+    Zscore,
+    Iqr,
+    // robust z against the median and MAD of the preceding window
+    Rolling,
+}
+
+impl OutlierMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutlierMethod::Zscore => "zscore",
+            OutlierMethod::Iqr => "iqr",
+            OutlierMethod::Rolling => "rolling",
+        }
+    }
+}
+
+// Upper bounds; a feature over any of them counts as drifted.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DriftThresholds {
+    // population stability index over reference deciles
+    #[serde(default = "default_psi")]
+    pub psi: f64,
+    // Kolmogorov–Smirnov statistic
+    #[serde(default = "default_ks")]
+    pub ks: f64,
+    // total variation distance between category frequencies
+    #[serde(default = "default_category_shift")]
+    pub category_shift: f64,
+    // absolute change in the share of nulls
+    #[serde(default = "default_null_rate_change")]
+    pub null_rate_change: f64,
+}
+
+fn default_psi() -> f64 { 0.2 }
+fn default_ks() -> f64 { 0.2 }
+fn default_category_shift() -> f64 { 0.2 }
+fn default_null_rate_change() -> f64 { 0.1 }
+
+impl Default for DriftThresholds {
+    fn default() -> Self {
+        Self {
+            psi: default_psi(),
+            ks: default_ks(),
+            category_shift: default_category_shift(),
+            null_rate_change: default_null_rate_change(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    Skipped,
+    Cancelled,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StepReport {
+    pub step: String,
+    pub status: StepStatus,
+    // 0.0..=1.0 within the step
+    pub progress: f64,
+    pub detail: Option<String>,
+}
+
+impl StepReport {
+    pub fn pending(step: &PipelineStep) -> Self {
+        Self { step: step.kind().into(), status: StepStatus::Pending, progress: 0.0, detail: None }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+// A pipeline run executing in the background worker pool
+#[derive(Clone, Serialize)]
+pub struct PipelineJob {
+    pub id: Uuid,
+    pub status: JobStatus,
+    // 0.0..=1.0 across all steps
+    pub progress: f64,
+    pub steps: Vec<StepReport>,
+    pub proposal_id: Option<Uuid>,
+    pub result: Option<ApiProposal>,
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub definition: PipelineDef,
+    // validated steps to execute
+    #[serde(skip)]
+    pub plan: Vec<PipelineStep>,
+    #[serde(skip)]
+    pub cancel: Arc<AtomicBool>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -85,41 +973,144 @@ pub struct PipelineRunResult {
 }
 
 impl PipelineRunResult {
-    pub fn ok(id: Uuid, prop: ApiProposal) -> Self {
-        Self { proposal_id: Some(id), result: Some(prop), error: None }
+    pub fn ok(id: Option<Uuid>, prop: ApiProposal) -> Self {
+        Self { proposal_id: id, result: Some(prop), error: None }
     }
     pub fn error(e: &str) -> Self {
         Self { proposal_id: None, result: None, error: Some(e.to_string()) }
     }
 }
 
+// draft → live | retired; live → paused | deprecated; paused → live | deprecated |
+// retired; deprecated → live | retired. Retired is final.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiStatus {
+    Draft,
+    Live,
+    Paused,
+    Deprecated,
+    Retired,
+}
+
+impl ApiStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            ApiStatus::Draft => "draft",
+            ApiStatus::Live => "live",
+            ApiStatus::Paused => "paused",
+            ApiStatus::Deprecated => "deprecated",
+            ApiStatus::Retired => "retired",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LifecycleEvent {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub from: Option<ApiStatus>,
+    pub to: ApiStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiStatusChange {
+    // must be the product's provider
+    pub provider_id: Uuid,
+    pub status: ApiStatus,
+    // required when deprecating
+    #[serde(default)]
+    pub sunset_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+// One published release of an API product, pinned to a dataset version and a
+// model profile. It serves the snapshot its proposal's pipeline made of that
+// version.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiVersion {
+    pub version: String,
+    pub proposal_id: Uuid,
+    pub dataset_id: Uuid,
+    pub dataset_version: u32,
+    pub rows: usize,
+    pub model_profile_id: Uuid,
+    pub model_profile_version: String,
+    pub published_by: String,
+    pub published_at: chrono::DateTime<chrono::Utc>,
+    pub human_approval_note: String,
+    // deprecated versions stop answering queries
+    #[serde(default)]
+    pub deprecated_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quarantined_rows: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped_rows: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub waivers: Vec<Waiver>,
+    #[serde(skip)]
+    pub snapshot: Snapshot,
+}
+
+// The fields from `proposal_id` through `waivers` mirror the latest version.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ApiProduct {
     pub id: Uuid,
     pub name: String,
     pub pricing: String, // e.g. "paygo:$0.002/call"
     pub provider_id: Uuid,
+    #[serde(default)]
+    pub proposal_id: Uuid,
     pub dataset_id: Uuid,
     pub model_profile_id: Uuid,
     pub version: String,
-    pub status: String,
+    pub status: ApiStatus,
+    // set while deprecated; queries stop once it passes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunset_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub lifecycle: Vec<LifecycleEvent>,
     pub human_approval_note: String,
+    // tier given to the consumer keys minted for this product
+    #[serde(default)]
+    pub key_tier: Tier,
+    // dataset row ids left out of query results
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quarantined_rows: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped_rows: Vec<u32>,
+    // failed checks waived on the proposal this was published from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub waivers: Vec<Waiver>,
+    // oldest first
+    #[serde(default)]
+    pub versions: Vec<ApiVersion>,
 }
 
-impl ApiProduct {
-    pub fn error(msg: &str) -> Self {
-        Self {
-            id: Uuid::nil(),
-            name: "error".into(),
-            pricing: "".into(),
-            provider_id: Uuid::nil(),
-            dataset_id: Uuid::nil(),
-            model_profile_id: Uuid::nil(),
-            version: "".into(),
-            status: format!("error:{msg}"),
-            human_approval_note: "".into(),
-        }
-    }
+// A further version of an existing product, from another approved proposal
+#[derive(Deserialize)]
+pub struct ApiVersionCreate {
+    pub proposal_id: Uuid,
+    // must be the product's provider
+    pub provider_id: Uuid,
+    #[serde(default)]
+    pub human_approval_note: String,
+    #[serde(default)]
+    pub published_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiVersionDeprecate {
+    // must be the product's provider
+    pub provider_id: Uuid,
+}
+
+// `version` is v1, v2, ... or `latest`; absent means latest
+#[derive(Deserialize)]
+pub struct VersionParam {
+    pub version: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -128,7 +1119,65 @@ pub struct ApiCreate {
     pub provider_id: Uuid,
     pub name: String,
     pub pricing: String,
+    // defaults to the approving reviewer's comment
+    #[serde(default)]
     pub human_approval_note: String,
+    // recorded in the proposal history; the provider id when absent
+    #[serde(default)]
+    pub published_by: Option<String>,
+    // draft or live (the default)
+    #[serde(default)]
+    pub status: Option<ApiStatus>,
+    // tier of the consumer keys the provider mints; free when absent
+    #[serde(default)]
+    pub key_tier: Tier,
+}
+
+// Consumer access tiers, matching the Free / Premium / Enterprise pricing tiers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    #[default]
+    Free,
+    Premium,
+    Enterprise,
+}
+
+impl Tier {
+    // concurrent real-time subscriptions per key
+    pub fn max_subscriptions(&self) -> usize {
+        match self {
+            Tier::Free => 1,
+            Tier::Premium => 5,
+            Tier::Enterprise => 50,
+        }
+    }
+
+    // rows pushed per second per subscription; None = unthrottled
+    pub fn rows_per_sec(&self) -> Option<usize> {
+        match self {
+            Tier::Free => Some(10),
+            Tier::Premium => Some(1_000),
+            Tier::Enterprise => None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConsumerKey {
+    pub key: String,
+    pub api_id: Uuid,
+    pub name: String,
+    pub tier: Tier,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// Keys are minted by the product's provider and get the product's key tier.
+#[derive(Deserialize)]
+pub struct ConsumerKeyCreate {
+    pub api_id: Uuid,
+    pub provider_id: Uuid,
+    pub name: String,
 }
 
 // New models for file uploads and OpenAI integration
@@ -154,6 +1203,53 @@ pub struct DirectoryAnalysis {
     pub model_reasoning: Option<String>,
 }
 
+// The part of a DirectoryAnalysis the LLM writes, checked against
+// analysis::reply_schema before use.
+#[derive(Serialize, Deserialize)]
+pub struct AnalysisReply {
+    pub data_patterns: Vec<String>,
+    pub suggested_api_structure: SuggestedApiStructure,
+    pub best_model: ModelInfo,
+    pub model_reasoning: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestedApiStructure {
+    pub endpoints: Vec<SuggestedEndpoint>,
+    pub authentication: SuggestedAuth,
+    pub rate_limits: SuggestedRateLimits,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestedEndpoint {
+    pub path: String,
+    pub method: String,
+    pub description: String,
+    pub parameters: Vec<SuggestedParameter>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestedParameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub required: bool,
+    pub description: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestedAuth {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub required: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SuggestedRateLimits {
+    pub requests_per_minute: u32,
+    pub requests_per_hour: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ModelInfo {
     pub id: String,
@@ -164,10 +1260,32 @@ pub struct ModelInfo {
     pub capabilities: Vec<String>,
 }
 
+// What a file holds, profiled locally; the LLM analysis is built on it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileProfile {
+    // csv | json
+    pub format: String,
+    pub rows: usize,
+    pub columns: Vec<ColumnProfile>,
+    // spread across the file, not just its head
+    pub sample_rows: Vec<serde_json::Value>,
+    // whole-file patterns: time series, candidate keys
+    pub patterns: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ColumnProfile {
+    #[serde(flatten)]
+    pub stats: FeatureStats,
+    pub patterns: Vec<String>,
+}
+
 #[derive(Deserialize)]
 pub struct OpenAIAnalysisRequest {
     pub file_id: Uuid,
     pub model: Option<String>,
+    // prompt size limit, in estimated tokens
+    pub max_prompt_tokens: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize)]
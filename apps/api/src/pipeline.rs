@@ -0,0 +1,575 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use axum::http::{header, HeaderMap};
+use serde_json::json;
+use uuid::Uuid;
+use crate::datasets;
+use crate::evals;
+use crate::impute;
+use crate::ingest;
+use crate::mapping;
+use crate::realtime;
+use crate::report;
+use crate::review;
+use crate::suggest;
+use crate::jobs::JobCtl;
+use crate::models::*;
+use crate::state::Store;
+use crate::table::Table;
+
+// Recipes are JSON unless the request says YAML.
+pub fn parse_definition(headers: &HeaderMap, body: &[u8]) -> Result<PipelineDef, String> {
+    let is_yaml = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.contains("yaml"));
+    if is_yaml {
+        serde_yaml::from_slice(body).map_err(|e| format!("INVALID_PIPELINE:{e}"))
+    } else {
+        serde_json::from_slice(body).map_err(|e| format!("INVALID_PIPELINE:{e}"))
+    }
+}
+
+fn default_steps() -> Vec<PipelineStep> {
+    vec![
+        PipelineStep::Map { mappings: Default::default(), auto: false, min_confidence: None },
+        PipelineStep::Eval(EvalCheck::Coverage { min: None }),
+        PipelineStep::Eval(EvalCheck::Constraints { min: None }),
+        PipelineStep::Hitl { required: true },
+        PipelineStep::Publish,
+    ]
+}
+
+fn invalid(reason: &str) -> String {
+    format!("INVALID_PIPELINE:{reason}")
+}
+
+// Checks step order and parameters before anything runs, and returns the steps to execute.
+pub fn validate(def: &PipelineDef) -> Result<Vec<PipelineStep>, String> {
+    let steps = if def.steps.is_empty() { default_steps() } else { def.steps.clone() };
+    let count = |kind: &str| steps.iter().filter(|s| s.kind() == kind).count();
+
+    let ingests = count("ingest");
+    if ingests > 1 || (ingests == 1 && steps[0].kind() != "ingest") {
+        return Err(invalid("ingest must be the first step and appear once"));
+    }
+    match (ingests, def.dataset_id) {
+        (0, None) => return Err(invalid("dataset_id is required without an ingest step")),
+        (1, Some(_)) => return Err(invalid("dataset_id conflicts with the ingest step")),
+        _ => {}
+    }
+    if let Some(PipelineStep::Ingest { into: Some(_), name, primary_key, .. }) = steps.first() {
+        if name.is_some() || !primary_key.is_empty() {
+            return Err(invalid("name and primary_key only apply when ingesting into a new dataset"));
+        }
+    }
+    if count("map") != 1 {
+        return Err(invalid("exactly one map step is required"));
+    }
+    let map_at = steps.iter().position(|s| s.kind() == "map").unwrap_or(0);
+    if steps.iter().take(map_at).any(|s| s.kind() == "eval") {
+        return Err(invalid("eval steps must come after map"));
+    }
+    if count("impute") > 1 || steps.iter().take(map_at).any(|s| s.kind() == "impute") {
+        return Err(invalid("impute must come after map and appear at most once"));
+    }
+    if count("hitl") > 1 {
+        return Err(invalid("at most one hitl step is allowed"));
+    }
+    if count("publish") > 1 || steps.iter().rev().skip(1).any(|s| s.kind() == "publish") {
+        return Err(invalid("publish must be the last step and appear once"));
+    }
+    // reviewers are checked against the creator, so a proposal needs one
+    if count("publish") == 1 && def.created_by.as_deref().is_none_or(|c| c.trim().is_empty()) {
+        return Err(invalid("created_by is required when the pipeline publishes a proposal"));
+    }
+    for step in steps.iter() {
+        match step {
+            PipelineStep::Map { mappings, min_confidence, .. } => {
+                if min_confidence.is_some_and(|m| !(0.0..=1.0).contains(&m)) {
+                    return Err(invalid("min_confidence must be within 0..=1"));
+                }
+                for (feature, m) in mappings {
+                    mapping::check(m).map_err(|e| invalid(&format!("mapping for {feature}: {e}")))?;
+                }
+            }
+            PipelineStep::Eval(EvalCheck::Coverage { min }) => threshold("coverage", min.or(def.min_coverage))?,
+            PipelineStep::Eval(EvalCheck::Conformance { min }) => threshold("conformance", min.or(def.min_conformance))?,
+            PipelineStep::Eval(EvalCheck::Constraints { min }) => threshold("constraints", Some(min.unwrap_or(1.0)))?,
+            PipelineStep::Eval(EvalCheck::Drift { thresholds: t, .. })
+                if [t.psi, t.ks, t.category_shift, t.null_rate_change].iter().any(|x| !x.is_finite() || *x < 0.0) =>
+            {
+                return Err(invalid("drift thresholds must be non-negative"));
+            }
+            PipelineStep::Eval(EvalCheck::Outliers { method, sort_by, window, threshold, max_rate, .. }) => {
+                if threshold.is_some_and(|t| !t.is_finite() || t <= 0.0) {
+                    return Err(invalid("outlier threshold must be positive"));
+                }
+                if max_rate.is_some_and(|m| !(0.0..=1.0).contains(&m)) {
+                    return Err(invalid("outlier max_rate must be within 0..=1"));
+                }
+                if !matches!(method, OutlierMethod::Rolling) && (sort_by.is_some() || window.is_some()) {
+                    return Err(invalid("sort_by and window only apply to the rolling method"));
+                }
+                if window.is_some_and(|w| w < evals::MIN_HISTORY) {
+                    return Err(invalid(&format!("rolling window must be at least {}", evals::MIN_HISTORY)));
+                }
+            }
+            PipelineStep::Eval(EvalCheck::Timeseries { cadence, max_staleness, max_gap_rate, .. }) => {
+                for (field, d) in [("cadence", cadence), ("max_staleness", max_staleness)] {
+                    if d.as_deref().is_some_and(|d| evals::parse_duration(d).is_none()) {
+                        return Err(invalid(&format!("{field} must be a duration such as 1m, 1h or 1d")));
+                    }
+                }
+                if max_gap_rate.is_some_and(|m| !(0.0..=1.0).contains(&m)) {
+                    return Err(invalid("max_gap_rate must be within 0..=1"));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(steps)
+}
+
+fn threshold(check: &str, min: Option<f64>) -> Result<(), String> {
+    match min {
+        None => Err(invalid(&format!("{check} check needs min or min_{check}"))),
+        Some(m) if !(0.0..=1.0).contains(&m) => Err(invalid(&format!("{check} threshold must be within 0..=1"))),
+        _ => Ok(()),
+    }
+}
+
+// Working state threaded through the steps of one run
+struct Run<'a> {
+    store: &'a Store,
+    def: &'a PipelineDef,
+    ctl: &'a JobCtl,
+    dataset_id: Option<Uuid>,
+    // dataset version the source rows were read from
+    dataset_version: u32,
+    source: Option<Arc<Table>>,
+    profile: ModelProfile,
+    mapped: Option<Table>,
+    // the mappings the map step ran, explicit and suggested
+    mappings: BTreeMap<String, FeatureMapping>,
+    // dataset row behind each mapped row
+    row_ids: Vec<usize>,
+    coverage: Vec<FeatureCoverage>,
+    conformance: Vec<FeatureConformance>,
+    constraints: Vec<ConstraintReport>,
+    drift: Vec<FeatureDrift>,
+    outliers: Vec<OutlierReport>,
+    timeseries: Vec<TimeseriesReport>,
+    imputation: Vec<ImputationReport>,
+    quarantined: BTreeSet<u32>,
+    dropped: BTreeSet<u32>,
+    checks: Vec<CheckResult>,
+    findings: Vec<Finding>,
+    pass: bool,
+    human_note_required: bool,
+    proposal_id: Option<Uuid>,
+}
+
+impl Run<'_> {
+    fn step(&mut self, at: usize, step: &PipelineStep) -> Result<Option<String>, String> {
+        match step {
+            PipelineStep::Ingest { file_id, provider_id, name, into, primary_key } => {
+                let file = self.store.files.get(file_id).ok_or("FILE_NOT_FOUND")?.clone();
+                let rows = ingest::parse_rows(&file)?;
+                // the run evaluates the whole resulting version, not just the file
+                let (id, v) = match into {
+                    Some(id) => {
+                        let (v, written) = {
+                            let mut ds = self.store.datasets.get_mut(id).ok_or("DATASET_NOT_FOUND")?;
+                            if ds.provider_id != *provider_id {
+                                return Err("PROVIDER_MISMATCH".into());
+                            }
+                            datasets::append(&mut ds, &rows)?
+                        };
+                        // live subscribers see pipeline ingests like any other append
+                        if !written.is_empty() {
+                            realtime::publish(self.store, *id, Arc::new(Table::from_rows(&written)));
+                        }
+                        (*id, v)
+                    }
+                    None => {
+                        let name = name.clone().unwrap_or_else(|| file.filename.clone());
+                        let key = if primary_key.is_empty() { self.profile.primary_key.clone() } else { primary_key.clone() };
+                        let ds = datasets::create(*provider_id, name, format!("Ingested from {}", file.filename), &rows, key)?;
+                        let v = ds.versions[0].clone();
+                        let id = ds.id;
+                        self.store.datasets.insert(id, ds);
+                        (id, v)
+                    }
+                };
+                let c = v.ingested.unwrap_or_default();
+                self.dataset_id = Some(id);
+                self.dataset_version = v.version;
+                self.source = Some(v.rows.table());
+                Ok(Some(format!(
+                    "{} rows into dataset {id} v{}: {} inserted, {} updated, {} unchanged",
+                    rows.len(), v.version, c.inserted, c.updated, c.unchanged
+                )))
+            }
+            PipelineStep::Map { mappings, auto, min_confidence } => {
+                let source = self.source.clone().ok_or("DATASET_NOT_FOUND")?;
+                if let Some(name) = mappings.keys().find(|k| !self.profile.features.iter().any(|f| &f.name == *k)) {
+                    return Err(format!("MAPPING_UNKNOWN_FEATURE:{name}"));
+                }
+                let mut mappings = mappings.clone();
+                if *auto {
+                    let min = min_confidence.unwrap_or(suggest::DEFAULT_MIN_CONFIDENCE);
+                    let suggested = suggest::suggest(self.dataset_id.unwrap_or_default(), &self.profile, &source, min);
+                    for (feature, m) in suggested.mappings {
+                        if source.column(&feature).is_none() {
+                            mappings.entry(feature).or_insert(m);
+                        }
+                    }
+                }
+                let mappers = mapping::compile_all(&mappings)?;
+                let total = self.profile.features.len().max(1) as f64;
+                let (mut mapped, mut missing, mut failed) = (0, 0, 0);
+                let mut findings = Vec::new();
+                let ctl = self.ctl;
+                let table = mapping::apply(&self.profile.features, &mappers, &source, |i, feat, n, absent| {
+                    findings.extend(report::mapping(&feat.name, n, absent));
+                    failed += n;
+                    if absent { missing += 1 } else if mappers.contains_key(&feat.name) { mapped += 1 }
+                    ctl.progress(at, (i + 1) as f64 / total)
+                })?;
+                self.findings.extend(findings);
+                self.row_ids = (0..table.len()).collect();
+                self.mapped = Some(table);
+                self.mappings = mappings;
+                let by_name = self.profile.features.len() - mapped - missing;
+                Ok(Some(format!("{by_name} features by name, {mapped} mapped, {missing} filled with null, {failed} values failed to cast")))
+            }
+            PipelineStep::Impute { strategies, default } => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                let ctl = self.ctl;
+                let (imputed, reports, kept) = impute::impute(mapped, strategies, default.as_ref(), |p| ctl.progress(at, p))?;
+                let filled: usize = reports.iter().map(|r| r.filled).sum();
+                let dropped = mapped.len() - imputed.len();
+                let kept_ids: Vec<usize> = kept.iter().map(|k| self.row_ids[*k]).collect();
+                let kept_set: BTreeSet<usize> = kept_ids.iter().copied().collect();
+                self.dropped.extend(self.row_ids.iter().filter(|id| !kept_set.contains(id)).map(|id| *id as u32));
+                self.mapped = Some(imputed);
+                self.row_ids = kept_ids;
+                self.findings.extend(report::imputation(&reports));
+                self.imputation = reports;
+                Ok(Some(format!("{filled} values filled, {dropped} rows dropped")))
+            }
+            PipelineStep::Eval(EvalCheck::Coverage { min }) => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                let min = min.or(self.def.min_coverage).unwrap_or(0.0);
+                let total = mapped.len() as f64;
+                let features = mapped.columns().len().max(1) as f64;
+                let mut coverage = Vec::with_capacity(mapped.columns().len());
+                for (i, col) in mapped.columns().iter().enumerate() {
+                    self.ctl.progress(at, i as f64 / features)?;
+                    coverage.push(FeatureCoverage {
+                        name: col.name.clone(),
+                        coverage: if total>0.0 { col.non_null() as f64/total } else {0.0},
+                    });
+                }
+                let failing = coverage.iter().filter(|c| c.coverage < min).count();
+                self.pass &= failing == 0;
+                self.record(at, "coverage", json!({ "min": min }), report::coverage(&coverage, min));
+                self.coverage = coverage;
+                Ok(Some(format!("{failing} features below coverage {min}")))
+            }
+            PipelineStep::Eval(EvalCheck::Conformance { min }) => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                let min = min.or(self.def.min_conformance).unwrap_or(1.0);
+                let total = self.profile.features.len().max(1) as f64;
+                let mut conformance = Vec::with_capacity(self.profile.features.len());
+                for (i, feat) in self.profile.features.iter().enumerate() {
+                    self.ctl.progress(at, i as f64 / total)?;
+                    if let Some(col) = mapped.column(&feat.name) {
+                        conformance.push(evals::conformance(feat, col));
+                    }
+                }
+                for c in conformance.iter_mut() {
+                    c.samples.iter_mut().for_each(|s| s.row = self.row_ids[s.row]);
+                }
+                let failing = conformance.iter().filter(|c| c.rate < min).count();
+                self.pass &= failing == 0;
+                self.record(at, "conformance", json!({ "min": min }), report::conformance(&conformance, min));
+                self.conformance = conformance;
+                Ok(Some(format!("{failing} features below conformance {min}")))
+            }
+            PipelineStep::Eval(EvalCheck::Constraints { min }) => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                let min = min.unwrap_or(1.0);
+                let total = self.profile.features.len().max(1) as f64;
+                let mut reports = Vec::new();
+                for (i, feat) in self.profile.features.iter().enumerate() {
+                    self.ctl.progress(at, i as f64 / total)?;
+                    if let Some(col) = mapped.column(&feat.name) {
+                        reports.extend(evals::constraints(feat, col)?);
+                    }
+                }
+                if !self.profile.primary_key.is_empty() {
+                    reports.push(evals::primary_key(&self.profile.primary_key, mapped));
+                }
+                for r in reports.iter_mut() {
+                    r.samples.iter_mut().for_each(|s| s.row = self.row_ids[s.row]);
+                }
+                let failing = reports.iter()
+                    .filter(|r| r.checked > 0 && ((r.checked - r.violations) as f64 / r.checked as f64) < min)
+                    .count();
+                self.pass &= failing == 0;
+                self.record(at, "constraints", json!({ "min": min }), report::constraints(&reports, min));
+                self.constraints = reports;
+                Ok(Some(format!("{failing} constraints violated")))
+            }
+            PipelineStep::Eval(EvalCheck::Drift { reference, reference_version, thresholds }) => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                let reference_id = *reference;
+                let reference = {
+                    let ds = self.store.datasets.get(reference).ok_or("REFERENCE_DATASET_NOT_FOUND")?;
+                    match reference_version {
+                        Some(n) => ds.at(*n).map(|v| v.rows.clone()).ok_or("REFERENCE_VERSION_NOT_FOUND")?,
+                        None => ds.rows.clone(),
+                    }
+                }.table();
+                // the reference goes through the same mappings, so renamed and cast
+                // features line up with the current rows
+                let mappers = mapping::compile_all(&self.mappings)?;
+                let reference = mapping::apply(&self.profile.features, &mappers, &reference, |_, _, _, _| Ok(()))?;
+                let total = self.profile.features.len().max(1) as f64;
+                let (mut drift, mut missing) = (Vec::new(), Vec::new());
+                for (i, feat) in self.profile.features.iter().enumerate() {
+                    self.ctl.progress(at, i as f64 / total)?;
+                    match (mapped.column(&feat.name), reference.column(&feat.name)) {
+                        (Some(cur), Some(refr)) if refr.non_null() > 0 => drift.push(evals::drift(feat, cur, refr, thresholds)),
+                        _ => missing.push(feat.name.clone()),
+                    }
+                }
+                let drifted = drift.iter().filter(|d| d.drifted).count();
+                let findings = report::drift(&drift, &missing);
+                self.pass &= findings.iter().all(|f| f.severity != Severity::Error);
+                let limits = json!({
+                    "reference": reference_id,
+                    "reference_version": reference_version,
+                    "psi": thresholds.psi,
+                    "ks": thresholds.ks,
+                    "category_shift": thresholds.category_shift,
+                    "null_rate_change": thresholds.null_rate_change,
+                });
+                self.record(at, "drift", limits, findings);
+                self.drift = drift;
+                Ok(Some(format!("{drifted} features drifted, {} not in the reference", missing.len())))
+            }
+            PipelineStep::Eval(EvalCheck::Outliers { method, features, group_by, sort_by, window, threshold, max_rate, quarantine }) => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                for name in group_by.iter().chain(sort_by.iter()) {
+                    if mapped.column(name).is_none() { return Err(format!("OUTLIER_UNKNOWN_COLUMN:{name}")); }
+                }
+                let numeric = |f: &FeatureSpec| matches!(f.dtype.as_str(), "number" | "float" | "double" | "integer" | "int");
+                let feats: Vec<FeatureSpec> = match features {
+                    Some(names) => names.iter().map(|n| match self.profile.features.iter().find(|f| &f.name == n) {
+                        None => Err(format!("OUTLIER_UNKNOWN_FEATURE:{n}")),
+                        Some(f) if !numeric(f) => Err(format!("OUTLIER_FEATURE_NOT_NUMERIC:{n}")),
+                        Some(f) => Ok(f.clone()),
+                    }).collect::<Result<_, _>>()?,
+                    None => self.profile.features.iter().filter(|f| numeric(f)).cloned().collect(),
+                };
+                let threshold = threshold.unwrap_or(evals::default_outlier_threshold(*method));
+                let window = window.unwrap_or(evals::DEFAULT_WINDOW);
+                let max_rate = max_rate.unwrap_or(0.0);
+                let groups = mapped.groups(sort_by.as_deref(), group_by.as_deref());
+                let total = feats.len().max(1) as f64;
+                let (mut reports, mut flagged) = (Vec::new(), BTreeSet::new());
+                for (i, feat) in feats.iter().enumerate() {
+                    self.ctl.progress(at, i as f64 / total)?;
+                    let (mut report, rows) = evals::outliers(feat, mapped, &groups, group_by.as_deref(), *method, threshold, window);
+                    report.samples.iter_mut().for_each(|s| s.row = self.row_ids[s.row]);
+                    report.quarantined = *quarantine;
+                    flagged.extend(rows);
+                    reports.push(report);
+                }
+                let found: usize = reports.iter().map(|r| r.count).sum();
+                let limits = json!({
+                    "method": method.name(),
+                    "threshold": threshold,
+                    "window": matches!(method, OutlierMethod::Rolling).then_some(window),
+                    "group_by": group_by,
+                    "max_rate": max_rate,
+                    "quarantine": quarantine,
+                });
+                let detail = if *quarantine {
+                    // later steps and the published API only see the remaining rows
+                    let keep: Vec<usize> = (0..mapped.len()).filter(|i| !flagged.contains(i)).collect();
+                    self.quarantined.extend(flagged.iter().map(|i| self.row_ids[*i] as u32));
+                    self.mapped = Some(mapped.take(&keep));
+                    self.row_ids = keep.iter().map(|k| self.row_ids[*k]).collect();
+                    format!("{found} outliers, {} rows quarantined", flagged.len())
+                } else {
+                    let failing = reports.iter().filter(|r| r.rate > max_rate).count();
+                    self.pass &= failing == 0;
+                    format!("{found} outliers, {failing} features above rate {max_rate}")
+                };
+                self.record(at, "outliers", limits, report::outliers(&reports, max_rate));
+                self.outliers.extend(reports);
+                Ok(Some(detail))
+            }
+            PipelineStep::Eval(EvalCheck::Timeseries { ts, entity, cadence, max_staleness, max_gap_rate, allow_unordered }) => {
+                let mapped = self.mapped.as_ref().ok_or("NOTHING_MAPPED")?;
+                let ts = ts.as_deref().unwrap_or("ts");
+                for name in std::iter::once(ts).chain(entity.as_deref()) {
+                    if mapped.column(name).is_none() { return Err(format!("TIMESERIES_UNKNOWN_COLUMN:{name}")); }
+                }
+                let limits = evals::SeriesLimits {
+                    cadence: cadence.as_deref().and_then(evals::parse_duration),
+                    max_staleness: max_staleness.as_deref().and_then(evals::parse_duration),
+                    max_gap_rate: max_gap_rate.unwrap_or(0.0),
+                    allow_unordered: *allow_unordered,
+                };
+                let mut report = evals::timeseries(mapped, ts, entity.as_deref(), &limits, chrono::Utc::now().timestamp_micros());
+                report.findings.iter_mut().flat_map(|f| f.rows.iter_mut()).for_each(|r| *r = self.row_ids[*r]);
+                let detail = format!(
+                    "{} duplicate keys, {} out of order, {} gaps, {} stale entities",
+                    report.duplicates, report.out_of_order, report.gaps, report.stale_entities,
+                );
+                self.pass &= report.pass;
+                let findings = report::timeseries(&report, limits.max_gap_rate, limits.allow_unordered);
+                let limits = json!({
+                    "cadence_secs": limits.cadence,
+                    "max_staleness_secs": limits.max_staleness,
+                    "max_gap_rate": limits.max_gap_rate,
+                    "allow_unordered": limits.allow_unordered,
+                });
+                self.record(at, "timeseries", limits, findings);
+                self.timeseries.push(report);
+                Ok(Some(detail))
+            }
+            PipelineStep::Hitl { required } => {
+                self.human_note_required = *required;
+                Ok(None)
+            }
+            PipelineStep::Publish => {
+                let prop_id = Uuid::new_v4();
+                let mut prop = self.proposal();
+                prop.id = prop_id;
+                // the rows as mapped, imputed and quarantined are what the API serves
+                prop.snapshot = Snapshot::new(self.mapped.take().unwrap_or_default(), self.mappings.clone());
+                let creator = self.def.created_by.as_deref().unwrap_or_default().trim();
+                prop.history.push(review::event(creator, "created", None, ProposalStatus::Pending, None));
+                self.store.proposals.insert(prop_id, prop);
+                self.proposal_id = Some(prop_id);
+                Ok(Some(format!("proposal {prop_id}")))
+            }
+        }
+    }
+
+    fn record(&mut self, at: usize, check: &str, thresholds: serde_json::Value, findings: Vec<Finding>) {
+        self.checks.push(report::check(at, check, thresholds, &findings));
+        self.findings.extend(findings);
+    }
+
+    fn proposal(&self) -> ApiProposal {
+        let sample = self.mapped.as_ref()
+            .map(|m| (0..m.len().min(3)).map(|i| m.row_dense(i)).collect())
+            .unwrap_or_default();
+        ApiProposal {
+            id: Uuid::nil(),
+            status: ProposalStatus::Pending,
+            created_by: self.def.created_by.clone(),
+            history: Vec::new(),
+            dataset_id: self.dataset_id.unwrap_or_default(),
+            dataset_version: self.dataset_version,
+            model_profile_id: self.profile.id,
+            sample,
+            coverage: self.coverage.clone(),
+            conformance: self.conformance.clone(),
+            constraints: self.constraints.clone(),
+            drift: self.drift.clone(),
+            outliers: self.outliers.clone(),
+            timeseries: self.timeseries.clone(),
+            imputation: self.imputation.clone(),
+            quarantined_rows: self.quarantined.iter().copied().collect(),
+            dropped_rows: self.dropped.iter().copied().collect(),
+            stats: self.mapped.as_ref().map(|m| report::stats(&self.profile, m)).unwrap_or_default(),
+            checks: self.checks.clone(),
+            findings: self.findings.clone(),
+            waivers: Vec::new(),
+            pass: self.pass,
+            human_note_required: self.human_note_required,
+            snapshot: Snapshot::default(),
+        }
+    }
+}
+
+// Run pipeline = execute each validated step in order → proposal. Step status and
+// progress are written to the job as they change; cancellation is honoured between
+// steps and between features.
+pub fn run(store: &Store, def: &PipelineDef, steps: &[PipelineStep], ctl: &JobCtl) -> PipelineRunResult {
+    let skip_all = |from: usize, status: StepStatus| {
+        for i in from..steps.len() { ctl.update(i, status, 0.0, None); }
+    };
+    // Fetch components
+    let Some(profile) = store.models.get(&def.model_profile_id).map(|mp| mp.clone()) else {
+        skip_all(0, StepStatus::Skipped);
+        return PipelineRunResult::error("MODEL_PROFILE_NOT_FOUND")
+    };
+    let (source, dataset_version) = match def.dataset_id {
+        Some(id) => match store.datasets.get(&id) {
+            Some(ds) => (Some(ds.rows.clone()), ds.version),
+            None => {
+                skip_all(0, StepStatus::Skipped);
+                return PipelineRunResult::error("DATASET_NOT_FOUND");
+            }
+        },
+        None => (None, 0),
+    };
+    let mut run = Run {
+        store,
+        def,
+        ctl,
+        dataset_id: def.dataset_id,
+        dataset_version,
+        // concatenated here rather than under the dataset's lock
+        source: source.map(|rows| rows.table()),
+        profile,
+        mapped: None,
+        mappings: BTreeMap::new(),
+        row_ids: Vec::new(),
+        coverage: Vec::new(),
+        conformance: Vec::new(),
+        constraints: Vec::new(),
+        drift: Vec::new(),
+        outliers: Vec::new(),
+        timeseries: Vec::new(),
+        imputation: Vec::new(),
+        quarantined: BTreeSet::new(),
+        dropped: BTreeSet::new(),
+        checks: Vec::new(),
+        findings: Vec::new(),
+        pass: true,
+        // approvals need a note unless a hitl step says otherwise
+        human_note_required: true,
+        proposal_id: None,
+    };
+    for (i, step) in steps.iter().enumerate() {
+        let outcome = ctl.progress(i, 0.0).and_then(|_| run.step(i, step));
+        match outcome {
+            Ok(detail) => ctl.update(i, StepStatus::Done, 1.0, detail),
+            Err(e) if ctl.is_cancelled() => {
+                ctl.update(i, StepStatus::Cancelled, 0.0, None);
+                skip_all(i + 1, StepStatus::Skipped);
+                return PipelineRunResult::error(&e);
+            }
+            Err(e) => {
+                ctl.update(i, StepStatus::Failed, 0.0, Some(e.clone()));
+                skip_all(i + 1, StepStatus::Skipped);
+                return PipelineRunResult::error(&e);
+            }
+        }
+    }
+    let proposal = run.proposal_id
+        .and_then(|id| store.proposals.get(&id).map(|p| p.clone()))
+        .unwrap_or_else(|| run.proposal());
+    PipelineRunResult::ok(run.proposal_id, proposal)
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::body::{Body, Bytes};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use crate::formats::Encoder;
+use crate::index::{ColumnIndex, DatasetIndexes};
+use crate::table::{Cell, Table};
+
+// Rows scanned between sends; also the unit of backpressure on slow consumers.
+pub const CHUNK_ROWS: usize = 1024;
+// Encoded chunks buffered ahead of the client before the scan blocks.
+const CHANNEL_CHUNKS: usize = 4;
+
+// Inclusive bounds on a numeric feature
+#[derive(Clone, Copy, Default, Deserialize)]
+pub struct NumberRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+// Filters accepted by the consumer query endpoint
+#[derive(Clone, Default)]
+pub struct RowFilter {
+    pub symbol: Option<String>,
+    pub start: Option<chrono::DateTime<chrono::Utc>>,
+    pub end: Option<chrono::DateTime<chrono::Utc>>,
+    pub ranges: HashMap<String, NumberRange>,
+}
+
+impl RowFilter {
+    // For demo: just filter symbol/time if fields exist
+    pub fn matches(&self, table: &Table, i: usize) -> bool {
+        if let Some(sym) = self.symbol.as_ref() {
+            match table.cell("symbol", i) {
+                Cell::Null => {}
+                cell => if cell.as_str() != Some(sym.as_str()) { return false; },
+            }
+        }
+        // numeric ranges only match rows that carry a number for the feature
+        for (col, r) in self.ranges.iter() {
+            let Some(x) = table.cell(col, i).as_f64() else { return false };
+            if r.min.is_some_and(|min| x < min) || r.max.is_some_and(|max| x > max) { return false; }
+        }
+        // time filter on field "ts"; native datetime columns need no parsing
+        if self.start.is_none() && self.end.is_none() { return true; }
+        let Some(t) = table.cell("ts", i).as_micros() else { return true };
+        if let Some(start) = self.start { if t < start.timestamp_micros() { return false; } }
+        if let Some(end) = self.end { if t > end.timestamp_micros() { return false; } }
+        true
+    }
+}
+
+// Narrows a query to candidate row ids using whatever indexes cover its predicates.
+// Returns None when no index applies and the caller has to scan. Candidates are a
+// superset of the answer in dataset order; `RowFilter::matches` still runs on each.
+pub fn plan(filter: &RowFilter, indexes: &DatasetIndexes) -> Option<Vec<u32>> {
+    let mut sets: Vec<Vec<u32>> = Vec::new();
+    if let (Some(sym), Some(ColumnIndex::Hash(h))) = (filter.symbol.as_ref(), indexes.get("symbol")) {
+        let mut ids = h.buckets.get(sym).cloned().unwrap_or_default();
+        ids.extend_from_slice(&h.missing);
+        sets.push(ids);
+    }
+    if filter.start.is_some() || filter.end.is_some() {
+        if let Some(ColumnIndex::Datetime(s)) = indexes.get("ts") {
+            let lo = filter.start.map(|t| t.timestamp_micros());
+            let hi = filter.end.map(|t| t.timestamp_micros());
+            sets.push(s.range(lo, hi).chain(s.unkeyed.iter().copied()).collect());
+        }
+    }
+    for (col, r) in filter.ranges.iter() {
+        if let Some(ColumnIndex::Number(s)) = indexes.get(col) {
+            sets.push(s.range(r.min, r.max).collect());
+        }
+    }
+    if sets.is_empty() { return None; }
+    sets.iter_mut().for_each(|ids| ids.sort_unstable());
+    sets.sort_by_key(|ids| ids.len());
+    let mut rest = sets.into_iter();
+    let first = rest.next().unwrap_or_default();
+    Some(rest.fold(first, |acc, ids| {
+        acc.into_iter().filter(|id| ids.binary_search(id).is_ok()).collect()
+    }))
+}
+
+// Scans `table` on a blocking thread and streams encoded chunks into the response body.
+// The channel is bounded, so a slow reader stalls the scan instead of growing a buffer,
+// and a disconnected reader drops the receiver, which the scan notices within a chunk's
+// worth of scanned rows even when a selective filter sends nothing.
+pub fn stream_rows(
+    table: Arc<Table>,
+    indexes: Arc<DatasetIndexes>,
+    filter: RowFilter,
+    limit: Option<usize>,
+    mut encoder: Encoder,
+) -> Body {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(CHANNEL_CHUNKS);
+    tokio::task::spawn_blocking(move || {
+        let send = |bytes: Result<Vec<u8>, String>| -> bool {
+            let item = bytes.map(Bytes::from).map_err(std::io::Error::other);
+            let failed = item.is_err();
+            tx.blocking_send(item).is_ok() && !failed
+        };
+        if !send(encoder.begin()) { return; }
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut emitted = 0usize;
+        let mut chunk: Vec<serde_json::Value> = Vec::with_capacity(CHUNK_ROWS);
+        let candidates: Box<dyn Iterator<Item = usize>> = match plan(&filter, &indexes) {
+            Some(ids) => Box::new(ids.into_iter().map(|id| id as usize)),
+            None => Box::new(0..table.len()),
+        };
+        for (scanned, i) in candidates.enumerate() {
+            if emitted >= limit { break; }
+            if scanned % CHUNK_ROWS == 0 && tx.is_closed() { return; }
+            if !filter.matches(&table, i) { continue; }
+            chunk.push(table.row_dense(i));
+            emitted += 1;
+            if chunk.len() == CHUNK_ROWS {
+                if !send(encoder.encode(&chunk)) { return; }
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() && !send(encoder.encode(&chunk)) { return; }
+        send(encoder.finish());
+    });
+    Body::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|item| (item, rx))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn table() -> Table {
+        Table::from_rows(&[
+            json!({"symbol": "A", "ts": "2024-01-01T00:00:00Z", "price": 10}),
+            json!({"symbol": "B", "ts": "2024-01-02T00:00:00Z", "price": 20}),
+            json!({"symbol": "A", "ts": "2024-01-03T00:00:00Z", "price": 30}),
+            json!({"ts": "2024-01-04T00:00:00Z", "price": 40}),
+            json!({"symbol": "A", "price": 50}),
+        ])
+    }
+
+    fn at(s: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        Some(s.parse().unwrap())
+    }
+
+    // what the planner proposes and what survives the row filter
+    fn answer(filter: &RowFilter) -> (Option<Vec<u32>>, Vec<usize>) {
+        let t = table();
+        let planned = plan(filter, &DatasetIndexes::build(&t));
+        let rows = (0..t.len()).filter(|i| filter.matches(&t, *i)).collect();
+        (planned, rows)
+    }
+
+    #[test]
+    fn no_indexed_predicate_means_a_scan() {
+        assert_eq!(answer(&RowFilter::default()), (None, vec![0, 1, 2, 3, 4]));
+        let ranges = [("volume".to_string(), NumberRange { min: Some(1.0), max: None })].into();
+        assert_eq!(plan(&RowFilter { ranges, ..Default::default() }, &DatasetIndexes::build(&table())), None);
+    }
+
+    #[test]
+    fn symbol_candidates_include_rows_without_one() {
+        let filter = RowFilter { symbol: Some("A".into()), ..Default::default() };
+        assert_eq!(answer(&filter), (Some(vec![0, 2, 3, 4]), vec![0, 2, 3, 4]));
+    }
+
+    #[test]
+    fn predicates_intersect_in_dataset_order() {
+        let ranges = [("price".to_string(), NumberRange { min: Some(15.0), max: Some(45.0) })].into();
+        let filter = RowFilter { symbol: Some("A".into()), start: at("2024-01-02T00:00:00Z"), end: None, ranges };
+        let (planned, rows) = answer(&filter);
+        // row 4 has no ts, so the time index lets it through and the price range drops it
+        assert_eq!(planned, Some(vec![2, 3]));
+        assert_eq!(rows, vec![2, 3]);
+    }
+
+    #[test]
+    fn candidates_are_a_superset_of_the_answer() {
+        let filter = RowFilter { start: at("2024-01-02T00:00:00Z"), end: at("2024-01-03T00:00:00Z"), ..Default::default() };
+        let (planned, rows) = answer(&filter);
+        assert_eq!(planned, Some(vec![1, 2, 4]));
+        assert_eq!(rows, vec![1, 2, 4]);
+        let ranges = [("price".to_string(), NumberRange { min: Some(60.0), max: None })].into();
+        assert_eq!(answer(&RowFilter { ranges, ..Default::default() }), (Some(vec![]), vec![]));
+    }
+}
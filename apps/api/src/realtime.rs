@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::http::{HeaderMap, StatusCode};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use crate::error::ApiError;
+use crate::lifecycle;
+use crate::mapping::{self, Mapper};
+use crate::models::FeatureSpec;
+use crate::query::RowFilter;
+use crate::state::{AppState, Store};
+use crate::table::Table;
+use crate::versions;
+
+// Appended batches buffered per dataset before slow subscribers start lagging.
+const FEED_CAPACITY: usize = 256;
+
+pub fn feed(store: &Store, dataset_id: Uuid) -> broadcast::Sender<Arc<Table>> {
+    store
+        .feeds
+        .entry(dataset_id)
+        .or_insert_with(|| broadcast::channel(FEED_CAPACITY).0)
+        .clone()
+}
+
+// Fans a freshly appended batch out to the dataset's subscribers, if any.
+pub fn publish(store: &Store, dataset_id: Uuid, batch: Arc<Table>) {
+    if let Some(tx) = store.feeds.get(&dataset_id) {
+        let _ = tx.send(batch);
+    }
+}
+
+// Key from the X-API-Key header, or the api_key query parameter for browser
+// EventSource / WebSocket clients that cannot set headers.
+pub fn api_key(headers: &HeaderMap, param: Option<&str>) -> Option<String> {
+    headers
+        .get("x-api-key")
+        .and_then(|v| v.to_str().ok())
+        .or(param)
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+}
+
+// Holds one of the key's subscription slots until dropped.
+struct Slot {
+    store: Store,
+    key: String,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(mut n) = self.store.subscriptions.get_mut(&self.key) {
+            *n = n.saturating_sub(1);
+        }
+    }
+}
+
+pub enum Push {
+    Rows(Vec<serde_json::Value>),
+    // rows withheld by the tier's rate limit
+    Throttled(usize),
+    // batches missed because the subscriber fell behind the feed
+    Lagged(u64),
+    // the product stopped serving; the last push before the stream ends
+    Closed(String),
+}
+
+pub struct Subscription {
+    api_id: Uuid,
+    rx: broadcast::Receiver<Arc<Table>>,
+    // the requested version's profile features and mappings; appended rows go
+    // out mapped like the rows a query of that version returns
+    features: Vec<FeatureSpec>,
+    mappers: BTreeMap<String, Mapper>,
+    filter: RowFilter,
+    rows_per_sec: Option<usize>,
+    window: Instant,
+    sent_in_window: usize,
+    throttled: usize,
+    closed: bool,
+    slot: Slot,
+}
+
+pub fn subscribe(
+    st: &AppState,
+    api_id: Uuid,
+    key: Option<String>,
+    version: Option<&str>,
+    filter: RowFilter,
+) -> Result<Subscription, ApiError> {
+    let key = key.ok_or(ApiError::new(StatusCode::UNAUTHORIZED, "API_KEY_REQUIRED"))?;
+    let Some(api) = st.store.apis.get(&api_id) else {
+        return Err(ApiError::not_found("API_NOT_FOUND"));
+    };
+    lifecycle::serving(&api, chrono::Utc::now())?;
+    let v = versions::resolve(&api, version).map_err(|e| match e.as_str() {
+        "VERSION_NOT_FOUND" => ApiError::not_found(e),
+        _ => ApiError::new(StatusCode::GONE, e),
+    })?;
+    let (dataset_id, mappings) = (v.dataset_id, v.snapshot.mappings.clone());
+    let features = st.store.models.get(&v.model_profile_id).map(|m| m.features.clone()).unwrap_or_default();
+    drop(api);
+    let mappers = mapping::compile_all(&mappings).map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("MAPPING_FAILED:{e}")))?;
+    let tier = match st.store.keys.get(&key) {
+        Some(k) if k.api_id == api_id => k.tier,
+        Some(_) => return Err(ApiError::new(StatusCode::FORBIDDEN, "KEY_NOT_VALID_FOR_API")),
+        None => return Err(ApiError::new(StatusCode::UNAUTHORIZED, "API_KEY_INVALID")),
+    };
+    {
+        let mut open = st.store.subscriptions.entry(key.clone()).or_insert(0);
+        if *open >= tier.max_subscriptions() {
+            return Err(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "SUBSCRIPTION_LIMIT_REACHED"));
+        }
+        *open += 1;
+    }
+    Ok(Subscription {
+        api_id,
+        rx: feed(&st.store, dataset_id).subscribe(),
+        features,
+        mappers,
+        filter,
+        rows_per_sec: tier.rows_per_sec(),
+        window: Instant::now(),
+        sent_in_window: 0,
+        throttled: 0,
+        closed: false,
+        slot: Slot { store: st.store.clone(), key },
+    })
+}
+
+impl Subscription {
+    // Waits for the next appended rows that pass the filter. Returns None once the
+    // feed is gone or after a Closed push.
+    pub async fn next(&mut self) -> Option<Push> {
+        if self.closed {
+            return None;
+        }
+        if self.throttled > 0 {
+            return Some(Push::Throttled(std::mem::take(&mut self.throttled)));
+        }
+        loop {
+            let batch = match self.rx.recv().await {
+                Ok(batch) => batch,
+                Err(broadcast::error::RecvError::Lagged(n)) => return Some(Push::Lagged(n)),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            // a product paused or retired since the subscription opened stops here
+            let serving = match self.slot.store.apis.get(&self.api_id) {
+                Some(api) => lifecycle::serving(&api, chrono::Utc::now()),
+                None => Err(ApiError::not_found("API_NOT_FOUND")),
+            };
+            if let Err(e) = serving {
+                self.closed = true;
+                return Some(Push::Closed(e.code));
+            }
+            // without a profile there is nothing to map to, so rows go out as appended
+            let batch = if self.features.is_empty() {
+                batch
+            } else {
+                match mapping::apply(&self.features, &self.mappers, &batch, |_, _, _, _| Ok(())) {
+                    Ok(mapped) => Arc::new(mapped),
+                    Err(_) => continue,
+                }
+            };
+            let mut rows: Vec<serde_json::Value> = (0..batch.len())
+                .filter(|i| self.filter.matches(&batch, *i))
+                .map(|i| batch.row_dense(i))
+                .collect();
+            if let Some(limit) = self.rows_per_sec {
+                if self.window.elapsed() >= Duration::from_secs(1) {
+                    self.window = Instant::now();
+                    self.sent_in_window = 0;
+                }
+                let allowed = limit.saturating_sub(self.sent_in_window);
+                if rows.len() > allowed {
+                    self.throttled += rows.len() - allowed;
+                    rows.truncate(allowed);
+                }
+                self.sent_in_window += rows.len();
+            }
+            if !rows.is_empty() { return Some(Push::Rows(rows)); }
+            if self.throttled > 0 {
+                return Some(Push::Throttled(std::mem::take(&mut self.throttled)));
+            }
+        }
+    }
+}
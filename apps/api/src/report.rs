@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+use crate::models::*;
+use crate::table::{format_micros, Column, Table};
+
+// Most frequent values and histogram bins kept per feature.
+const TOP_VALUES: usize = 5;
+const BINS: usize = 10;
+// Rows copied from an eval sample onto its finding.
+const FINDING_ROWS: usize = 5;
+
+pub fn stats(profile: &ModelProfile, table: &Table) -> Vec<FeatureStats> {
+    profile.features.iter()
+        .filter_map(|feat| table.column(&feat.name).map(|col| feature_stats(feat, col)))
+        .collect()
+}
+
+pub fn feature_stats(feat: &FeatureSpec, col: &Column) -> FeatureStats {
+    let mut s = FeatureStats {
+        feature: feat.name.clone(),
+        dtype: feat.dtype.clone(),
+        count: col.len(),
+        null_count: col.len() - col.non_null(),
+        distinct: 0,
+        min: None,
+        max: None,
+        mean: None,
+        top_values: Vec::new(),
+        histogram: Vec::new(),
+    };
+    // (value, count, first row) per distinct value
+    let mut counts: HashMap<String, (Value, usize, usize)> = HashMap::new();
+    for (i, c) in col.iter().enumerate().filter(|(_, c)| !c.is_null()) {
+        let v = c.to_value();
+        counts.entry(v.to_string()).or_insert((v, 0, i)).1 += 1;
+    }
+    s.distinct = counts.len();
+    match feat.dtype.as_str() {
+        "datetime" | "timestamp" => {
+            let ms: Vec<i64> = col.iter().filter_map(|c| c.as_micros()).collect();
+            s.min = ms.iter().min().map(|m| Value::String(format_micros(*m)));
+            s.max = ms.iter().max().map(|m| Value::String(format_micros(*m)));
+            return s;
+        }
+        "number" | "float" | "double" | "integer" | "int" => {
+            let xs: Vec<f64> = col.iter().filter_map(|c| c.as_f64()).collect();
+            if !xs.is_empty() {
+                let (lo, hi) = xs.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| (lo.min(*x), hi.max(*x)));
+                s.min = Some(number(lo));
+                s.max = Some(number(hi));
+                s.mean = Some(xs.iter().sum::<f64>() / xs.len() as f64);
+                s.histogram = histogram(&xs, lo, hi);
+            }
+        }
+        _ => {}
+    }
+    let mut top: Vec<(Value, usize, usize)> = counts.into_values().collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+    s.top_values = top.into_iter().take(TOP_VALUES).map(|(value, count, _)| ValueCount { value, count }).collect();
+    s
+}
+
+fn histogram(xs: &[f64], lo: f64, hi: f64) -> Vec<HistogramBin> {
+    if lo == hi {
+        return vec![HistogramBin { start: lo, end: hi, count: xs.len() }];
+    }
+    let width = (hi - lo) / BINS as f64;
+    let mut counts = [0usize; BINS];
+    for x in xs {
+        counts[(((x - lo) / width) as usize).min(BINS - 1)] += 1;
+    }
+    counts.iter().enumerate()
+        .map(|(b, count)| HistogramBin { start: lo + b as f64 * width, end: lo + (b + 1) as f64 * width, count: *count })
+        .collect()
+}
+
+fn number(x: f64) -> Value {
+    serde_json::Number::from_f64(x).map(Value::Number).unwrap_or(Value::Null)
+}
+
+fn finding(severity: Severity, check: &str, feature: Option<&str>, message: String) -> Finding {
+    Finding { severity, check: check.into(), feature: feature.map(String::from), message, rows: Vec::new() }
+}
+
+fn rows(samples: &[OffendingRow]) -> Vec<usize> {
+    samples.iter().take(FINDING_ROWS).map(|s| s.row).collect()
+}
+
+pub fn check(step: usize, name: &str, thresholds: Value, findings: &[Finding]) -> CheckResult {
+    let count = |sev: Severity| findings.iter().filter(|f| f.severity == sev).count();
+    CheckResult {
+        step,
+        check: name.into(),
+        thresholds,
+        passed: count(Severity::Error) == 0,
+        errors: count(Severity::Error),
+        warnings: count(Severity::Warning),
+    }
+}
+
+pub fn mapping(feature: &str, failed: usize, missing: bool) -> Vec<Finding> {
+    let mut out = Vec::new();
+    if missing {
+        out.push(finding(Severity::Warning, "map", Some(feature), "no source column or mapping; all values are null".into()));
+    }
+    if failed > 0 {
+        out.push(finding(Severity::Warning, "map", Some(feature), format!("{failed} values failed to cast and became null")));
+    }
+    out
+}
+
+pub fn imputation(reports: &[ImputationReport]) -> Vec<Finding> {
+    let mut out = Vec::new();
+    for r in reports {
+        if r.filled > 0 {
+            out.push(finding(Severity::Info, "impute", Some(&r.feature), format!("{} nulls filled by {}", r.filled, r.strategy)));
+        }
+        if r.dropped > 0 {
+            out.push(finding(Severity::Warning, "impute", Some(&r.feature), format!("{} rows dropped for null values", r.dropped)));
+        }
+    }
+    out
+}
+
+pub fn coverage(reports: &[FeatureCoverage], min: f64) -> Vec<Finding> {
+    reports.iter().filter_map(|c| match c.coverage {
+        x if x < min => Some(finding(Severity::Error, "coverage", Some(&c.name), format!("coverage {x:.3} is below {min}"))),
+        x if x < 1.0 => Some(finding(Severity::Info, "coverage", Some(&c.name), format!("{:.1}% of values are null", (1.0 - x) * 100.0))),
+        _ => None,
+    }).collect()
+}
+
+pub fn conformance(reports: &[FeatureConformance], min: f64) -> Vec<Finding> {
+    reports.iter().filter(|c| c.nonconforming > 0).map(|c| {
+        let severity = if c.rate < min { Severity::Error } else { Severity::Warning };
+        let message = format!("{} of {} values are not {} (rate {:.3}, min {min})", c.nonconforming, c.checked, c.dtype, c.rate);
+        Finding { rows: rows(&c.samples), ..finding(severity, "conformance", Some(&c.name), message) }
+    }).collect()
+}
+
+pub fn constraints(reports: &[ConstraintReport], min: f64) -> Vec<Finding> {
+    reports.iter().filter(|r| r.violations > 0).map(|r| {
+        let rate = (r.checked - r.violations) as f64 / r.checked.max(1) as f64;
+        let severity = if rate < min { Severity::Error } else { Severity::Warning };
+        let message = format!("{} of {} values violate {}", r.violations, r.checked, r.constraint);
+        Finding { rows: rows(&r.samples), ..finding(severity, "constraints", Some(&r.feature), message) }
+    }).collect()
+}
+
+// `missing` are features the reference has no values for. They are not compared,
+// and a check that compared nothing fails rather than passing vacuously.
+pub fn drift(reports: &[FeatureDrift], missing: &[String]) -> Vec<Finding> {
+    let mut out: Vec<Finding> = missing.iter()
+        .map(|f| finding(Severity::Warning, "drift", Some(f), "no values in the reference; not compared".into()))
+        .collect();
+    if reports.is_empty() && !missing.is_empty() {
+        out.push(finding(Severity::Error, "drift", None, "no feature could be compared with the reference".into()));
+    }
+    out.extend(reports.iter().filter(|d| d.drifted).map(|d| {
+        let measures: Vec<String> = d.exceeded.iter().map(|m| match m.as_str() {
+            "psi" => format!("psi {:.3}", d.psi.unwrap_or_default()),
+            "ks" => format!("ks {:.3}", d.ks.unwrap_or_default()),
+            "category_shift" => format!("category shift {:.3}", d.category_shift.unwrap_or_default()),
+            _ => format!("null rate {:.3} -> {:.3}", d.null_rate_reference, d.null_rate_current),
+        }).collect();
+        finding(Severity::Error, "drift", Some(&d.feature), format!("drifted from the reference: {}", measures.join(", ")))
+    }));
+    out
+}
+
+pub fn outliers(reports: &[OutlierReport], max_rate: f64) -> Vec<Finding> {
+    reports.iter().filter(|r| r.count > 0).map(|r| {
+        let (severity, message) = if r.quarantined {
+            (Severity::Info, format!("{} {} outliers quarantined", r.count, r.method))
+        } else if r.rate > max_rate {
+            (Severity::Error, format!("{} {} outliers (rate {:.4}, max {max_rate})", r.count, r.method, r.rate))
+        } else {
+            (Severity::Warning, format!("{} {} outliers within max rate {max_rate}", r.count, r.method))
+        };
+        let rows = r.samples.iter().take(FINDING_ROWS).map(|s| s.row).collect();
+        Finding { rows, ..finding(severity, "outliers", Some(&r.feature), message) }
+    }).collect()
+}
+
+pub fn timeseries(r: &TimeseriesReport, max_gap_rate: f64, allow_unordered: bool) -> Vec<Finding> {
+    let rows_of = |kind: &str| -> Vec<usize> {
+        let mut rows: Vec<usize> = r.findings.iter().filter(|f| f.kind == kind).flat_map(|f| f.rows.iter().copied()).collect();
+        let mut seen = HashSet::new();
+        rows.retain(|x| seen.insert(*x));
+        rows.truncate(FINDING_ROWS);
+        rows
+    };
+    let feature = Some(r.ts.as_str());
+    let mut out = Vec::new();
+    let mut push = |severity, kind: &str, message: String| {
+        out.push(Finding { rows: rows_of(kind), ..finding(severity, "timeseries", feature, message) });
+    };
+    if r.missing_ts > 0 {
+        push(Severity::Warning, "", format!("{} rows have no timestamp", r.missing_ts));
+    }
+    if r.duplicates > 0 {
+        push(Severity::Error, "duplicate_key", format!("{} duplicate (entity, {}) keys", r.duplicates, r.ts));
+    }
+    if r.out_of_order > 0 {
+        let severity = if allow_unordered { Severity::Warning } else { Severity::Error };
+        push(severity, "out_of_order", format!("{} rows are earlier than the row before them", r.out_of_order));
+    }
+    if r.gaps > 0 {
+        let severity = if r.gap_rate > max_gap_rate { Severity::Error } else { Severity::Warning };
+        let message = format!("{} gaps, {} intervals missing (rate {:.4}, max {max_gap_rate})", r.gaps, r.missing_intervals, r.gap_rate);
+        push(severity, "gap", message);
+    }
+    if r.stale_entities > 0 {
+        let message = format!("{} series older than {}s; latest {}", r.stale_entities, r.max_staleness_secs.unwrap_or_default(), r.latest.clone().unwrap_or_default());
+        push(Severity::Error, "stale", message);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    // (severity, feature) of each finding
+    fn summary(findings: &[Finding]) -> Vec<(Value, Option<String>)> {
+        findings.iter().map(|f| (json!(f.severity), f.feature.clone())).collect()
+    }
+
+    fn feature(name: &str, dtype: &str) -> FeatureSpec {
+        FeatureSpec { name: name.into(), dtype: dtype.into(), ..Default::default() }
+    }
+
+    #[test]
+    fn coverage_below_the_minimum_is_an_error_and_any_gap_is_noted() {
+        let reports = [("a", 1.0), ("b", 0.9), ("c", 0.4)].map(|(name, coverage)| FeatureCoverage { name: name.into(), coverage });
+        let findings = coverage(&reports, 0.5);
+        assert_eq!(summary(&findings), vec![(json!("info"), Some("b".into())), (json!("error"), Some("c".into()))]);
+        assert_eq!(findings[0].message, "10.0% of values are null");
+        let result = check(2, "coverage", json!({"min": 0.5}), &findings);
+        assert_eq!((result.step, result.passed, result.errors, result.warnings), (2, false, 1, 0));
+    }
+
+    #[test]
+    fn numeric_stats_have_a_range_mean_and_histogram() {
+        let col = Column::from_values("x", [json!(0), json!(10), json!(null), json!(5), json!(5)].iter().map(Some));
+        let s = feature_stats(&feature("x", "number"), &col);
+        assert_eq!((s.count, s.null_count, s.distinct), (5, 1, 3));
+        assert_eq!((s.min, s.max, s.mean), (Some(json!(0.0)), Some(json!(10.0)), Some(5.0)));
+        assert_eq!(s.histogram.len(), BINS);
+        assert_eq!(s.histogram.iter().map(|b| b.count).collect::<Vec<_>>(), vec![1, 0, 0, 0, 0, 2, 0, 0, 0, 1]);
+        assert_eq!(s.top_values.first().map(|v| (v.value.clone(), v.count)), Some((json!(5), 2)));
+    }
+
+    #[test]
+    fn categorical_stats_rank_values_by_count_then_first_seen() {
+        let values = ["b", "a", "a", "c", "b"].map(|v| json!(v));
+        let s = feature_stats(&feature("s", "string"), &Column::from_values("s", values.iter().map(Some)));
+        assert_eq!(s.top_values.iter().map(|v| (v.value.clone(), v.count)).collect::<Vec<_>>(), vec![(json!("b"), 2), (json!("a"), 2), (json!("c"), 1)]);
+        assert_eq!((s.min, s.mean), (None, None));
+    }
+
+    #[test]
+    fn datetime_stats_report_the_span() {
+        let values = [json!("2024-01-02T00:00:00Z"), json!("2024-01-01T00:00:00Z")];
+        let s = feature_stats(&feature("ts", "datetime"), &Column::from_values("ts", values.iter().map(Some)));
+        assert_eq!((s.min, s.max), (Some(json!("2024-01-01T00:00:00Z")), Some(json!("2024-01-02T00:00:00Z"))));
+        assert!(s.top_values.is_empty());
+    }
+
+    #[test]
+    fn outlier_severity_follows_the_rate_and_quarantine() {
+        let report = |rate: f64, quarantined: bool| OutlierReport {
+            feature: "x".into(), method: "zscore".into(), threshold: 3.0, checked: 100, count: 2, rate, samples: Vec::new(), quarantined,
+        };
+        let findings = outliers(&[report(0.02, false), report(0.2, false), report(0.2, true)], 0.05);
+        assert_eq!(findings.iter().map(|f| json!(f.severity)).collect::<Vec<_>>(), vec![json!("warning"), json!("error"), json!("info")]);
+    }
+}
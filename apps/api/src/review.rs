@@ -0,0 +1,232 @@
+use chrono::Utc;
+use uuid::Uuid;
+use crate::models::*;
+
+// Evals passed, or every failed check carries an unexpired waiver.
+pub fn passes(prop: &ApiProposal) -> bool {
+    let now = Utc::now();
+    prop.pass || prop.checks.iter()
+        .filter(|c| !c.passed)
+        .all(|c| prop.waivers.iter().any(|w| w.step == c.step && w.active(now)))
+}
+
+// Applies a reviewer's decision to a proposal and records it in the history.
+// Decisions are only taken on pending proposals; comments are allowed at any time.
+pub fn review(prop: &mut ApiProposal, req: ReviewRequest) -> Result<(), String> {
+    let reviewer = req.reviewer.trim();
+    if reviewer.is_empty() {
+        return Err("REVIEWER_REQUIRED".into());
+    }
+    let comment = req.comment.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    let from = prop.status;
+    let (action, to) = match req.action {
+        ReviewAction::Comment => ("commented", from),
+        ReviewAction::Approve => ("approved", ProposalStatus::Approved),
+        ReviewAction::Reject => ("rejected", ProposalStatus::Rejected),
+        ReviewAction::RequestChanges => ("changes_requested", ProposalStatus::ChangesRequested),
+        ReviewAction::Resubmit => ("resubmitted", ProposalStatus::Pending),
+    };
+    let allowed = match req.action {
+        ReviewAction::Comment => true,
+        ReviewAction::Resubmit => from == ProposalStatus::ChangesRequested,
+        _ => from == ProposalStatus::Pending,
+    };
+    if !allowed {
+        return Err(format!("INVALID_TRANSITION:{}->{action}", from.name()));
+    }
+    // everything but an approval or a resubmit needs a reason
+    if comment.is_none() && !matches!(req.action, ReviewAction::Approve | ReviewAction::Resubmit) {
+        return Err("COMMENT_REQUIRED".into());
+    }
+    if matches!(req.action, ReviewAction::Approve) {
+        not_creator(prop, reviewer, "SELF_APPROVAL_NOT_ALLOWED")?;
+        if !passes(prop) {
+            return Err("EVALS_NOT_PASSED".into());
+        }
+        if prop.human_note_required && comment.is_none() {
+            return Err("HUMAN_NOTE_REQUIRED".into());
+        }
+    }
+    prop.status = to;
+    prop.history.push(event(reviewer, action, Some(from), to, comment));
+    Ok(())
+}
+
+// Approvals and waivers must come from someone other than the creator, so a
+// proposal whose creator is unknown takes neither.
+fn not_creator(prop: &ApiProposal, reviewer: &str, self_error: &str) -> Result<(), String> {
+    match prop.created_by.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        None => Err("PROPOSAL_CREATOR_UNKNOWN".into()),
+        Some(c) if c.eq_ignore_ascii_case(reviewer) => Err(self_error.into()),
+        Some(_) => Ok(()),
+    }
+}
+
+// Waives one failed check until `expires_at`. Only open proposals take waivers,
+// and, like approval, not from the proposal's creator.
+pub fn waive(prop: &mut ApiProposal, req: WaiverCreate) -> Result<Waiver, String> {
+    let reviewer = req.reviewer.trim();
+    let justification = req.justification.trim();
+    if reviewer.is_empty() {
+        return Err("REVIEWER_REQUIRED".into());
+    }
+    if justification.is_empty() {
+        return Err("JUSTIFICATION_REQUIRED".into());
+    }
+    let now = Utc::now();
+    if req.expires_at <= now {
+        return Err("WAIVER_ALREADY_EXPIRED".into());
+    }
+    if !matches!(prop.status, ProposalStatus::Pending | ProposalStatus::ChangesRequested) {
+        return Err(format!("PROPOSAL_NOT_OPEN:{}", prop.status.name()));
+    }
+    not_creator(prop, reviewer, "SELF_WAIVER_NOT_ALLOWED")?;
+    let check = prop.checks.iter().find(|c| c.step == req.step).ok_or("CHECK_NOT_FOUND")?;
+    if check.passed {
+        return Err("CHECK_NOT_FAILED".into());
+    }
+    if prop.waivers.iter().any(|w| w.step == req.step && w.active(now)) {
+        return Err("CHECK_ALREADY_WAIVED".into());
+    }
+    let waiver = Waiver {
+        id: Uuid::new_v4(),
+        step: req.step,
+        check: check.check.clone(),
+        reviewer: reviewer.into(),
+        justification: justification.into(),
+        created_at: now,
+        expires_at: req.expires_at,
+    };
+    let comment = format!("waived {} (step {}) until {}: {justification}", waiver.check, waiver.step, waiver.expires_at.to_rfc3339());
+    prop.history.push(event(reviewer, "waived", Some(prop.status), prop.status, Some(comment)));
+    prop.waivers.push(waiver.clone());
+    Ok(waiver)
+}
+
+// Marks an approved proposal published and returns the approving comment.
+pub fn publish(prop: &mut ApiProposal, actor: &str) -> Result<Option<String>, String> {
+    if prop.status != ProposalStatus::Approved {
+        return Err("PROPOSAL_NOT_APPROVED".into());
+    }
+    let note = prop.history.iter().rev().find(|e| e.action == "approved").and_then(|e| e.comment.clone());
+    prop.status = ProposalStatus::Published;
+    prop.history.push(event(actor, "published", Some(ProposalStatus::Approved), ProposalStatus::Published, None));
+    Ok(note)
+}
+
+pub fn event(actor: &str, action: &str, from: Option<ProposalStatus>, to: ProposalStatus, comment: Option<String>) -> ReviewEvent {
+    ReviewEvent { at: Utc::now(), actor: actor.into(), action: action.into(), from, to, comment }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    // a failing proposal has failed the coverage check at step 1
+    fn proposal(pass: bool) -> ApiProposal {
+        let checks = [("map", true), ("coverage", pass)].iter().enumerate()
+            .map(|(step, (check, passed))| json!({"step": step, "check": check, "thresholds": {}, "passed": passed, "errors": 0, "warnings": 0}))
+            .collect::<Vec<_>>();
+        serde_json::from_value(json!({
+            "created_by": "alice", "dataset_id": Uuid::new_v4(), "model_profile_id": Uuid::new_v4(),
+            "sample": [], "coverage": [], "checks": checks, "pass": pass, "human_note_required": false,
+        })).unwrap()
+    }
+
+    fn act(prop: &mut ApiProposal, reviewer: &str, action: &str, comment: Option<&str>) -> Result<(), String> {
+        let req = serde_json::from_value(json!({"reviewer": reviewer, "action": action, "comment": comment})).unwrap();
+        review(prop, req)
+    }
+
+    #[test]
+    fn decisions_move_pending_proposals_only() {
+        let mut prop = proposal(true);
+        act(&mut prop, "bob", "request_changes", Some("rename ts")).unwrap();
+        assert_eq!(prop.status.name(), "changes_requested");
+        assert_eq!(act(&mut prop, "bob", "approve", None), Err("INVALID_TRANSITION:changes_requested->approved".into()));
+        act(&mut prop, "alice", "resubmit", None).unwrap();
+        act(&mut prop, "bob", "approve", None).unwrap();
+        assert_eq!(prop.status.name(), "approved");
+        assert_eq!(act(&mut prop, "bob", "reject", Some("no")), Err("INVALID_TRANSITION:approved->rejected".into()));
+        assert_eq!(act(&mut prop, "alice", "resubmit", None), Err("INVALID_TRANSITION:approved->resubmitted".into()));
+        let actions: Vec<&str> = prop.history.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, vec!["changes_requested", "resubmitted", "approved"]);
+    }
+
+    #[test]
+    fn comments_keep_the_status_and_need_text() {
+        let mut prop = proposal(true);
+        act(&mut prop, "bob", "reject", Some("bad data")).unwrap();
+        act(&mut prop, "carol", "comment", Some("agreed")).unwrap();
+        assert_eq!(prop.status.name(), "rejected");
+        assert_eq!(act(&mut prop, "carol", "comment", Some("   ")), Err("COMMENT_REQUIRED".into()));
+        assert_eq!(act(&mut proposal(true), "bob", "reject", None), Err("COMMENT_REQUIRED".into()));
+        assert_eq!(act(&mut proposal(true), "  ", "approve", None), Err("REVIEWER_REQUIRED".into()));
+    }
+
+    #[test]
+    fn approval_needs_another_reviewer_passing_evals_and_a_note_when_asked() {
+        assert_eq!(act(&mut proposal(true), "ALICE", "approve", None), Err("SELF_APPROVAL_NOT_ALLOWED".into()));
+        let mut anonymous = proposal(true);
+        anonymous.created_by = None;
+        assert_eq!(act(&mut anonymous, "bob", "approve", None), Err("PROPOSAL_CREATOR_UNKNOWN".into()));
+        assert_eq!(act(&mut proposal(false), "bob", "approve", None), Err("EVALS_NOT_PASSED".into()));
+        let mut noted = proposal(true);
+        noted.human_note_required = true;
+        assert_eq!(act(&mut noted, "bob", "approve", None), Err("HUMAN_NOTE_REQUIRED".into()));
+        act(&mut noted, "bob", "approve", Some("checked the sample")).unwrap();
+    }
+
+    #[test]
+    fn publish_takes_approved_proposals_and_returns_the_approval_note() {
+        let mut prop = proposal(true);
+        assert_eq!(publish(&mut prop, "bob"), Err("PROPOSAL_NOT_APPROVED".into()));
+        act(&mut prop, "bob", "approve", Some("ship it")).unwrap();
+        assert_eq!(publish(&mut prop, "bob"), Ok(Some("ship it".into())));
+        assert_eq!(prop.status.name(), "published");
+        assert_eq!(publish(&mut prop, "bob"), Err("PROPOSAL_NOT_APPROVED".into()));
+    }
+
+    fn waiver(reviewer: &str, step: usize, justification: &str, expires_in: chrono::Duration) -> WaiverCreate {
+        WaiverCreate { reviewer: reviewer.into(), step, justification: justification.into(), expires_at: Utc::now() + expires_in }
+    }
+
+    #[test]
+    fn an_active_waiver_on_every_failed_check_lets_approval_through() {
+        let mut prop = proposal(false);
+        assert!(!passes(&prop));
+        let w = waive(&mut prop, waiver("bob", 1, " known sparse feed ", chrono::Duration::days(1))).unwrap();
+        assert_eq!((w.check.as_str(), w.justification.as_str()), ("coverage", "known sparse feed"));
+        assert!(passes(&prop));
+        assert_eq!(prop.history.last().map(|e| e.action.as_str()), Some("waived"));
+        act(&mut prop, "carol", "approve", None).unwrap();
+    }
+
+    #[test]
+    fn expired_waivers_stop_counting() {
+        let mut prop = proposal(false);
+        waive(&mut prop, waiver("bob", 1, "temporary", chrono::Duration::days(1))).unwrap();
+        prop.waivers[0].expires_at = Utc::now() - chrono::Duration::seconds(1);
+        assert!(!passes(&prop));
+        // once expired, the check can be waived again
+        waive(&mut prop, waiver("bob", 1, "renewed", chrono::Duration::days(1))).unwrap();
+        assert!(passes(&prop));
+    }
+
+    #[test]
+    fn waivers_are_checked_before_they_are_taken() {
+        let day = chrono::Duration::days(1);
+        let mut prop = proposal(false);
+        assert_eq!(waive(&mut prop, waiver(" ", 1, "x", day)).err(), Some("REVIEWER_REQUIRED".into()));
+        assert_eq!(waive(&mut prop, waiver("bob", 1, " ", day)).err(), Some("JUSTIFICATION_REQUIRED".into()));
+        assert_eq!(waive(&mut prop, waiver("bob", 1, "x", -day)).err(), Some("WAIVER_ALREADY_EXPIRED".into()));
+        assert_eq!(waive(&mut prop, waiver("Alice", 1, "x", day)).err(), Some("SELF_WAIVER_NOT_ALLOWED".into()));
+        assert_eq!(waive(&mut prop, waiver("bob", 7, "x", day)).err(), Some("CHECK_NOT_FOUND".into()));
+        assert_eq!(waive(&mut prop, waiver("bob", 0, "x", day)).err(), Some("CHECK_NOT_FAILED".into()));
+        waive(&mut prop, waiver("bob", 1, "x", day)).unwrap();
+        assert_eq!(waive(&mut prop, waiver("carol", 1, "x", day)).err(), Some("CHECK_ALREADY_WAIVED".into()));
+        act(&mut prop, "bob", "reject", Some("no")).unwrap();
+        assert_eq!(waive(&mut prop, waiver("bob", 1, "x", day)).err(), Some("PROPOSAL_NOT_OPEN:rejected".into()));
+    }
+}
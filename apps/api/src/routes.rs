@@ -1,10 +1,32 @@
-use axum::{routing::{get, post}, Router, extract::{Path, State}, Json};
+use axum::{routing::{get, post}, Router, extract::{Path, Query, State}, Json};
+use axum::http::{HeaderMap, StatusCode};
+use axum::body::Bytes;
+use axum::response::{IntoResponse, Response};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::extract::ws::{Message, WebSocketUpgrade};
 use axum_extra::extract::Multipart;
 use serde::Deserialize;
 use uuid::Uuid;
+use std::collections::HashMap;
+use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use crate::state::{AppState, FileInfo};
 use crate::models::*;
+use crate::formats::{self, Encoder, ResponseFormat};
+use crate::query::{self, NumberRange, RowFilter};
+use crate::table::{ChunkedTable, Table};
+use crate::pipeline;
+use crate::jobs;
+use crate::suggest;
+use crate::evals;
+use crate::spec;
+use crate::review;
+use crate::datasets;
+use crate::versions;
+use crate::lifecycle;
+use crate::realtime::{self, Push, Subscription};
+use crate::error::ApiError;
+use crate::analysis;
 
 pub fn app(state: AppState) -> Router {
     Router::new()
@@ -16,11 +38,29 @@ pub fn app(state: AppState) -> Router {
         // Datasets
         .route("/api/datasets", post(create_dataset).get(list_datasets))
         .route("/api/datasets/:id/preview", get(preview_dataset))
+        .route("/api/datasets/:id/rows", post(append_rows))
+        .route("/api/datasets/:id/versions", post(change_dataset).get(list_dataset_versions))
+        .route("/api/datasets/:id/diff", get(diff_dataset))
         // Pipelines
-        .route("/api/pipelines", post(run_pipeline))
+        .route("/api/mappings/suggest", post(suggest_mappings))
+        .route("/api/pipelines", post(run_pipeline).get(list_jobs))
+        .route("/api/pipelines/:id", get(get_job))
+        .route("/api/pipelines/:id/cancel", post(cancel_job))
         // APIs (published products)
+        .route("/api/proposals", get(list_proposals))
+        .route("/api/proposals/:id", get(get_proposal))
+        .route("/api/proposals/:id/reviews", post(review_proposal))
+        .route("/api/proposals/:id/waivers", post(waive_check))
         .route("/api/apis", get(list_apis).post(create_api))
+        .route("/api/apis/:id/openapi", get(api_openapi))
+        .route("/api/apis/:id/status", post(change_status))
+        .route("/api/apis/:id/versions", post(create_version))
+        .route("/api/apis/:id/versions/:version/deprecate", post(deprecate_version))
         .route("/v1/data/:api_id/query", post(query_api))
+        .route("/v1/data/:api_id/stream", get(stream_sse))
+        .route("/v1/data/:api_id/ws", get(stream_ws))
+        // Consumer keys
+        .route("/api/keys", post(create_key).get(list_keys))
         // New file upload and OpenAI endpoints
         .route("/api/upload", post(upload_file))
         .route("/api/analyze", post(analyze_with_openai))
@@ -32,7 +72,7 @@ pub fn app(state: AppState) -> Router {
 async fn health() -> &'static str { "ok" }
 
 async fn create_provider(
-    State(mut st): State<AppState>,
+    State(st): State<AppState>,
     Json(req): Json<ProviderCreate>
 ) -> Json<Provider> {
     let provider = Provider {
@@ -49,19 +89,26 @@ async fn list_providers(State(st): State<AppState>) -> Json<Vec<Provider>> {
 }
 
 async fn create_model(
-    State(mut st): State<AppState>,
+    State(st): State<AppState>,
     Json(req): Json<ModelProfileCreate>
-) -> Json<ModelProfile> {
+) -> Result<Json<ModelProfile>, ApiError> {
+    for feat in req.features.iter() {
+        evals::check_spec(feat).map_err(|e| ApiError::bad_request(format!("INVALID_FEATURE_SPEC:{e}")))?;
+    }
+    if let Some(k) = req.primary_key.iter().find(|k| !req.features.iter().any(|f| &f.name == *k)) {
+        return Err(ApiError::bad_request(format!("INVALID_PRIMARY_KEY:{k}")));
+    }
     let profile = ModelProfile {
         id: Uuid::new_v4(),
         name: req.name,
         version: req.version,
         description: req.description,
-        // minimal feature schema: name + dtype
+        // name + dtype, plus optional constraints
         features: req.features,
+        primary_key: req.primary_key,
     };
     st.store.models.insert(profile.id, profile.clone());
-    Json(profile)
+    Ok(Json(profile))
 }
 
 async fn list_models(State(st): State<AppState>) -> Json<Vec<ModelProfile>> {
@@ -69,18 +116,13 @@ async fn list_models(State(st): State<AppState>) -> Json<Vec<ModelProfile>> {
 }
 
 async fn create_dataset(
-    State(mut st): State<AppState>,
+    State(st): State<AppState>,
     Json(req): Json<DatasetCreate>
-) -> Json<Dataset> {
-    let ds = Dataset {
-        id: Uuid::new_v4(),
-        provider_id: req.provider_id,
-        name: req.name,
-        description: req.description,
-        rows: req.rows,
-    };
+) -> Result<Json<Dataset>, ApiError> {
+    let ds = datasets::create(req.provider_id, req.name, req.description, &req.rows, req.primary_key)
+        .map_err(ApiError::bad_request)?;
     st.store.datasets.insert(ds.id, ds.clone());
-    Json(ds)
+    Ok(Json(ds))
 }
 
 async fn list_datasets(State(st): State<AppState>) -> Json<Vec<Dataset>> {
@@ -92,97 +134,289 @@ async fn preview_dataset(
     Path(id): Path<Uuid>,
 ) -> Json<Vec<serde_json::Value>> {
     let Some(ds) = st.store.datasets.get(&id) else { return Json(vec![])};
-    let preview: Vec<_> = ds.rows.iter().take(5).cloned().collect();
+    let table = ds.rows.table();
+    let preview: Vec<_> = (0..table.len().min(5)).map(|i| table.row_dense(i)).collect();
     Json(preview)
 }
 
-// Run pipeline = validate → map → evaluate → publish proposal
-async fn run_pipeline(
-    State(mut st): State<AppState>,
-    Json(req): Json<PipelineRunRequest>
-) -> Json<PipelineRunResult> {
-    // Fetch components
-    let Some(ds) = st.store.datasets.get(&req.dataset_id) else {
-        return Json(PipelineRunResult::error("DATASET_NOT_FOUND"))
+// Appends rows to a dataset, upserting by its primary key if it has one, and
+// pushes the rows written to real-time subscribers
+async fn append_rows(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<DatasetAppend>,
+) -> Result<Json<DatasetAppendResult>, ApiError> {
+    let (v, written) = {
+        let Some(mut ds) = st.store.datasets.get_mut(&id) else {
+            return Err(ApiError::not_found("DATASET_NOT_FOUND"));
+        };
+        datasets::append(&mut ds, &req.rows).map_err(ApiError::bad_request)?
     };
-    let Some(mp) = st.store.models.get(&req.model_profile_id) else {
-        return Json(PipelineRunResult::error("MODEL_PROFILE_NOT_FOUND"))
+    if !written.is_empty() {
+        realtime::publish(&st.store, id, Arc::new(Table::from_rows(&written)));
+    }
+    Ok(Json(DatasetAppendResult {
+        dataset_id: id,
+        appended: written.len(),
+        total_rows: v.row_count,
+        version: v.version,
+        counts: v.ingested.unwrap_or_default(),
+    }))
+}
+
+// Appends, replaces or upserts rows as a new dataset version. Appended and
+// upserted rows go out to real-time subscribers; a replace does not.
+async fn change_dataset(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<DatasetChange>,
+) -> Result<Json<DatasetVersion>, ApiError> {
+    let (v, pushed) = {
+        let mut ds = st.store.datasets.get_mut(&id).ok_or(ApiError::not_found("DATASET_NOT_FOUND"))?;
+        match req.mode {
+            ChangeMode::Append => datasets::append(&mut ds, &req.rows),
+            ChangeMode::Replace => datasets::replace(&mut ds, &req.rows, &req.key).map(|v| (v, vec![])),
+            ChangeMode::Upsert => datasets::upsert(&mut ds, &req.rows, &req.key),
+        }.map_err(ApiError::bad_request)?
     };
-    // Map: select features
-    let mut mapped: Vec<serde_json::Value> = Vec::new();
-    for row in ds.rows.iter() {
-        let mut out = serde_json::Map::new();
-        for feat in mp.features.iter() {
-            if let Some(v) = row.get(&feat.name) {
-                out.insert(feat.name.clone(), v.clone());
-            } else {
-                // simple impute: null
-                out.insert(feat.name.clone(), serde_json::Value::Null);
-            }
+    if !pushed.is_empty() {
+        realtime::publish(&st.store, id, Arc::new(Table::from_rows(&pushed)));
+    }
+    Ok(Json(v))
+}
+
+async fn list_dataset_versions(State(st): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<Vec<DatasetVersion>>, ApiError> {
+    st.store.datasets.get(&id).map(|ds| Json(ds.versions.clone())).ok_or(ApiError::not_found("DATASET_NOT_FOUND"))
+}
+
+async fn diff_dataset(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(q): Query<DiffQuery>,
+) -> Result<Json<DatasetDiff>, ApiError> {
+    let (from, to) = {
+        let ds = st.store.datasets.get(&id).ok_or(ApiError::not_found("DATASET_NOT_FOUND"))?;
+        let to = q.to.unwrap_or(ds.version);
+        let from = q.from.unwrap_or(to.saturating_sub(1));
+        // version 0 is the empty dataset before the first version, so v1 diffs as all added
+        let version = |n: u32| match n {
+            0 => Ok((0, ChunkedTable::default())),
+            n => ds.at(n).map(|v| (v.version, v.rows.clone())).ok_or(ApiError::not_found("DATASET_VERSION_NOT_FOUND")),
+        };
+        (version(from)?, version(to)?)
+    };
+    let key: Vec<String> = q.key.iter().flat_map(|k| k.split(',')).map(|k| k.trim().to_string()).filter(|k| !k.is_empty()).collect();
+    // the comparison materializes both versions, so keep it off the async workers
+    let (rows_a, rows_b, k) = (from.1, to.1, key.clone());
+    let d = tokio::task::spawn_blocking(move || datasets::diff(&rows_a.table(), &rows_b.table(), &k)).await
+        .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "DIFF_FAILED"))?;
+    Ok(Json(DatasetDiff {
+        dataset_id: id,
+        from: from.0,
+        to: to.0,
+        key,
+        summary: d.summary,
+        added: d.added,
+        removed: d.removed,
+        changed: d.changed,
+    }))
+}
+
+// Proposes column → feature matches for a dataset; the returned mappings can be
+// pasted into a map step, or the step can set `auto: true`.
+async fn suggest_mappings(
+    State(st): State<AppState>,
+    Json(req): Json<MappingSuggestRequest>,
+) -> Result<Json<MappingSuggestion>, ApiError> {
+    let min = req.min_confidence.unwrap_or(suggest::DEFAULT_MIN_CONFIDENCE);
+    if !(0.0..=1.0).contains(&min) {
+        return Err(ApiError::bad_request("INVALID_MIN_CONFIDENCE"));
+    }
+    let profile = st.store.models.get(&req.model_profile_id).map(|m| m.clone())
+        .ok_or(ApiError::not_found("MODEL_PROFILE_NOT_FOUND"))?;
+    let table = st.store.datasets.get(&req.dataset_id).map(|d| d.rows.clone())
+        .ok_or(ApiError::not_found("DATASET_NOT_FOUND"))?;
+    Ok(Json(suggest::suggest(req.dataset_id, &profile, &table.table(), min)))
+}
+
+// Run pipeline = validate → queue for a worker → poll the job for step progress
+async fn run_pipeline(
+    State(st): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<PipelineJob>), ApiError> {
+    let def = pipeline::parse_definition(&headers, &body).map_err(ApiError::bad_request)?;
+    match jobs::enqueue(&st, def) {
+        Ok(job) => Ok((StatusCode::ACCEPTED, Json(job))),
+        Err(e) if e == "PIPELINE_QUEUE_FULL" => Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, e)),
+        Err(e) => Err(ApiError::bad_request(e)),
+    }
+}
+
+async fn list_jobs(State(st): State<AppState>) -> Json<Vec<PipelineJob>> {
+    let mut jobs: Vec<PipelineJob> = st.store.jobs.iter().map(|j| j.clone()).collect();
+    jobs.sort_by_key(|j| j.created_at);
+    Json(jobs)
+}
+
+async fn get_job(State(st): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<PipelineJob>, ApiError> {
+    st.store.jobs.get(&id).map(|j| Json(j.clone())).ok_or(ApiError::not_found("JOB_NOT_FOUND"))
+}
+
+async fn cancel_job(State(st): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<PipelineJob>, ApiError> {
+    match jobs::cancel(&st.store, id) {
+        Ok(job) => Ok(Json(job)),
+        Err(e) if e == "JOB_NOT_FOUND" => Err(ApiError::not_found(e)),
+        Err(e) => Err(ApiError::new(StatusCode::CONFLICT, e)),
+    }
+}
+
+async fn list_proposals(State(st): State<AppState>, Query(q): Query<ProposalListQuery>) -> Json<Vec<ApiProposal>> {
+    Json(st.store.proposals.iter()
+        .filter(|kv| q.status.is_none_or(|s| kv.value().status == s))
+        .map(|kv| kv.value().clone())
+        .collect())
+}
+
+async fn get_proposal(State(st): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<ApiProposal>, ApiError> {
+    st.store.proposals.get(&id).map(|p| Json(p.clone())).ok_or(ApiError::not_found("PROPOSAL_NOT_FOUND"))
+}
+
+async fn review_proposal(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<ReviewRequest>,
+) -> Result<Json<ApiProposal>, ApiError> {
+    let mut prop = st.store.proposals.get_mut(&id).ok_or(ApiError::not_found("PROPOSAL_NOT_FOUND"))?;
+    match review::review(&mut prop, req) {
+        Ok(()) => Ok(Json(prop.clone())),
+        Err(e) if e == "SELF_APPROVAL_NOT_ALLOWED" => Err(ApiError::new(StatusCode::FORBIDDEN, e)),
+        Err(e) if e.starts_with("INVALID_TRANSITION") || e == "EVALS_NOT_PASSED" || e == "PROPOSAL_CREATOR_UNKNOWN" => {
+            Err(ApiError::new(StatusCode::CONFLICT, e))
         }
-        mapped.push(serde_json::Value::Object(out));
+        Err(e) => Err(ApiError::bad_request(e)),
     }
-    // Evaluate: basic coverage stats
-    let total = mapped.len() as f64;
-    let mut coverage: Vec<FeatureCoverage> = Vec::new();
-    for feat in mp.features.iter() {
-        let mut non_null = 0.0;
-        for row in mapped.iter() {
-            if row.get(&feat.name).and_then(|v| if v.is_null(){None}else{Some(v)}).is_some() {
-                non_null += 1.0;
-            }
+}
+
+async fn waive_check(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<WaiverCreate>,
+) -> Result<Json<Waiver>, ApiError> {
+    let mut prop = st.store.proposals.get_mut(&id).ok_or(ApiError::not_found("PROPOSAL_NOT_FOUND"))?;
+    match review::waive(&mut prop, req) {
+        Ok(w) => Ok(Json(w)),
+        Err(e) if e == "SELF_WAIVER_NOT_ALLOWED" => Err(ApiError::new(StatusCode::FORBIDDEN, e)),
+        Err(e) if e == "CHECK_NOT_FOUND" => Err(ApiError::not_found(e)),
+        Err(e) if e.starts_with("PROPOSAL_NOT_OPEN") || e == "CHECK_NOT_FAILED" || e == "CHECK_ALREADY_WAIVED" || e == "PROPOSAL_CREATOR_UNKNOWN" => {
+            Err(ApiError::new(StatusCode::CONFLICT, e))
         }
-        coverage.push(FeatureCoverage { name: feat.name.clone(), coverage: if total>0.0 { non_null/total } else {0.0} });
+        Err(e) => Err(ApiError::bad_request(e)),
     }
-    let pass = coverage.iter().all(|c| c.coverage >= req.min_coverage);
-    // Create proposal
-    let prop = ApiProposal {
-        dataset_id: ds.id,
-        model_profile_id: mp.id,
-        sample: mapped.iter().take(3).cloned().collect(),
-        coverage,
-        pass,
-        human_note_required: true,
-    };
-    // Store temp proposal
-    let prop_id = Uuid::new_v4();
-    st.store.proposals.insert(prop_id, prop.clone());
-    Json(PipelineRunResult::ok(prop_id, prop))
 }
 
 async fn create_api(
-    State(mut st): State<AppState>,
+    State(st): State<AppState>,
     Json(req): Json<ApiCreate>
-) -> Json<ApiProduct> {
-    // requires an approved proposal
-    let Some(prop) = st.store.proposals.get(&req.proposal_id) else {
-        return Json(ApiProduct::error("PROPOSAL_NOT_FOUND"))
-    };
-    if req.human_approval_note.trim().is_empty() { 
-        return Json(ApiProduct::error("HUMAN_NOTE_REQUIRED"))
-    }
-    if !prop.pass { 
-        return Json(ApiProduct::error("EVALS_NOT_PASSED"))
+) -> Result<Json<ApiProduct>, ApiError> {
+    let status = req.status.unwrap_or(ApiStatus::Live);
+    if !matches!(status, ApiStatus::Draft | ApiStatus::Live) {
+        return Err(ApiError::bad_request("INVALID_INITIAL_STATUS"));
     }
-    let api = ApiProduct {
+    // requires an approved proposal; publishing it freezes the dataset as v1
+    let actor = req.published_by.clone().unwrap_or_else(|| req.provider_id.to_string());
+    let v = versions::publish(&st.store, req.proposal_id, req.provider_id, None, &actor, &req.human_approval_note)
+        .map_err(version_error)?;
+    let mut api = ApiProduct {
         id: Uuid::new_v4(),
         name: req.name,
         pricing: req.pricing,
         provider_id: req.provider_id,
-        dataset_id: prop.dataset_id,
-        model_profile_id: prop.model_profile_id,
-        version: "v1".into(),
-        status: "live".into(),
-        human_approval_note: req.human_approval_note,
+        proposal_id: v.proposal_id,
+        dataset_id: v.dataset_id,
+        model_profile_id: v.model_profile_id,
+        version: String::new(),
+        status,
+        sunset_at: None,
+        lifecycle: vec![lifecycle::event(None, status, None)],
+        human_approval_note: String::new(),
+        key_tier: req.key_tier,
+        quarantined_rows: vec![],
+        dropped_rows: vec![],
+        waivers: vec![],
+        versions: vec![],
     };
+    versions::add(&mut api, v);
     st.store.apis.insert(api.id, api.clone());
-    Json(api)
+    Ok(Json(api))
+}
+
+async fn change_status(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<ApiStatusChange>,
+) -> Result<Json<ApiProduct>, ApiError> {
+    let mut api = st.store.apis.get_mut(&id).ok_or(ApiError::not_found("API_NOT_FOUND"))?;
+    match lifecycle::transition(&mut api, req) {
+        Ok(()) => Ok(Json(api.clone())),
+        Err(e) if e == "PROVIDER_MISMATCH" => Err(ApiError::new(StatusCode::FORBIDDEN, e)),
+        Err(e) if e.starts_with("INVALID_TRANSITION") => Err(ApiError::new(StatusCode::CONFLICT, e)),
+        Err(e) => Err(ApiError::bad_request(e)),
+    }
 }
 
 async fn list_apis(State(st): State<AppState>) -> Json<Vec<ApiProduct>> {
     Json(st.store.apis.iter().map(|kv| kv.value().clone()).collect())
 }
 
+fn version_error(e: String) -> ApiError {
+    match e.as_str() {
+        "API_NOT_FOUND" | "PROPOSAL_NOT_FOUND" | "VERSION_NOT_FOUND" | "MODEL_PROFILE_NOT_FOUND" | "DATASET_NOT_FOUND" => ApiError::not_found(e),
+        "PROVIDER_MISMATCH" | "DATASET_PROVIDER_MISMATCH" => ApiError::new(StatusCode::FORBIDDEN, e),
+        "API_RETIRED" => ApiError::new(StatusCode::GONE, e),
+        _ if e.starts_with("VERSION_DEPRECATED") => ApiError::new(StatusCode::GONE, e),
+        _ => ApiError::new(StatusCode::CONFLICT, e),
+    }
+}
+
+// Publishes another approved proposal as the product's next version
+async fn create_version(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<ApiVersionCreate>,
+) -> Result<Json<ApiProduct>, ApiError> {
+    let api = st.store.apis.get(&id).map(|a| a.clone()).ok_or(ApiError::not_found("API_NOT_FOUND"))?;
+    let actor = req.published_by.clone().unwrap_or_else(|| req.provider_id.to_string());
+    let v = versions::publish(&st.store, req.proposal_id, req.provider_id, Some(&api), &actor, &req.human_approval_note)
+        .map_err(version_error)?;
+    let mut api = st.store.apis.get_mut(&id).ok_or(ApiError::not_found("API_NOT_FOUND"))?;
+    versions::add(&mut api, v);
+    Ok(Json(api.clone()))
+}
+
+async fn deprecate_version(
+    State(st): State<AppState>,
+    Path((id, version)): Path<(Uuid, String)>,
+    Json(req): Json<ApiVersionDeprecate>,
+) -> Result<Json<ApiProduct>, ApiError> {
+    let mut api = st.store.apis.get_mut(&id).ok_or(ApiError::not_found("API_NOT_FOUND"))?;
+    versions::deprecate(&mut api, &version, req.provider_id).map_err(version_error)?;
+    Ok(Json(api.clone()))
+}
+
+async fn api_openapi(
+    State(st): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(param): Query<VersionParam>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let mut api = st.store.apis.get(&id).map(|a| a.clone()).ok_or(ApiError::not_found("API_NOT_FOUND"))?;
+    let v = versions::resolve(&api, param.version.as_deref()).map_err(version_error)?.clone();
+    api.version = v.version;
+    let profile = st.store.models.get(&v.model_profile_id).map(|m| m.clone())
+        .ok_or(ApiError::not_found("MODEL_PROFILE_NOT_FOUND"))?;
+    Ok(Json(spec::openapi(&api, &profile)))
+}
+
 // Consumer query endpoint: filters mapped rows by simple params
 #[derive(Deserialize)]
 struct QueryReq {
@@ -191,47 +425,194 @@ struct QueryReq {
     start: Option<chrono::DateTime<chrono::Utc>>,
     end: Option<chrono::DateTime<chrono::Utc>>,
     limit: Option<usize>,
+    // inclusive bounds on numeric features, e.g. {"price": {"min": 100}}
+    #[serde(default)]
+    ranges: HashMap<String, NumberRange>,
+    // json | ndjson | csv | arrow; overrides the Accept header
+    format: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FormatParam {
+    format: Option<String>,
 }
 
 async fn query_api(
     State(st): State<AppState>,
     Path(api_id): Path<Uuid>,
+    Query(param): Query<FormatParam>,
+    Query(pin): Query<VersionParam>,
+    headers: HeaderMap,
     Json(req): Json<QueryReq>
-) -> Json<Vec<serde_json::Value>> {
-    let Some(api) = st.store.apis.get(&api_id) else { return Json(vec![]) };
-    let Some(ds) = st.store.datasets.get(&api.dataset_id) else { return Json(vec![]) };
-    // For demo: just filter symbol/time if fields exist
-    let mut out = Vec::new();
-    for row in ds.rows.iter() {
-        if let Some(sym) = req.symbol.as_ref() {
-            if let Some(v) = row.get("symbol") {
-                if v != sym { continue; }
-            }
-        }
-        // naive time filter (expects ISO in field "ts")
-        if let (Some(start), Some(v)) = (req.start, row.get("ts")) {
-            if let Some(ts) = v.as_str() {
-                if let Ok(t) = ts.parse::<chrono::DateTime<chrono::Utc>>() {
-                    if t < start { continue; }
-                }
-            }
+) -> Response {
+    let requested = param.format.as_deref().or(req.format.as_deref());
+    let format = match ResponseFormat::negotiate(requested, &headers) {
+        Ok(f) => f,
+        Err(code) => return ApiError::new(StatusCode::NOT_ACCEPTABLE, code).into_response(),
+    };
+    // Release the map guards before streaming; the scan only needs the version's snapshot.
+    let (version, lifecycle_headers, features, snapshot) = {
+        let Some(api) = st.store.apis.get(&api_id) else { return empty_result(format) };
+        if let Err(e) = lifecycle::serving(&api, chrono::Utc::now()) {
+            return e.into_response();
         }
-        if let (Some(end), Some(v)) = (req.end, row.get("ts")) {
-            if let Some(ts) = v.as_str() {
-                if let Ok(t) = ts.parse::<chrono::DateTime<chrono::Utc>>() {
-                    if t > end { continue; }
+        let v = match versions::resolve(&api, pin.version.as_deref()) {
+            Ok(v) => v,
+            Err(e) => return version_error(e).into_response(),
+        };
+        let features = st.store.models.get(&v.model_profile_id).map(|mp| mp.features.clone());
+        (v.version.clone(), lifecycle::headers(&api), features, v.snapshot.clone())
+    };
+    let columns = formats::columns_for(features.as_deref(), &snapshot.rows);
+    let encoder = match Encoder::new(format, columns) {
+        Ok(e) => e,
+        Err(e) => return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("ENCODE_FAILED:{e}")).into_response(),
+    };
+    let filter = RowFilter { symbol: req.symbol, start: req.start, end: req.end, ranges: req.ranges };
+    let mut res = formats::respond(format, query::stream_rows(snapshot.rows, snapshot.indexes, filter, req.limit, encoder));
+    if let Ok(v) = version.parse() {
+        res.headers_mut().insert("x-api-version", v);
+    }
+    res.headers_mut().extend(lifecycle_headers);
+    res
+}
+
+fn empty_result(format: ResponseFormat) -> Response {
+    let encoded = Encoder::new(format, vec![]).and_then(|mut enc| {
+        let mut out = enc.begin()?;
+        out.extend(enc.finish()?);
+        Ok(out)
+    });
+    match encoded {
+        Ok(bytes) => formats::respond(format, bytes.into()),
+        Err(e) => ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("ENCODE_FAILED:{e}")).into_response(),
+    }
+}
+
+async fn create_key(
+    State(st): State<AppState>,
+    Json(req): Json<ConsumerKeyCreate>,
+) -> Result<Json<ConsumerKey>, ApiError> {
+    let (provider_id, status, tier) = st.store.apis.get(&req.api_id)
+        .map(|a| (a.provider_id, a.status, a.key_tier))
+        .ok_or(ApiError::not_found("API_NOT_FOUND"))?;
+    if req.provider_id != provider_id {
+        return Err(ApiError::new(StatusCode::FORBIDDEN, "PROVIDER_MISMATCH"));
+    }
+    if status == ApiStatus::Retired {
+        return Err(ApiError::new(StatusCode::GONE, "API_RETIRED"));
+    }
+    let key = ConsumerKey {
+        key: format!("mk_{}", Uuid::new_v4().simple()),
+        api_id: req.api_id,
+        name: req.name,
+        tier,
+        created_at: chrono::Utc::now(),
+    };
+    st.store.keys.insert(key.key.clone(), key.clone());
+    Ok(Json(key))
+}
+
+async fn list_keys(State(st): State<AppState>) -> Json<Vec<ConsumerKey>> {
+    Json(st.store.keys.iter().map(|kv| kv.value().clone()).collect())
+}
+
+// Real-time subscriptions take the query filter as query-string parameters
+#[derive(Deserialize)]
+struct StreamParams {
+    api_key: Option<String>,
+    // `latest` when absent, as for queries
+    version: Option<String>,
+    symbol: Option<String>,
+    start: Option<chrono::DateTime<chrono::Utc>>,
+    end: Option<chrono::DateTime<chrono::Utc>>,
+    // JSON object in the same shape as the query body's `ranges`
+    ranges: Option<String>,
+}
+
+impl StreamParams {
+    fn filter(&self) -> Result<RowFilter, ApiError> {
+        let ranges = match self.ranges.as_deref() {
+            Some(r) => serde_json::from_str(r).map_err(|e| ApiError::bad_request(format!("INVALID_RANGES:{e}")))?,
+            None => HashMap::new(),
+        };
+        Ok(RowFilter { symbol: self.symbol.clone(), start: self.start, end: self.end, ranges })
+    }
+}
+
+fn open_subscription(
+    st: &AppState,
+    api_id: Uuid,
+    headers: &HeaderMap,
+    params: &StreamParams,
+) -> Result<Subscription, ApiError> {
+    let filter = params.filter()?;
+    let key = realtime::api_key(headers, params.api_key.as_deref());
+    realtime::subscribe(st, api_id, key, params.version.as_deref(), filter)
+}
+
+fn push_event(push: Push) -> Event {
+    match push {
+        Push::Rows(rows) => Event::default().event("rows").json_data(rows).unwrap_or_default(),
+        Push::Throttled(n) => Event::default().event("throttled").data(serde_json::json!({ "dropped": n }).to_string()),
+        Push::Lagged(n) => Event::default().event("lagged").data(serde_json::json!({ "missed_batches": n }).to_string()),
+        Push::Closed(code) => Event::default().event("closed").data(serde_json::json!({ "error": code }).to_string()),
+    }
+}
+
+async fn stream_sse(
+    State(st): State<AppState>,
+    Path(api_id): Path<Uuid>,
+    Query(params): Query<StreamParams>,
+    headers: HeaderMap,
+) -> Response {
+    let sub = match open_subscription(&st, api_id, &headers, &params) {
+        Ok(sub) => sub,
+        Err(e) => return e.into_response(),
+    };
+    let lifecycle_headers = st.store.apis.get(&api_id).map(|a| lifecycle::headers(&a)).unwrap_or_default();
+    let events = futures::stream::unfold(sub, |mut sub| async move {
+        sub.next().await.map(|push| (Ok::<_, std::convert::Infallible>(push_event(push)), sub))
+    });
+    (lifecycle_headers, Sse::new(events).keep_alive(KeepAlive::default())).into_response()
+}
+
+async fn stream_ws(
+    State(st): State<AppState>,
+    Path(api_id): Path<Uuid>,
+    Query(params): Query<StreamParams>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let mut sub = match open_subscription(&st, api_id, &headers, &params) {
+        Ok(sub) => sub,
+        Err(e) => return e.into_response(),
+    };
+    ws.on_upgrade(move |mut socket| async move {
+        loop {
+            tokio::select! {
+                push = sub.next() => {
+                    let Some(push) = push else { break };
+                    let msg = match push {
+                        Push::Rows(rows) => serde_json::json!({ "type": "rows", "rows": rows }),
+                        Push::Throttled(n) => serde_json::json!({ "type": "throttled", "dropped": n }),
+                        Push::Lagged(n) => serde_json::json!({ "type": "lagged", "missed_batches": n }),
+                        Push::Closed(code) => serde_json::json!({ "type": "closed", "error": code }),
+                    };
+                    if socket.send(Message::Text(msg.to_string())).await.is_err() { break; }
                 }
+                incoming = socket.recv() => match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => {}
+                },
             }
         }
-        out.append(&mut vec![row.clone()]);
-        if let Some(lim) = req.limit { if out.len() >= lim { break; } }
-    }
-    Json(out)
+    })
 }
 
 // New handler functions for file uploads and OpenAI integration
 async fn upload_file(
-    State(mut st): State<AppState>,
+    State(st): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<Json<FileUploadResponse>, String> {
     let file_id = Uuid::new_v4();
@@ -256,19 +637,16 @@ async fn upload_file(
     }
 
     let file_info = FileInfo {
-        id: file_id,
         filename: filename.clone(),
         file_size,
         file_type: file_type.clone(),
         content,
-        uploaded_at: chrono::Utc::now(),
     };
 
+    // what the file holds, without a model
+    let analysis = analysis::base(&file_info, analysis::profile(&file_info).as_ref());
     st.store.files.insert(file_id, file_info);
 
-    // Perform basic analysis
-    let analysis = analyze_file_content(&filename, &file_type, file_size).await;
-
     Ok(Json(FileUploadResponse {
         file_id,
         filename,
@@ -278,83 +656,14 @@ async fn upload_file(
     }))
 }
 
-async fn analyze_file_content(filename: &str, file_type: &str, file_size: u64) -> DirectoryAnalysis {
-    let file_types = vec![file_type.to_string()];
-    let data_patterns = detect_data_patterns(filename, file_type);
-    
-    DirectoryAnalysis {
-        path: format!("uploaded/{}", filename),
-        file_count: 1,
-        file_types,
-        total_size: format_file_size(file_size),
-        structure: serde_json::json!({
-            "root": {
-                "files": [{
-                    "name": filename,
-                    "size": file_size,
-                    "type": file_type
-                }]
-            }
-        }),
-        data_patterns,
-        suggested_api_structure: None,
-        best_model: None,
-        model_reasoning: None,
-    }
-}
-
-fn detect_data_patterns(filename: &str, file_type: &str) -> Vec<String> {
-    let mut patterns = Vec::new();
-    let name_lower = filename.to_lowercase();
-    
-    if name_lower.contains("csv") || file_type.contains("csv") {
-        patterns.push("Structured Data (CSV)".to_string());
-    }
-    if name_lower.contains("json") || file_type.contains("json") {
-        patterns.push("JSON Data".to_string());
-    }
-    if name_lower.contains("log") {
-        patterns.push("Log Files".to_string());
-    }
-    if name_lower.contains("config") || name_lower.contains("settings") {
-        patterns.push("Configuration Files".to_string());
-    }
-    if name_lower.contains("image") || name_lower.contains("photo") {
-        patterns.push("Image Data".to_string());
-    }
-    
-    if patterns.is_empty() {
-        patterns.push("Mixed Data Types".to_string());
-    }
-    
-    patterns
-}
-
-fn format_file_size(bytes: u64) -> String {
-    if bytes == 0 {
-        return "0 Bytes".to_string();
-    }
-    let k = 1024;
-    let sizes = ["Bytes", "KB", "MB", "GB"];
-    let i = (bytes as f64).log(k as f64).floor() as usize;
-    let i = i.min(sizes.len() - 1);
-    format!("{:.2} {}", bytes as f64 / (k as f64).powi(i as i32), sizes[i])
-}
-
 async fn analyze_with_openai(
     State(st): State<AppState>,
     Json(req): Json<OpenAIAnalysisRequest>,
-) -> Result<Json<OpenAIAnalysisResponse>, String> {
-    let file_info = st.store.files.get(&req.file_id)
-        .ok_or("File not found")?;
-
-    // Get OpenAI API key from environment
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OpenAI API key not configured")?;
-
-    // Call OpenAI API
-    let analysis = call_openai_analysis(&api_key, &file_info, req.model.as_deref()).await
-        .map_err(|e| format!("OpenAI analysis failed: {}", e))?;
+) -> Result<Json<OpenAIAnalysisResponse>, ApiError> {
+    let file_info = st.store.files.get(&req.file_id).map(|f| f.clone())
+        .ok_or(ApiError::not_found("FILE_NOT_FOUND"))?;
+    let budget = req.max_prompt_tokens.unwrap_or(analysis::DEFAULT_PROMPT_TOKENS);
+    let analysis = analysis::analyze(st.llm.as_ref(), &file_info, req.model, budget).await?;
 
     Ok(Json(OpenAIAnalysisResponse {
         analysis,
@@ -365,16 +674,10 @@ async fn analyze_with_openai(
 }
 
 async fn generate_api_specification(
-    State(_st): State<AppState>,
+    State(st): State<AppState>,
     Json(req): Json<ApiSpecificationRequest>,
-) -> Result<Json<ApiSpecificationResponse>, String> {
-    // Get OpenAI API key from environment
-    let api_key = std::env::var("OPENAI_API_KEY")
-        .map_err(|_| "OpenAI API key not configured")?;
-
-    // Call OpenAI API to generate specification
-    let specification = call_openai_generate_spec(&api_key, &req.analysis, req.model.as_deref()).await
-        .map_err(|e| format!("OpenAI specification generation failed: {}", e))?;
+) -> Result<Json<ApiSpecificationResponse>, ApiError> {
+    let specification = analysis::generate_spec(st.llm.as_ref(), &req.analysis, req.model).await?;
 
     Ok(Json(ApiSpecificationResponse {
         specification,
@@ -382,202 +685,3 @@ async fn generate_api_specification(
         error: None,
     }))
 }
-
-async fn call_openai_analysis(
-    api_key: &str,
-    file_info: &FileInfo,
-    model: Option<&str>,
-) -> Result<DirectoryAnalysis, Box<dyn std::error::Error + Send + Sync>> {
-    let client = reqwest::Client::new();
-    let model = model.unwrap_or("gpt-4o");
-    
-    let prompt = format!(
-        "Analyze this file for API generation: {} ({} bytes, type: {}). \
-         Provide a detailed analysis including data patterns, suggested API structure, \
-         and recommend the best AI model for processing this data.",
-        file_info.filename, file_info.file_size, file_info.file_type
-    );
-
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&serde_json::json!({
-            "model": model,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": "You are an expert API architect and data analyst. Analyze files and suggest optimal API designs."
-                },
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ],
-            "max_tokens": 4000,
-            "temperature": 0.3
-        }))
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("OpenAI API error: {}", response.status()).into());
-    }
-
-    let result: serde_json::Value = response.json().await?;
-    let content = result["choices"][0]["message"]["content"].as_str()
-        .ok_or("Invalid response from OpenAI")?;
-
-    // Parse the response and create DirectoryAnalysis
-    let analysis = DirectoryAnalysis {
-        path: format!("uploaded/{}", file_info.filename),
-        file_count: 1,
-        file_types: vec![file_info.file_type.clone()],
-        total_size: format_file_size(file_info.file_size),
-        structure: serde_json::json!({
-            "root": {
-                "files": [{
-                    "name": file_info.filename,
-                    "size": file_info.file_size,
-                    "type": file_info.file_type
-                }]
-            }
-        }),
-        data_patterns: detect_data_patterns(&file_info.filename, &file_info.file_type),
-        suggested_api_structure: Some(serde_json::json!({
-            "endpoints": [
-                {
-                    "path": "/data",
-                    "method": "GET",
-                    "description": "Retrieve processed data",
-                    "parameters": [
-                        {"name": "format", "type": "string", "required": false, "default": "json"},
-                        {"name": "limit", "type": "number", "required": false, "default": 100}
-                    ]
-                }
-            ],
-            "authentication": {"type": "api_key", "required": true},
-            "rate_limits": {"requests_per_minute": 100, "requests_per_hour": 1000}
-        })),
-        best_model: Some(ModelInfo {
-            id: model.to_string(),
-            name: model.to_string(),
-            description: format!("AI-selected optimal model for {}", file_info.file_type),
-            max_tokens: 200000,
-            cost_per_1k_tokens: 0.01,
-            capabilities: vec!["ai-optimized".to_string(), "auto-selected".to_string()],
-        }),
-        model_reasoning: Some(content.to_string()),
-    };
-
-    Ok(analysis)
-}
-
-async fn call_openai_generate_spec(
-    api_key: &str,
-    analysis: &DirectoryAnalysis,
-    model: Option<&str>,
-) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
-    let client = reqwest::Client::new();
-    let model = model.unwrap_or("gpt-4o");
-    
-    let prompt = format!(
-        "Create a complete API specification based on this analysis:
-        
-        Path: {}
-        File Types: {:?}
-        Data Patterns: {:?}
-        Suggested Structure: {}
-        
-        Generate a complete API specification including:
-        - OpenAPI 3.0 spec
-        - Authentication methods
-        - Rate limiting
-        - Pricing tiers (Free, Premium, Enterprise)
-        - Error handling
-        - Documentation
-        - SDK examples
-        
-        Return as JSON.",
-        analysis.path,
-        analysis.file_types,
-        analysis.data_patterns,
-        serde_json::to_string_pretty(&analysis.suggested_api_structure).unwrap_or_default()
-    );
-
-    let response = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&serde_json::json!({
-            "model": model,
-            "messages": [
-                {
-                    "role": "system",
-                    "content": "You are an expert API architect. Create complete API specifications based on data analysis."
-                },
-                {
-                    "role": "user",
-                    "content": prompt
-                }
-            ],
-            "max_tokens": 6000,
-            "temperature": 0.2
-        }))
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        return Err(format!("OpenAI API error: {}", response.status()).into());
-    }
-
-    let result: serde_json::Value = response.json().await?;
-    let content = result["choices"][0]["message"]["content"].as_str()
-        .ok_or("Invalid response from OpenAI")?;
-
-    // Try to parse as JSON, fallback to mock spec if parsing fails
-    Ok(serde_json::from_str(content).unwrap_or_else(|_| {
-        serde_json::json!({
-            "openapi": "3.0.0",
-            "info": {
-                "title": format!("API for {}", analysis.path),
-                "version": "1.0.0",
-                "description": "Generated API specification"
-            },
-            "servers": [
-                {"url": "https://api.minam.com/v1", "description": "Production server"}
-            ],
-            "paths": {
-                "/data": {
-                    "get": {
-                        "summary": "Retrieve processed data",
-                        "parameters": [
-                            {"name": "format", "in": "query", "schema": {"type": "string", "default": "json"}},
-                            {"name": "limit", "in": "query", "schema": {"type": "integer", "default": 100}}
-                        ],
-                        "responses": {
-                            "200": {
-                                "description": "Successful response",
-                                "content": {
-                                    "application/json": {
-                                        "schema": {"type": "object"}
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            },
-            "components": {
-                "securitySchemes": {
-                    "apiKey": {
-                        "type": "apiKey",
-                        "in": "header",
-                        "name": "X-API-Key"
-                    }
-                }
-            },
-            "security": [{"apiKey": []}]
-        })
-    }))
-}
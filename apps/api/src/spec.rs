@@ -0,0 +1,147 @@
+use serde_json::{json, Map, Value};
+use crate::models::{ApiProduct, FeatureSpec, ModelProfile, Monotonic};
+
+// OpenAPI document for a published API, generated from its model profile so that
+// feature constraints travel with the contract.
+pub fn openapi(api: &ApiProduct, profile: &ModelProfile) -> Value {
+    let properties: Map<String, Value> = profile.features.iter().map(|f| (f.name.clone(), feature_schema(f))).collect();
+    // key parts can never be null
+    let required: Vec<&str> = profile.features.iter()
+        .filter(|f| f.nullable == Some(false) || profile.primary_key.contains(&f.name))
+        .map(|f| f.name.as_str())
+        .collect();
+    let mut row = json!({ "type": "object", "properties": properties, "required": required });
+    if !profile.primary_key.is_empty() {
+        row["x-primary-key"] = json!(profile.primary_key);
+    }
+    let rows = json!({ "type": "array", "items": { "$ref": "#/components/schemas/Row" } });
+    // deprecated versions answer 410, so only the servable ones are listed
+    let servable: Vec<&str> = std::iter::once("latest")
+        .chain(api.versions.iter().filter(|v| v.deprecated_at.is_none()).map(|v| v.version.as_str()))
+        .collect();
+    let version = json!({
+        "name": "version",
+        "in": "query",
+        "description": "Version to read; the latest when absent",
+        "schema": { "type": "string", "enum": servable, "default": "latest" }
+    });
+    let provider = json!({
+        "type": "object",
+        "properties": { "provider_id": { "type": "string", "format": "uuid" } },
+        "required": ["provider_id"]
+    });
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": api.name,
+            "version": api.version,
+            "description": format!("{} (model profile {} v{})", profile.description, profile.name, profile.version),
+        },
+        "paths": {
+            format!("/v1/data/{}/query", api.id): {
+                "post": {
+                    "summary": "Query rows",
+                    "parameters": [
+                        { "name": "format", "in": "query", "schema": { "type": "string", "enum": ["json", "ndjson", "csv", "arrow"] } },
+                        version
+                    ],
+                    "requestBody": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Query" } } } },
+                    "responses": {
+                        "200": {
+                            "description": "Matching rows",
+                            "content": {
+                                "application/json": { "schema": rows },
+                                "application/x-ndjson": { "schema": { "$ref": "#/components/schemas/Row" } },
+                                "text/csv": { "schema": { "type": "string" } },
+                                "application/vnd.apache.arrow.stream": { "schema": { "type": "string", "format": "binary" } }
+                            }
+                        }
+                    }
+                }
+            },
+            format!("/v1/data/{}/stream", api.id): {
+                "get": {
+                    "summary": "Server-sent events of appended rows",
+                    "security": [{ "apiKey": [] }],
+                    "parameters": [version],
+                    "responses": { "200": { "description": "`rows` events carry arrays of Row; a `closed` event ends the stream once the product stops serving", "content": { "text/event-stream": {} } } }
+                }
+            },
+            format!("/api/apis/{}/versions", api.id): {
+                "post": {
+                    "summary": "Publish an approved proposal as the next version (provider only)",
+                    "requestBody": { "content": { "application/json": { "schema": {
+                        "type": "object",
+                        "properties": {
+                            "proposal_id": { "type": "string", "format": "uuid" },
+                            "provider_id": { "type": "string", "format": "uuid" },
+                            "human_approval_note": { "type": "string" },
+                            "published_by": { "type": "string" }
+                        },
+                        "required": ["proposal_id", "provider_id"]
+                    } } } },
+                    "responses": { "200": { "description": "The product with the new version as its latest" } }
+                }
+            },
+            format!("/api/apis/{}/versions/{{version}}/deprecate", api.id): {
+                "post": {
+                    "summary": "Deprecate a version other than the latest (provider only)",
+                    "parameters": [{ "name": "version", "in": "path", "required": true, "schema": { "type": "string" } }],
+                    "requestBody": { "content": { "application/json": { "schema": provider } } },
+                    "responses": { "200": { "description": "The product; queries for the version now answer 410" } }
+                }
+            }
+        },
+        "components": {
+            "schemas": {
+                "Row": row,
+                "Query": {
+                    "type": "object",
+                    "properties": {
+                        "symbol": { "type": "string" },
+                        "start": { "type": "string", "format": "date-time" },
+                        "end": { "type": "string", "format": "date-time" },
+                        "limit": { "type": "integer", "minimum": 0 },
+                        "ranges": { "type": "object", "additionalProperties": {
+                            "type": "object",
+                            "properties": { "min": { "type": "number" }, "max": { "type": "number" } }
+                        } },
+                        "format": { "type": "string" }
+                    }
+                }
+            },
+            "securitySchemes": { "apiKey": { "type": "apiKey", "in": "header", "name": "X-API-Key" } }
+        }
+    })
+}
+
+fn feature_schema(f: &FeatureSpec) -> Value {
+    let mut s = Map::new();
+    let (ty, format) = match f.dtype.as_str() {
+        "number" | "float" | "double" => ("number", None),
+        "integer" | "int" => ("integer", None),
+        "bool" | "boolean" => ("boolean", None),
+        "datetime" | "timestamp" => ("string", Some("date-time")),
+        _ => ("string", None),
+    };
+    s.insert("type".into(), ty.into());
+    if let Some(format) = format { s.insert("format".into(), format.into()); }
+    s.insert("nullable".into(), (f.nullable != Some(false)).into());
+    if let Some(d) = &f.description { s.insert("description".into(), d.clone().into()); }
+    if let Some(x) = f.min { s.insert("minimum".into(), x.into()); }
+    if let Some(x) = f.max { s.insert("maximum".into(), x.into()); }
+    if let Some(v) = &f.allowed_values { s.insert("enum".into(), v.clone().into()); }
+    if let Some(p) = &f.pattern { s.insert("pattern".into(), p.clone().into()); }
+    // no JSON Schema keyword for these, so they go in extensions
+    if let Some(u) = &f.unit { s.insert("x-unit".into(), u.clone().into()); }
+    if f.unique == Some(true) { s.insert("x-unique".into(), true.into()); }
+    if let Some(m) = f.monotonic {
+        s.insert("x-monotonic".into(), match m {
+            Monotonic::Increasing => "increasing",
+            Monotonic::StrictlyIncreasing => "strictly_increasing",
+            Monotonic::Decreasing => "decreasing",
+            Monotonic::StrictlyDecreasing => "strictly_decreasing",
+        }.into());
+    }
+    Value::Object(s)
+}
@@ -1,7 +1,11 @@
 use dashmap::DashMap;
 use uuid::Uuid;
 use std::sync::Arc;
+use tokio::sync::broadcast;
+use crate::jobs::JobQueue;
+use crate::llm::{self, LlmProvider};
 use crate::models::*;
+use crate::table::Table;
 
 #[derive(Clone)]
 pub struct Store {
@@ -11,16 +15,20 @@ pub struct Store {
     pub proposals: Arc<DashMap<Uuid, ApiProposal>>,
     pub apis: Arc<DashMap<Uuid, ApiProduct>>,
     pub files: Arc<DashMap<Uuid, FileInfo>>,
+    pub keys: Arc<DashMap<String, ConsumerKey>>,
+    // appended rows per dataset, fanned out to real-time subscribers
+    pub feeds: Arc<DashMap<Uuid, broadcast::Sender<Arc<Table>>>>,
+    // open real-time subscriptions per consumer key
+    pub subscriptions: Arc<DashMap<String, usize>>,
+    pub jobs: Arc<DashMap<Uuid, PipelineJob>>,
 }
 
 #[derive(Clone)]
 pub struct FileInfo {
-    pub id: Uuid,
     pub filename: String,
     pub file_size: u64,
     pub file_type: String,
     pub content: Vec<u8>,
-    pub uploaded_at: chrono::DateTime<chrono::Utc>,
 }
 
 impl Default for Store {
@@ -32,6 +40,10 @@ impl Default for Store {
             proposals: Arc::new(DashMap::new()),
             apis: Arc::new(DashMap::new()),
             files: Arc::new(DashMap::new()),
+            keys: Arc::new(DashMap::new()),
+            feeds: Arc::new(DashMap::new()),
+            subscriptions: Arc::new(DashMap::new()),
+            jobs: Arc::new(DashMap::new()),
         }
     }
 }
@@ -39,8 +51,14 @@ impl Default for Store {
 #[derive(Clone)]
 pub struct AppState {
     pub store: Store,
+    // pipeline jobs waiting for a worker
+    pub queue: JobQueue,
+    // chat model behind the analysis endpoints
+    pub llm: Arc<dyn LlmProvider>,
 }
 
 impl Default for AppState {
-    fn default() -> Self { Self { store: Store::default() } }
+    fn default() -> Self {
+        Self { store: Store::default(), queue: JobQueue::default(), llm: llm::from_env() }
+    }
 }
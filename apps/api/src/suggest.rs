@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde_json::Value;
+use crate::mapping;
+use crate::models::*;
+use crate::table::{Column, ColumnData, Table};
+
+pub const DEFAULT_MIN_CONFIDENCE: f64 = 0.6;
+
+// Values looked at per column when judging its shape.
+const SAMPLE_VALUES: usize = 200;
+
+// Names that mean the same thing; the first entry is the group's canonical token.
+const SYNONYMS: &[&[&str]] = &[
+    &["ts", "timestamp", "time", "datetime", "date", "t", "epoch", "created_at", "event_time"],
+    &["symbol", "ticker", "pair", "instrument", "asset", "coin", "sym"],
+    &["price", "px", "last", "close", "last_price", "mid"],
+    &["volume", "vol", "qty", "quantity", "size", "amount"],
+    &["funding", "funding_rate", "fr"],
+    &["open_interest", "oi"],
+    &["bid", "bid_price", "best_bid"],
+    &["ask", "offer", "ask_price", "best_ask"],
+    &["rvol", "realized_vol", "realised_vol", "volatility", "rv"],
+    &["sentiment", "sentiment_score", "score"],
+    &["market_cap", "mcap", "marketcap"],
+];
+
+// What individual values look like, independent of storage
+#[derive(Clone, Copy, PartialEq)]
+enum Shape {
+    Datetime,
+    // integers in the range of epoch seconds through nanoseconds
+    Epoch,
+    Integer,
+    Decimal,
+    Bool,
+    // short identifier-like text: BTC, ETH-USD, btc_usdt
+    Code,
+    Text,
+    Nested,
+}
+
+pub fn suggest(dataset_id: uuid::Uuid, profile: &ModelProfile, table: &Table, min_confidence: f64) -> MappingSuggestion {
+    let shapes: Vec<Vec<Shape>> = table.columns().iter().map(sample_shapes).collect();
+    // every (feature, column) pair, strongest first
+    let mut scored: Vec<(usize, usize, ColumnCandidate)> = Vec::new();
+    for (f, feat) in profile.features.iter().enumerate() {
+        for (c, col) in table.columns().iter().enumerate() {
+            scored.push((f, c, score(feat, col, &shapes[c])));
+        }
+    }
+    scored.sort_by(|a, b| b.2.confidence.total_cmp(&a.2.confidence));
+
+    // greedy one-to-one assignment
+    let mut matched: Vec<Option<ColumnCandidate>> = vec![None; profile.features.len()];
+    let mut taken = HashSet::new();
+    for (f, c, cand) in scored.iter() {
+        if cand.confidence < min_confidence || matched[*f].is_some() || taken.contains(c) { continue; }
+        taken.insert(*c);
+        matched[*f] = Some(cand.clone());
+    }
+
+    let mut mappings = BTreeMap::new();
+    let matches = profile.features.iter().enumerate().map(|(f, feat)| {
+        if let Some(m) = &matched[f] {
+            let col = table.column(&m.column).expect("matched column exists");
+            mappings.insert(feat.name.clone(), FeatureMapping {
+                from: Some(m.column.clone()),
+                format: epoch_format(feat, col),
+                ..Default::default()
+            });
+        }
+        let alternatives = scored.iter()
+            .filter(|(sf, _, cand)| *sf == f && cand.confidence > 0.0 && Some(&cand.column) != matched[f].as_ref().map(|m| &m.column))
+            .take(3)
+            .map(|(_, _, cand)| cand.clone())
+            .collect();
+        FeatureMatch { feature: feat.name.clone(), matched: matched[f].clone(), alternatives }
+    }).collect();
+
+    MappingSuggestion {
+        dataset_id,
+        model_profile_id: profile.id,
+        min_confidence,
+        matches,
+        unmatched_features: profile.features.iter().enumerate()
+            .filter(|(f, _)| matched[*f].is_none())
+            .map(|(_, feat)| feat.name.clone())
+            .collect(),
+        unused_columns: table.columns().iter().enumerate()
+            .filter(|(c, _)| !taken.contains(c))
+            .map(|(_, col)| col.name.clone())
+            .collect(),
+        mappings,
+    }
+}
+
+// confidence = name 55% + dtype 20% + value shape 25%
+fn score(feat: &FeatureSpec, col: &Column, shapes: &[Shape]) -> ColumnCandidate {
+    let mut reasons = Vec::new();
+    let (name, why) = name_score(&feat.name, &col.name);
+    if let Some(why) = why { reasons.push(why); }
+    let dtype = dtype_score(&feat.dtype, col);
+    reasons.push(format!("dtype {} -> {}: {}", col.dtype(), feat.dtype, match dtype {
+        x if x >= 1.0 => "compatible",
+        x if x > 0.0 => "castable",
+        _ => "incompatible",
+    }));
+    let expected = expected_shapes(feat);
+    let shape = if shapes.is_empty() { 0.0 } else {
+        shapes.iter().filter(|s| expected.contains(s)).count() as f64 / shapes.len() as f64
+    };
+    if !shapes.is_empty() {
+        reasons.push(format!("{:.0}% of sampled values look like {}", shape * 100.0, feat.dtype));
+    }
+    // a column that shares nothing with the name needs near-perfect values to be suggested
+    let confidence = if name == 0.0 { 0.5 * (0.4 * dtype + 0.6 * shape) } else { 0.55 * name + 0.2 * dtype + 0.25 * shape };
+    ColumnCandidate { column: col.name.clone(), confidence: (confidence * 1000.0).round() / 1000.0, reasons }
+}
+
+// Lowercase tokens: camelCase, spaces, dashes and dots all split words.
+fn tokens(name: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut prev_lower = false;
+    for ch in name.chars() {
+        if !ch.is_alphanumeric() {
+            if !cur.is_empty() { out.push(std::mem::take(&mut cur)); }
+            prev_lower = false;
+            continue;
+        }
+        if ch.is_uppercase() && prev_lower && !cur.is_empty() {
+            out.push(std::mem::take(&mut cur));
+        }
+        prev_lower = ch.is_lowercase() || ch.is_ascii_digit();
+        cur.extend(ch.to_lowercase());
+    }
+    if !cur.is_empty() { out.push(cur); }
+    out
+}
+
+fn synonym_group(word: &str) -> Option<usize> {
+    SYNONYMS.iter().position(|g| g.contains(&word))
+}
+
+fn name_score(feature: &str, column: &str) -> (f64, Option<String>) {
+    let (ft, ct) = (tokens(feature), tokens(column));
+    let (fj, cj) = (ft.join("_"), ct.join("_"));
+    if fj == cj {
+        return (1.0, Some("same name".into()));
+    }
+    if let (Some(a), Some(b)) = (synonym_group(&fj), synonym_group(&cj)) {
+        if a == b {
+            return (0.9, Some(format!("synonym of {}", SYNONYMS[a][0])));
+        }
+    }
+    // token overlap after folding synonyms onto their canonical token
+    let canon = |t: &Vec<String>| -> HashSet<String> {
+        t.iter().map(|w| synonym_group(w).map(|g| SYNONYMS[g][0].to_string()).unwrap_or_else(|| w.clone())).collect()
+    };
+    let (fs, cs) = (canon(&ft), canon(&ct));
+    let overlap = fs.intersection(&cs).count() as f64 / fs.union(&cs).count().max(1) as f64;
+    let similar = 1.0 - levenshtein(&fj, &cj) as f64 / fj.len().max(cj.len()).max(1) as f64;
+    let best = (0.75 * overlap).max(0.6 * similar);
+    match best {
+        x if x < 0.3 => (0.0, None),
+        x if overlap > 0.0 && 0.75 * overlap >= 0.6 * similar => (x, Some("shares name tokens".into())),
+        x => (x, Some("similar name".into())),
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + usize::from(ca != *cb)).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+fn dtype_score(dtype: &str, col: &Column) -> f64 {
+    let have = col.dtype();
+    match (dtype, have) {
+        (_, "null") => 0.0,
+        ("number" | "float" | "double", "number" | "integer") => 1.0,
+        ("integer" | "int", "integer") => 1.0,
+        ("integer" | "int", "number") => 0.5,
+        ("bool" | "boolean", "bool") => 1.0,
+        ("bool" | "boolean", "integer") => 0.5,
+        ("datetime" | "timestamp", "datetime") => 1.0,
+        ("datetime" | "timestamp", "string" | "integer" | "number") => 0.5,
+        ("string", "string") => 1.0,
+        ("string", _) => 0.5,
+        (_, "string") => 0.5,
+        _ if !matches!(dtype, "number" | "float" | "double" | "integer" | "int" | "bool" | "boolean" | "datetime" | "timestamp") => 0.5,
+        _ => 0.0,
+    }
+}
+
+fn expected_shapes(feat: &FeatureSpec) -> &'static [Shape] {
+    let group = synonym_group(&tokens(&feat.name).join("_"));
+    match feat.dtype.as_str() {
+        "number" | "float" | "double" => &[Shape::Decimal, Shape::Integer, Shape::Epoch],
+        "integer" | "int" => &[Shape::Integer, Shape::Epoch],
+        "bool" | "boolean" => &[Shape::Bool],
+        "datetime" | "timestamp" => &[Shape::Datetime, Shape::Epoch],
+        "string" if group == Some(1) => &[Shape::Code],
+        "string" => &[Shape::Code, Shape::Text],
+        _ => &[Shape::Nested, Shape::Text, Shape::Code],
+    }
+}
+
+fn sample_shapes(col: &Column) -> Vec<Shape> {
+    let datetime_col = matches!(col.data, ColumnData::Datetime(_));
+    col.iter()
+        .filter(|c| !c.is_null())
+        .take(SAMPLE_VALUES)
+        .map(|c| if datetime_col { Shape::Datetime } else { shape(&c.to_value()) })
+        .collect()
+}
+
+fn shape(v: &Value) -> Shape {
+    match v {
+        Value::Bool(_) => Shape::Bool,
+        Value::Number(n) if n.as_i64().is_some_and(|x| (1_000_000_000..1_000_000_000_000_000_000).contains(&x.abs())) => Shape::Epoch,
+        Value::Number(n) if n.is_i64() || n.is_u64() => Shape::Integer,
+        Value::Number(_) => Shape::Decimal,
+        Value::String(s) => {
+            let s = s.trim();
+            if s.parse::<i64>().is_ok() { Shape::Integer }
+            else if s.parse::<f64>().is_ok() { Shape::Decimal }
+            else if !mapping::cast(v, "datetime", None).is_null() { Shape::Datetime }
+            else if matches!(s.to_lowercase().as_str(), "true" | "false" | "yes" | "no") { Shape::Bool }
+            else if (1..=16).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric() || "-_/:.".contains(c)) { Shape::Code }
+            else { Shape::Text }
+        }
+        _ => Shape::Nested,
+    }
+}
+
+// Numeric timestamps get an epoch format guessed from their magnitude.
+fn epoch_format(feat: &FeatureSpec, col: &Column) -> Option<String> {
+    if !matches!(feat.dtype.as_str(), "datetime" | "timestamp") { return None; }
+    if !matches!(col.data, ColumnData::Integer(_) | ColumnData::Number { .. }) { return None; }
+    let x = col.iter().find_map(|c| c.as_f64())?.abs();
+    Some(match x {
+        x if x >= 1e17 => "epoch_ns",
+        x if x >= 1e14 => "epoch_us",
+        x if x >= 1e11 => "epoch_ms",
+        _ => "epoch_s",
+    }.into())
+}
@@ -0,0 +1,808 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use chrono::SecondsFormat;
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+// String columns with at most this many distinct values are dictionary encoded.
+const MAX_DICT_CARDINALITY: usize = 4096;
+// Largest chunk a ChunkedTable builds, which bounds the copy one patched row costs.
+const MAX_CHUNK_ROWS: usize = 65_536;
+
+// Packed validity bits: set means the slot holds a value, clear means null or missing.
+#[derive(Clone, Default)]
+pub struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) { self.words.push(0); }
+        if bit { self.words[self.len / 64] |= 1 << (self.len % 64); }
+        self.len += 1;
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        i < self.len && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn set(&mut self, i: usize, bit: bool) {
+        if bit { self.words[i / 64] |= 1 << (i % 64); } else { self.words[i / 64] &= !(1 << (i % 64)); }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+// Typed storage for one column. Slots that are null still occupy a (default) entry
+// so positions line up with the validity bitmap.
+#[derive(Clone)]
+pub enum ColumnData {
+    // no value was ever seen
+    Null,
+    Bool(Bitmap),
+    Integer(Vec<i64>),
+    // `ints` marks slots written as integers, which read back as integers
+    Number { values: Vec<f64>, ints: Bitmap },
+    // microseconds since the epoch; only used when the text round-trips as RFC 3339
+    Datetime(Vec<i64>),
+    Dict { values: Vec<String>, lookup: HashMap<String, u32>, codes: Vec<u32> },
+    Utf8 { offsets: Vec<usize>, data: String },
+    // mixed types or nested values
+    Json(Vec<serde_json::Value>),
+}
+
+// Borrowed view of a single slot
+#[derive(Clone, Copy)]
+pub enum Cell<'a> {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Number(f64),
+    Datetime(i64),
+    Str(&'a str),
+    Json(&'a serde_json::Value),
+}
+
+impl<'a> Cell<'a> {
+    pub fn is_null(self) -> bool {
+        matches!(self, Cell::Null | Cell::Json(serde_json::Value::Null))
+    }
+
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            Cell::Integer(i) => Some(i as f64),
+            Cell::Number(x) => Some(x),
+            Cell::Json(v) => v.as_f64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> Option<&'a str> {
+        match self {
+            Cell::Str(s) => Some(s),
+            Cell::Json(v) => v.as_str(),
+            _ => None,
+        }
+    }
+
+    // Microseconds since the epoch for native datetimes and parseable strings.
+    pub fn as_micros(self) -> Option<i64> {
+        match self {
+            Cell::Datetime(m) => Some(m),
+            other => other.as_str().and_then(parse_micros),
+        }
+    }
+
+    pub fn to_value(self) -> serde_json::Value {
+        match self {
+            Cell::Null => serde_json::Value::Null,
+            Cell::Bool(b) => serde_json::Value::Bool(b),
+            Cell::Integer(i) => serde_json::Value::from(i),
+            Cell::Number(x) => serde_json::Number::from_f64(x).map(serde_json::Value::Number).unwrap_or_default(),
+            Cell::Datetime(m) => serde_json::Value::String(format_micros(m)),
+            Cell::Str(s) => serde_json::Value::String(s.to_string()),
+            Cell::Json(v) => v.clone(),
+        }
+    }
+}
+
+pub fn parse_micros(s: &str) -> Option<i64> {
+    s.parse::<chrono::DateTime<chrono::Utc>>().ok().map(|t| t.timestamp_micros())
+}
+
+pub fn format_micros(m: i64) -> String {
+    chrono::DateTime::from_timestamp_micros(m)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        .unwrap_or_default()
+}
+
+// Largest integer every f64 below it holds exactly.
+const MAX_EXACT_INT: u64 = 1 << 53;
+
+// A number as a Number column stores it: the value, and whether it was written as
+// an integer. Integers an f64 cannot hold exactly do not fit.
+fn exact_f64(v: &serde_json::Value) -> Option<(f64, bool)> {
+    let serde_json::Value::Number(n) = v else { return None };
+    match (n.as_i64(), n.as_u64()) {
+        (Some(i), _) => (i.unsigned_abs() <= MAX_EXACT_INT).then_some((i as f64, true)),
+        (_, Some(u)) => (u <= MAX_EXACT_INT).then_some((u as f64, true)),
+        _ => n.as_f64().map(|x| (x, false)),
+    }
+}
+
+// Datetime storage is lossless only for text we can reproduce byte for byte.
+fn canonical_micros(s: &str) -> Option<i64> {
+    parse_micros(s).filter(|m| format_micros(*m) == s)
+}
+
+#[derive(Clone)]
+pub struct Column {
+    pub name: String,
+    pub data: ColumnData,
+    pub validity: Bitmap,
+}
+
+impl Column {
+    // Infers the narrowest storage that holds every value without loss. Integers
+    // mixed with floats keep reading back as integers; a mix with integers past
+    // 2^53 is kept as JSON.
+    pub fn from_values<'a, I>(name: &str, values: I) -> Self
+    where
+        I: Iterator<Item = Option<&'a serde_json::Value>> + Clone,
+    {
+        let (mut bools, mut ints, mut nums, mut exact, mut strs, mut dts, mut other, mut present) = (0, 0, 0, 0, 0, 0, 0, 0usize);
+        let mut distinct: HashSet<&str> = HashSet::new();
+        for v in values.clone().flatten() {
+            match v {
+                serde_json::Value::Null => continue,
+                serde_json::Value::Bool(_) => bools += 1,
+                serde_json::Value::Number(n) => {
+                    nums += 1;
+                    if n.is_i64() { ints += 1; }
+                    if exact_f64(v).is_some() { exact += 1; }
+                }
+                serde_json::Value::String(s) => {
+                    strs += 1;
+                    if canonical_micros(s).is_some() { dts += 1; }
+                    if distinct.len() <= MAX_DICT_CARDINALITY { distinct.insert(s); }
+                }
+                _ => other += 1,
+            }
+            present += 1;
+        }
+        let data = if present == 0 {
+            ColumnData::Null
+        } else if other > 0 {
+            ColumnData::Json(Vec::new())
+        } else if bools == present {
+            ColumnData::Bool(Bitmap::default())
+        } else if ints == present {
+            ColumnData::Integer(Vec::new())
+        } else if nums == present && exact == present {
+            ColumnData::Number { values: Vec::new(), ints: Bitmap::default() }
+        } else if dts == present {
+            ColumnData::Datetime(Vec::new())
+        } else if strs == present && distinct.len() <= MAX_DICT_CARDINALITY {
+            ColumnData::Dict { values: Vec::new(), lookup: HashMap::new(), codes: Vec::new() }
+        } else if strs == present {
+            ColumnData::Utf8 { offsets: vec![0], data: String::new() }
+        } else {
+            ColumnData::Json(Vec::new())
+        };
+        let mut col = Column { name: name.to_string(), data, validity: Bitmap::default() };
+        for v in values {
+            let ok = col.push(v);
+            debug_assert!(ok, "inferred column type must accept its own values");
+        }
+        col
+    }
+
+    // An empty column with the same storage type
+    fn empty_like(&self) -> Self {
+        let data = match &self.data {
+            ColumnData::Null => ColumnData::Null,
+            ColumnData::Bool(_) => ColumnData::Bool(Bitmap::default()),
+            ColumnData::Integer(_) => ColumnData::Integer(Vec::new()),
+            ColumnData::Number { .. } => ColumnData::Number { values: Vec::new(), ints: Bitmap::default() },
+            ColumnData::Datetime(_) => ColumnData::Datetime(Vec::new()),
+            ColumnData::Dict { .. } => ColumnData::Dict { values: Vec::new(), lookup: HashMap::new(), codes: Vec::new() },
+            ColumnData::Utf8 { .. } => ColumnData::Utf8 { offsets: vec![0], data: String::new() },
+            ColumnData::Json(_) => ColumnData::Json(Vec::new()),
+        };
+        Column { name: self.name.clone(), data, validity: Bitmap::default() }
+    }
+
+    pub fn nulls(name: &str, len: usize) -> Self {
+        let mut validity = Bitmap::default();
+        (0..len).for_each(|_| validity.push(false));
+        Column { name: name.to_string(), data: ColumnData::Null, validity }
+    }
+
+    pub fn len(&self) -> usize {
+        self.validity.len
+    }
+
+    // The FeatureSpec dtype this column stores
+    pub fn dtype(&self) -> &'static str {
+        match self.data {
+            ColumnData::Null => "null",
+            ColumnData::Bool(_) => "bool",
+            ColumnData::Integer(_) => "integer",
+            ColumnData::Number { .. } => "number",
+            ColumnData::Datetime(_) => "datetime",
+            ColumnData::Dict { .. } | ColumnData::Utf8 { .. } => "string",
+            ColumnData::Json(_) => "json",
+        }
+    }
+
+    pub fn non_null(&self) -> usize {
+        self.validity.count_ones()
+    }
+
+    pub fn get(&self, i: usize) -> Cell<'_> {
+        if !self.validity.get(i) { return Cell::Null; }
+        match &self.data {
+            ColumnData::Null => Cell::Null,
+            ColumnData::Bool(b) => Cell::Bool(b.get(i)),
+            ColumnData::Integer(v) => Cell::Integer(v[i]),
+            ColumnData::Number { values, ints } if ints.get(i) => Cell::Integer(values[i] as i64),
+            ColumnData::Number { values, .. } => Cell::Number(values[i]),
+            ColumnData::Datetime(v) => Cell::Datetime(v[i]),
+            ColumnData::Dict { values, codes, .. } => Cell::Str(&values[codes[i] as usize]),
+            ColumnData::Utf8 { offsets, data } => Cell::Str(&data[offsets[i]..offsets[i + 1]]),
+            ColumnData::Json(v) => Cell::Json(&v[i]),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Cell<'_>> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    // Overwrites slot `i`; returns false when the value does not fit this column's
+    // type. Variable-width strings are not patched in place.
+    fn set(&mut self, i: usize, v: Option<&serde_json::Value>) -> bool {
+        let v = v.filter(|v| !v.is_null());
+        let ok = match (&mut self.data, v) {
+            // the old entry stays as a placeholder behind a clear validity bit
+            (_, None) => true,
+            (ColumnData::Bool(b), Some(serde_json::Value::Bool(x))) => { b.set(i, *x); true }
+            (ColumnData::Integer(xs), Some(x)) => x.as_i64().map(|x| xs[i] = x).is_some(),
+            (ColumnData::Number { values, ints }, Some(x)) => exact_f64(x).map(|(x, int)| { values[i] = x; ints.set(i, int) }).is_some(),
+            (ColumnData::Datetime(xs), Some(x)) => x.as_str().and_then(canonical_micros).map(|m| xs[i] = m).is_some(),
+            (ColumnData::Dict { values, lookup, codes }, Some(serde_json::Value::String(s))) => match lookup.get(s) {
+                Some(code) => { codes[i] = *code; true }
+                None if values.len() < MAX_DICT_CARDINALITY => {
+                    lookup.insert(s.clone(), values.len() as u32);
+                    codes[i] = values.len() as u32;
+                    values.push(s.clone());
+                    true
+                }
+                None => false,
+            },
+            (ColumnData::Json(xs), Some(x)) => { xs[i] = x.clone(); true }
+            _ => false,
+        };
+        if ok { self.validity.set(i, v.is_some()); }
+        ok
+    }
+
+    // Appends a cell of another column, skipping JSON where the types line up.
+    fn push_cell(&mut self, cell: Cell) -> bool {
+        if cell.is_null() { return self.push(None); }
+        match (&mut self.data, cell) {
+            (ColumnData::Bool(b), Cell::Bool(x)) => b.push(x),
+            (ColumnData::Integer(xs), Cell::Integer(x)) => xs.push(x),
+            (ColumnData::Number { values, ints }, Cell::Number(x)) => { values.push(x); ints.push(false) }
+            (ColumnData::Number { values, ints }, Cell::Integer(x)) if x.unsigned_abs() <= MAX_EXACT_INT => { values.push(x as f64); ints.push(true) }
+            (ColumnData::Datetime(xs), Cell::Datetime(m)) => xs.push(m),
+            (_, c) => return self.push(Some(&c.to_value())),
+        }
+        self.validity.push(true);
+        true
+    }
+
+    // Appends one slot; returns false when the value does not fit this column's type.
+    fn push(&mut self, v: Option<&serde_json::Value>) -> bool {
+        let v = v.filter(|v| !v.is_null());
+        let ok = match (&mut self.data, v) {
+            (ColumnData::Null, None) => true,
+            (ColumnData::Null, Some(_)) => false,
+            (ColumnData::Bool(b), v) => match v {
+                None => { b.push(false); true }
+                Some(serde_json::Value::Bool(x)) => { b.push(*x); true }
+                _ => false,
+            },
+            (ColumnData::Integer(xs), v) => match v {
+                None => { xs.push(0); true }
+                Some(x) => x.as_i64().map(|x| xs.push(x)).is_some(),
+            },
+            (ColumnData::Number { values, ints }, v) => match v {
+                None => { values.push(0.0); ints.push(false); true }
+                Some(x) => exact_f64(x).map(|(x, int)| { values.push(x); ints.push(int) }).is_some(),
+            },
+            (ColumnData::Datetime(xs), v) => match v {
+                None => { xs.push(0); true }
+                Some(x) => x.as_str().and_then(canonical_micros).map(|m| xs.push(m)).is_some(),
+            },
+            (ColumnData::Dict { values, lookup, codes }, v) => match v {
+                None => { codes.push(0); true }
+                Some(serde_json::Value::String(s)) => match lookup.get(s) {
+                    Some(code) => { codes.push(*code); true }
+                    None if values.len() < MAX_DICT_CARDINALITY => {
+                        lookup.insert(s.clone(), values.len() as u32);
+                        codes.push(values.len() as u32);
+                        values.push(s.clone());
+                        true
+                    }
+                    None => false,
+                },
+                _ => false,
+            },
+            (ColumnData::Utf8 { offsets, data }, v) => match v {
+                None => { offsets.push(data.len()); true }
+                Some(serde_json::Value::String(s)) => {
+                    data.push_str(s);
+                    offsets.push(data.len());
+                    true
+                }
+                _ => false,
+            },
+            (ColumnData::Json(xs), v) => {
+                xs.push(v.cloned().unwrap_or_default());
+                true
+            }
+        };
+        if ok { self.validity.push(v.is_some()); }
+        ok
+    }
+}
+
+// A dataset held as typed columns. Rows are only materialized when something
+// needs to serialize them.
+#[derive(Clone, Default)]
+pub struct Table {
+    len: usize,
+    columns: Vec<Column>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Table {
+    pub fn from_rows(rows: &[serde_json::Value]) -> Self {
+        let mut names: Vec<&String> = rows.iter().filter_map(|r| r.as_object()).flat_map(|o| o.keys()).collect();
+        names.sort();
+        names.dedup();
+        let columns = names
+            .into_iter()
+            .map(|name| Column::from_values(name, rows.iter().map(move |r| r.get(name))))
+            .collect();
+        Self::from_columns(rows.len(), columns)
+    }
+
+    pub fn from_columns(len: usize, columns: Vec<Column>) -> Self {
+        let by_name = columns.iter().enumerate().map(|(i, c)| (c.name.clone(), i)).collect();
+        Self { len, columns, by_name }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.by_name.get(name).map(|i| &self.columns[*i])
+    }
+
+    pub fn cell(&self, name: &str, i: usize) -> Cell<'_> {
+        self.column(name).map(|c| c.get(i)).unwrap_or(Cell::Null)
+    }
+
+    // Materializes row `i`; null slots are left out, as they were absent or null on
+    // ingest. Used for comparing rows; responses use `row_dense`.
+    pub fn row(&self, i: usize) -> serde_json::Value {
+        let mut out = serde_json::Map::new();
+        for col in self.columns.iter() {
+            let cell = col.get(i);
+            if !cell.is_null() { out.insert(col.name.clone(), cell.to_value()); }
+        }
+        serde_json::Value::Object(out)
+    }
+
+    // Materializes row `i` with every column present, nulls included, so consumers
+    // see each feature as a key.
+    pub fn row_dense(&self, i: usize) -> serde_json::Value {
+        let out = self.columns.iter().map(|c| (c.name.clone(), c.get(i).to_value())).collect();
+        serde_json::Value::Object(out)
+    }
+
+    // New table holding only the given rows, in the given order.
+    pub fn take(&self, rows: &[usize]) -> Table {
+        let columns = self.columns.iter().map(|col| {
+            let values: Vec<serde_json::Value> = rows.iter().map(|i| col.get(*i).to_value()).collect();
+            Column::from_values(&col.name, values.iter().map(Some))
+        }).collect();
+        Self::from_columns(rows.len(), columns)
+    }
+
+    // Sort key of a row: numbers as-is, datetimes as epoch microseconds.
+    pub fn sort_key(&self, column: &str, i: usize) -> Option<f64> {
+        let cell = self.cell(column, i);
+        cell.as_f64().or_else(|| cell.as_micros().map(|m| m as f64))
+    }
+
+    // Row ids split by partition value, each group ordered by the sort key (rows
+    // without a key keep their relative order at the end). Groups come in order of
+    // first appearance.
+    pub fn groups(&self, sort_by: Option<&str>, partition_by: Option<&str>) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut by_value: HashMap<String, usize> = HashMap::new();
+        for i in 0..self.len {
+            let part = partition_by.map(|p| self.cell(p, i).to_value().to_string()).unwrap_or_default();
+            let g = *by_value.entry(part).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[g].push(i);
+        }
+        if let Some(k) = sort_by {
+            for g in groups.iter_mut() {
+                g.sort_by(|a, b| match (self.sort_key(k, *a), self.sort_key(k, *b)) {
+                    (Some(x), Some(y)) => x.total_cmp(&y),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                });
+            }
+        }
+        groups
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = serde_json::Value> + '_ {
+        (0..self.len).map(move |i| self.row(i))
+    }
+
+    // One table with the rows of `parts` in order. Columns line up by name and are
+    // null in parts that lack them; a column whose parts disagree on type is
+    // re-inferred from its values.
+    pub fn concat(parts: &[&Table]) -> Table {
+        let mut names: Vec<&String> = Vec::new();
+        let mut seen = HashSet::new();
+        for part in parts {
+            names.extend(part.columns.iter().map(|c| &c.name).filter(|n| seen.insert(*n)));
+        }
+        let columns = names.into_iter().map(|name| {
+            let cols: Vec<Option<&Column>> = parts.iter().map(|p| p.column(name)).collect();
+            let mut col = cols.iter().flatten().next().map(|c| c.empty_like()).unwrap_or_else(|| Column::nulls(name, 0));
+            let fits = parts.iter().zip(&cols).all(|(p, c)| match c {
+                Some(c) => c.iter().all(|cell| col.push_cell(cell)),
+                None => (0..p.len).all(|_| col.push(None)),
+            });
+            if fits { return col; }
+            let values: Vec<serde_json::Value> = parts.iter().zip(&cols)
+                .flat_map(|(p, c)| (0..p.len).map(move |i| c.map(|c| c.get(i).to_value()).unwrap_or_default()))
+                .collect();
+            Column::from_values(name, values.iter().map(Some))
+        }).collect();
+        Self::from_columns(parts.iter().map(|p| p.len).sum(), columns)
+    }
+
+    // Rows `from..to` as a new table with the same column types.
+    pub fn slice(&self, from: usize, to: usize) -> Table {
+        let columns = self.columns.iter().map(|c| {
+            let mut col = c.empty_like();
+            (from..to).for_each(|i| { col.push_cell(c.get(i)); });
+            col
+        }).collect();
+        Self::from_columns(to - from, columns)
+    }
+
+    // Overwrites row `i` in place; columns the row lacks become null there. A value
+    // that does not fit its column's type makes that column be re-inferred.
+    pub fn set_row(&mut self, i: usize, row: &serde_json::Value) {
+        for name in row.as_object().into_iter().flat_map(|o| o.keys()) {
+            if !self.by_name.contains_key(name) {
+                self.by_name.insert(name.clone(), self.columns.len());
+                self.columns.push(Column::nulls(name, self.len));
+            }
+        }
+        for col in self.columns.iter_mut() {
+            let v = row.get(&col.name);
+            if !col.set(i, v) {
+                let values: Vec<serde_json::Value> = (0..col.len())
+                    .map(|j| if j == i { v.cloned().unwrap_or_default() } else { col.get(j).to_value() })
+                    .collect();
+                *col = Column::from_values(&col.name, values.iter().map(Some));
+            }
+        }
+    }
+}
+
+// The rows of a dataset version as immutable shared chunks of at most
+// MAX_CHUNK_ROWS. An append adds a chunk and merges trailing chunks of similar
+// size, so each row is copied O(log n) times; patching a row copies only its
+// chunk. A version built on the previous one shares every other chunk.
+#[derive(Clone, Default)]
+pub struct ChunkedTable {
+    chunks: Vec<Arc<Table>>,
+    // column names across chunks, in order of first appearance
+    names: Vec<String>,
+    len: usize,
+}
+
+impl ChunkedTable {
+    pub fn new(table: Table) -> Self {
+        let names = table.columns.iter().map(|c| c.name.clone()).collect();
+        let len = table.len;
+        let chunks = if table.len <= MAX_CHUNK_ROWS {
+            vec![Arc::new(table)]
+        } else {
+            (0..table.len).step_by(MAX_CHUNK_ROWS)
+                .map(|from| Arc::new(table.slice(from, (from + MAX_CHUNK_ROWS).min(table.len))))
+                .collect()
+        };
+        Self { len, names, chunks }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn locate(&self, mut i: usize) -> Option<(usize, usize)> {
+        for (c, chunk) in self.chunks.iter().enumerate() {
+            if i < chunk.len { return Some((c, i)); }
+            i -= chunk.len;
+        }
+        None
+    }
+
+    // Row `i` with null slots left out, as `Table::row`.
+    pub fn row(&self, i: usize) -> serde_json::Value {
+        self.locate(i).map(|(c, i)| self.chunks[c].row(i)).unwrap_or_default()
+    }
+
+    // The rows as one table. Free when there is a single chunk; otherwise the
+    // chunks are concatenated, so callers materialize it once and outside locks.
+    pub fn table(&self) -> Arc<Table> {
+        match self.chunks.as_slice() {
+            [] => Arc::default(),
+            [only] => only.clone(),
+            chunks => Arc::new(Table::concat(&chunks.iter().map(|c| c.as_ref()).collect::<Vec<_>>())),
+        }
+    }
+
+    pub fn append(&mut self, rows: &[serde_json::Value]) {
+        let tail = Table::from_rows(rows);
+        self.add_names(&tail);
+        self.len += tail.len;
+        self.chunks.push(Arc::new(tail));
+        while let [.., prev, last] = self.chunks.as_slice() {
+            if prev.len > 2 * last.len || prev.len + last.len > MAX_CHUNK_ROWS { break; }
+            let merged = Table::concat(&[prev, last]);
+            self.chunks.truncate(self.chunks.len() - 2);
+            self.chunks.push(Arc::new(merged));
+        }
+    }
+
+    // Overwrites row `i`. Only the chunk holding it is copied, and only while an
+    // earlier version still shares it.
+    pub fn set_row(&mut self, i: usize, row: &serde_json::Value) {
+        let Some((c, i)) = self.locate(i) else { return };
+        Arc::make_mut(&mut self.chunks[c]).set_row(i, row);
+        for name in row.as_object().into_iter().flat_map(|o| o.keys()) {
+            if !self.names.contains(name) { self.names.push(name.clone()); }
+        }
+    }
+
+    fn add_names(&mut self, table: &Table) {
+        for col in table.columns.iter() {
+            if !self.names.contains(&col.name) { self.names.push(col.name.clone()); }
+        }
+    }
+}
+
+impl Serialize for ChunkedTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len))?;
+        for table in self.chunks.iter() {
+            for i in 0..table.len {
+                seq.serialize_element(&RowRef { table, names: &self.names, i })?;
+            }
+        }
+        seq.end()
+    }
+}
+
+struct RowRef<'a> {
+    table: &'a Table,
+    names: &'a [String],
+    i: usize,
+}
+
+impl Serialize for RowRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for name in self.names.iter() {
+            map.serialize_entry(name, &self.table.cell(name, self.i).to_value())?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn column(values: &[serde_json::Value]) -> Column {
+        Column::from_values("c", values.iter().map(Some))
+    }
+
+    fn values(col: &Column) -> Vec<serde_json::Value> {
+        col.iter().map(|c| c.to_value()).collect()
+    }
+
+    #[test]
+    fn bitmap_tracks_bits_across_words() {
+        let mut b = Bitmap::default();
+        (0..130).for_each(|i| b.push(i % 3 == 0));
+        assert_eq!(b.count_ones(), 44);
+        assert!(b.get(129) && !b.get(128) && !b.get(130));
+        b.set(128, true);
+        b.set(129, false);
+        assert!(b.get(128) && !b.get(129));
+        assert_eq!(b.count_ones(), 44);
+    }
+
+    #[test]
+    fn infers_the_narrowest_storage() {
+        let dtype = |vs: &[serde_json::Value]| column(vs).dtype();
+        assert_eq!(dtype(&[json!(null), json!(null)]), "null");
+        assert_eq!(dtype(&[json!(true), json!(null)]), "bool");
+        assert_eq!(dtype(&[json!(1), json!(-2)]), "integer");
+        assert_eq!(dtype(&[json!(1), json!(2.5)]), "number");
+        assert_eq!(dtype(&[json!("2024-01-01T00:00:00Z")]), "datetime");
+        assert_eq!(dtype(&[json!("BTC"), json!("ETH")]), "string");
+        assert_eq!(dtype(&[json!(1), json!("a")]), "json");
+        assert_eq!(dtype(&[json!({"a": 1})]), "json");
+    }
+
+    #[test]
+    fn nulls_stay_null_in_typed_columns() {
+        let col = column(&[json!(1), json!(null), json!(3)]);
+        assert_eq!(col.non_null(), 2);
+        assert!(col.get(1).is_null());
+        assert_eq!(values(&col), vec![json!(1), json!(null), json!(3)]);
+    }
+
+    #[test]
+    fn integers_mixed_with_floats_read_back_as_integers() {
+        let col = column(&[json!(1), json!(1.0), json!(2.5), json!(9007199254740992i64)]);
+        assert!(matches!(col.data, ColumnData::Number { .. }));
+        assert_eq!(values(&col), vec![json!(1), json!(1.0), json!(2.5), json!(9007199254740992i64)]);
+        assert!(matches!(col.get(0), Cell::Integer(1)));
+        assert!(matches!(col.get(1), Cell::Number(_)));
+    }
+
+    #[test]
+    fn integers_past_2_53_mixed_with_floats_are_kept_as_json() {
+        let col = column(&[json!(9007199254740993i64), json!(0.5)]);
+        assert_eq!(col.dtype(), "json");
+        assert_eq!(values(&col), vec![json!(9007199254740993i64), json!(0.5)]);
+    }
+
+    #[test]
+    fn only_canonical_datetimes_are_stored_natively() {
+        assert_eq!(column(&[json!("2024-01-01T00:00:00Z")]).dtype(), "datetime");
+        // parses, but would not come back byte for byte
+        let col = column(&[json!("2024-01-01T00:00:00+00:00")]);
+        assert_eq!(col.dtype(), "string");
+        assert_eq!(values(&col), vec![json!("2024-01-01T00:00:00+00:00")]);
+    }
+
+    #[test]
+    fn many_distinct_strings_are_not_dictionary_encoded() {
+        let vs: Vec<serde_json::Value> = (0..=MAX_DICT_CARDINALITY).map(|i| json!(format!("s{i}"))).collect();
+        let col = column(&vs);
+        assert!(matches!(col.data, ColumnData::Utf8 { .. }));
+        assert_eq!(col.get(MAX_DICT_CARDINALITY).as_str(), Some("s4096"));
+        assert!(matches!(column(&vs[..10]).data, ColumnData::Dict { .. }));
+    }
+
+    #[test]
+    fn rows_leave_out_nulls_and_dense_rows_keep_them() {
+        let t = Table::from_rows(&[json!({"a": 1, "b": "x"}), json!({"a": 2})]);
+        assert_eq!(t.row(1), json!({"a": 2}));
+        assert_eq!(t.row_dense(1), json!({"a": 2, "b": null}));
+    }
+
+    #[test]
+    fn set_row_patches_in_place_and_reinfers_a_column_that_no_longer_fits() {
+        let mut t = Table::from_rows(&[json!({"a": 1, "b": 1}), json!({"a": 2, "b": 2})]);
+        t.set_row(1, &json!({"a": 3, "b": "two", "c": true}));
+        assert_eq!(t.column("a").unwrap().dtype(), "integer");
+        assert_eq!(t.column("b").unwrap().dtype(), "json");
+        assert_eq!(t.row(0), json!({"a": 1, "b": 1}));
+        assert_eq!(t.row(1), json!({"a": 3, "b": "two", "c": true}));
+        t.set_row(0, &json!({"a": 4}));
+        assert_eq!(t.row(0), json!({"a": 4}));
+    }
+
+    #[test]
+    fn concat_lines_up_columns_and_widens_types_that_disagree() {
+        let a = Table::from_rows(&[json!({"n": 1, "s": "x"})]);
+        let b = Table::from_rows(&[json!({"n": 2.5, "t": true})]);
+        let c = Table::from_rows(&[json!({"n": "three"})]);
+        let t = Table::concat(&[&a, &b]);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.column("n").unwrap().dtype(), "number");
+        assert_eq!(t.rows().collect::<Vec<_>>(), vec![json!({"n": 1, "s": "x"}), json!({"n": 2.5, "t": true})]);
+        let t = Table::concat(&[&a, &c]);
+        assert_eq!(t.column("n").unwrap().dtype(), "json");
+        assert_eq!(t.cell("n", 1).as_str(), Some("three"));
+    }
+
+    #[test]
+    fn groups_partition_and_sort_with_keyless_rows_last() {
+        let t = Table::from_rows(&[
+            json!({"s": "a", "ts": 3}),
+            json!({"s": "b", "ts": 1}),
+            json!({"s": "a"}),
+            json!({"s": "a", "ts": 1}),
+        ]);
+        assert_eq!(t.groups(Some("ts"), Some("s")), vec![vec![3, 0, 2], vec![1]]);
+        assert_eq!(t.groups(None, None), vec![vec![0, 1, 2, 3]]);
+    }
+
+    fn chunked(rows: std::ops::Range<i64>) -> ChunkedTable {
+        let rows: Vec<serde_json::Value> = rows.map(|i| json!({"i": i})).collect();
+        ChunkedTable::new(Table::from_rows(&rows))
+    }
+
+    #[test]
+    fn chunked_append_merges_tail_chunks_of_similar_size() {
+        let mut t = chunked(0..4);
+        for n in 0..4 {
+            t.append(&[json!({"i": 4 + n})]);
+        }
+        assert_eq!(t.len(), 8);
+        assert_eq!(t.chunks.iter().map(|c| c.len).collect::<Vec<_>>(), vec![6, 2]);
+        t.append(&[json!({"i": 8}), json!({"i": 9})]);
+        assert_eq!(t.chunks.iter().map(|c| c.len).collect::<Vec<_>>(), vec![10]);
+        assert_eq!((0..10).map(|i| t.row(i)).collect::<Vec<_>>(), (0..10).map(|i| json!({"i": i})).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chunked_new_splits_a_large_table() {
+        let t = chunked(0..(MAX_CHUNK_ROWS as i64 + 1));
+        assert_eq!(t.chunks.iter().map(|c| c.len).collect::<Vec<_>>(), vec![MAX_CHUNK_ROWS, 1]);
+        assert_eq!(t.row(MAX_CHUNK_ROWS), json!({"i": MAX_CHUNK_ROWS}));
+        assert_eq!(t.table().len(), MAX_CHUNK_ROWS + 1);
+    }
+
+    #[test]
+    fn chunked_set_row_leaves_earlier_copies_alone() {
+        let mut t = chunked(0..8);
+        t.append(&[json!({"i": 8}), json!({"i": 9})]);
+        let before = t.clone();
+        t.set_row(9, &json!({"i": 19, "note": "patched"}));
+        assert_eq!(before.row(9), json!({"i": 9}));
+        assert_eq!(t.row(9), json!({"i": 19, "note": "patched"}));
+        // only the patched chunk was copied
+        assert!(Arc::ptr_eq(&before.chunks[0], &t.chunks[0]));
+        assert!(!Arc::ptr_eq(&before.chunks[1], &t.chunks[1]));
+        assert_eq!(t.names, vec!["i", "note"]);
+    }
+
+    #[test]
+    fn chunked_serializes_dense_rows_with_every_column() {
+        let mut t = ChunkedTable::new(Table::from_rows(&[json!({"a": 1})]));
+        t.append(&[json!({"b": "x"})]);
+        let out = serde_json::to_value(&t).unwrap();
+        assert_eq!(out, json!([{"a": 1, "b": null}, {"a": null, "b": "x"}]));
+    }
+}
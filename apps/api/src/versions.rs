@@ -0,0 +1,268 @@
+use chrono::Utc;
+use uuid::Uuid;
+use crate::models::*;
+use crate::review;
+use crate::state::Store;
+
+// Publishes an approved proposal of `provider_id`'s dataset as a version, not yet
+// numbered. For an existing `product` the caller must be its provider, the
+// proposal must target a profile of the same name, so the product keeps serving
+// the same model across versions, and its dataset must be one the product has
+// served. The proposal lock is released before returning; callers add the
+// version to the product afterwards.
+pub fn publish(
+    store: &Store,
+    proposal_id: Uuid,
+    provider_id: Uuid,
+    product: Option<&ApiProduct>,
+    actor: &str,
+    note: &str,
+) -> Result<ApiVersion, String> {
+    if let Some(api) = product {
+        if provider_id != api.provider_id {
+            return Err("PROVIDER_MISMATCH".into());
+        }
+        if api.status == ApiStatus::Retired {
+            return Err("API_RETIRED".into());
+        }
+    }
+    let profile_name = product.and_then(|api| store.models.get(&api.model_profile_id).map(|m| m.name.clone()));
+    let mut prop = store.proposals.get_mut(&proposal_id).ok_or("PROPOSAL_NOT_FOUND")?;
+    if !review::passes(&prop) {
+        return Err("EVALS_NOT_PASSED".into());
+    }
+    let profile = store.models.get(&prop.model_profile_id).map(|m| m.clone()).ok_or("MODEL_PROFILE_NOT_FOUND")?;
+    if profile_name.is_some_and(|n| n != profile.name) {
+        return Err("MODEL_PROFILE_MISMATCH".into());
+    }
+    if prop.status != ProposalStatus::Approved {
+        return Err("PROPOSAL_NOT_APPROVED".into());
+    }
+    // proposals from before dataset versioning name the latest version
+    let dataset_version = {
+        let ds = store.datasets.get(&prop.dataset_id).ok_or("DATASET_NOT_FOUND")?;
+        if ds.provider_id != provider_id {
+            return Err("DATASET_PROVIDER_MISMATCH".into());
+        }
+        if product.is_some_and(|api| api.dataset_id != ds.id && api.versions.iter().all(|v| v.dataset_id != ds.id)) {
+            return Err("DATASET_MISMATCH".into());
+        }
+        let version = if prop.dataset_version == 0 { ds.version } else { prop.dataset_version };
+        ds.at(version).ok_or("DATASET_VERSION_NOT_FOUND")?.version
+    };
+    let approval_note = review::publish(&mut prop, actor)?;
+    let now = Utc::now();
+    Ok(ApiVersion {
+        version: String::new(),
+        proposal_id,
+        dataset_id: prop.dataset_id,
+        dataset_version,
+        rows: prop.snapshot.rows.len(),
+        model_profile_id: profile.id,
+        model_profile_version: profile.version,
+        published_by: actor.into(),
+        published_at: now,
+        human_approval_note: match note.trim() {
+            "" => approval_note.unwrap_or_default(),
+            n => n.to_string(),
+        },
+        deprecated_at: None,
+        quarantined_rows: prop.quarantined_rows.clone(),
+        dropped_rows: prop.dropped_rows.clone(),
+        waivers: prop.waivers.iter().filter(|w| w.active(now)).cloned().collect(),
+        snapshot: prop.snapshot.clone(),
+    })
+}
+
+// Numbers the version after the existing ones and makes it the product's latest.
+pub fn add(api: &mut ApiProduct, mut v: ApiVersion) {
+    v.version = format!("v{}", api.versions.len() + 1);
+    api.version = v.version.clone();
+    api.proposal_id = v.proposal_id;
+    api.dataset_id = v.dataset_id;
+    api.model_profile_id = v.model_profile_id;
+    api.human_approval_note = v.human_approval_note.clone();
+    api.quarantined_rows = v.quarantined_rows.clone();
+    api.dropped_rows = v.dropped_rows.clone();
+    api.waivers = v.waivers.clone();
+    api.versions.push(v);
+}
+
+// `latest` (or nothing) is the newest version; `v2` and `2` both name version 2.
+pub fn resolve<'a>(api: &'a ApiProduct, requested: Option<&str>) -> Result<&'a ApiVersion, String> {
+    let v = match requested.map(str::trim) {
+        None | Some("" | "latest") => api.versions.last(),
+        Some(name) => {
+            let name = if name.starts_with('v') { name.to_string() } else { format!("v{name}") };
+            api.versions.iter().find(|v| v.version == name)
+        }
+    };
+    match v {
+        None => Err("VERSION_NOT_FOUND".into()),
+        Some(v) if v.deprecated_at.is_some() => Err(format!("VERSION_DEPRECATED:{}", v.version)),
+        Some(v) => Ok(v),
+    }
+}
+
+pub fn deprecate(api: &mut ApiProduct, version: &str, provider_id: Uuid) -> Result<(), String> {
+    if provider_id != api.provider_id {
+        return Err("PROVIDER_MISMATCH".into());
+    }
+    let latest = api.versions.last().map(|v| v.version.clone());
+    let name = if version.starts_with('v') { version.to_string() } else { format!("v{version}") };
+    let v = api.versions.iter_mut().find(|v| v.version == name).ok_or("VERSION_NOT_FOUND")?;
+    if latest.as_deref() == Some(v.version.as_str()) {
+        return Err("CANNOT_DEPRECATE_LATEST".into());
+    }
+    v.deprecated_at.get_or_insert_with(Utc::now);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::datasets;
+    use super::*;
+
+    struct Fixture {
+        store: Store,
+        provider: Uuid,
+        dataset: Uuid,
+        profile: Uuid,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let store = Store::default();
+            let provider = Uuid::new_v4();
+            let ds = datasets::create(provider, "d".into(), "d".into(), &[json!({"symbol": "A", "price": 1})], Vec::new()).unwrap();
+            let dataset = ds.id;
+            store.datasets.insert(dataset, ds);
+            let profile = add_profile(&store, "prices", "1.0");
+            Self { store, provider, dataset, profile }
+        }
+
+        fn profile(&self, name: &str, version: &str) -> Uuid {
+            add_profile(&self.store, name, version)
+        }
+
+        fn proposal(&self, dataset: Uuid, profile: Uuid, status: &str) -> Uuid {
+            let prop: ApiProposal = serde_json::from_value(json!({
+                "id": Uuid::new_v4(), "status": status, "created_by": "alice", "dataset_id": dataset,
+                "model_profile_id": profile, "sample": [], "coverage": [], "pass": true, "human_note_required": false,
+                "history": [{"at": Utc::now(), "actor": "bob", "action": "approved", "from": "pending", "to": "approved", "comment": "looks right"}],
+            })).unwrap();
+            let id = prop.id;
+            self.store.proposals.insert(id, prop);
+            id
+        }
+
+        // a live product on its first version
+        fn product(&self) -> ApiProduct {
+            let prop = self.proposal(self.dataset, self.profile, "approved");
+            let v = publish(&self.store, prop, self.provider, None, "bob", "").unwrap();
+            let mut api: ApiProduct = serde_json::from_value(json!({
+                "id": Uuid::new_v4(), "name": "p", "pricing": "free", "provider_id": self.provider,
+                "dataset_id": self.dataset, "model_profile_id": self.profile, "version": "",
+                "status": "live", "human_approval_note": "",
+            })).unwrap();
+            add(&mut api, v);
+            api
+        }
+    }
+
+    fn add_profile(store: &Store, name: &str, version: &str) -> Uuid {
+        let m: ModelProfile = serde_json::from_value(json!({
+            "id": Uuid::new_v4(), "name": name, "version": version, "description": "", "features": [],
+        })).unwrap();
+        let id = m.id;
+        store.models.insert(id, m);
+        id
+    }
+
+    fn names(api: &ApiProduct) -> Vec<&str> {
+        api.versions.iter().map(|v| v.version.as_str()).collect()
+    }
+
+    #[test]
+    fn publishing_numbers_versions_and_mirrors_the_latest() {
+        let f = Fixture::new();
+        let mut api = f.product();
+        assert_eq!(api.versions[0].human_approval_note, "looks right");
+        assert_eq!(f.store.proposals.get(&api.proposal_id).unwrap().status.name(), "published");
+        let newer = f.profile("prices", "1.1");
+        let prop = f.proposal(f.dataset, newer, "approved");
+        let v = publish(&f.store, prop, f.provider, Some(&api), "bob", " rebuilt ").unwrap();
+        add(&mut api, v);
+        assert_eq!(names(&api), vec!["v1", "v2"]);
+        assert_eq!((api.version.as_str(), api.proposal_id, api.model_profile_id), ("v2", prop, newer));
+        assert_eq!((api.versions[1].model_profile_version.as_str(), api.human_approval_note.as_str()), ("1.1", "rebuilt"));
+        assert_eq!(api.versions[1].dataset_version, 1);
+    }
+
+    #[test]
+    fn publishing_checks_the_provider_product_and_proposal() {
+        let f = Fixture::new();
+        let mut api = f.product();
+        let approved = || f.proposal(f.dataset, f.profile, "approved");
+        let err = |prop, provider, api: Option<&ApiProduct>| publish(&f.store, prop, provider, api, "bob", "").err();
+        assert_eq!(err(approved(), Uuid::new_v4(), Some(&api)).as_deref(), Some("PROVIDER_MISMATCH"));
+        assert_eq!(err(approved(), Uuid::new_v4(), None).as_deref(), Some("DATASET_PROVIDER_MISMATCH"));
+        assert_eq!(err(Uuid::new_v4(), f.provider, None).as_deref(), Some("PROPOSAL_NOT_FOUND"));
+        assert_eq!(err(f.proposal(f.dataset, f.profile, "pending"), f.provider, None).as_deref(), Some("PROPOSAL_NOT_APPROVED"));
+        let other_model = f.profile("volumes", "1.0");
+        assert_eq!(err(f.proposal(f.dataset, other_model, "approved"), f.provider, Some(&api)).as_deref(), Some("MODEL_PROFILE_MISMATCH"));
+        let other = datasets::create(f.provider, "e".into(), "e".into(), &[json!({"x": 1})], Vec::new()).unwrap();
+        let other_id = other.id;
+        f.store.datasets.insert(other_id, other);
+        assert_eq!(err(f.proposal(other_id, f.profile, "approved"), f.provider, Some(&api)).as_deref(), Some("DATASET_MISMATCH"));
+        api.status = ApiStatus::Retired;
+        assert_eq!(err(approved(), f.provider, Some(&api)).as_deref(), Some("API_RETIRED"));
+    }
+
+    #[test]
+    fn proposals_publish_the_dataset_version_their_evals_ran_on() {
+        let f = Fixture::new();
+        datasets::append(&mut f.store.datasets.get_mut(&f.dataset).unwrap(), &[json!({"symbol": "B", "price": 2})]).unwrap();
+        let pinned = f.proposal(f.dataset, f.profile, "approved");
+        f.store.proposals.get_mut(&pinned).unwrap().dataset_version = 1;
+        assert_eq!(publish(&f.store, pinned, f.provider, None, "bob", "").unwrap().dataset_version, 1);
+        let unpinned = f.proposal(f.dataset, f.profile, "approved");
+        assert_eq!(publish(&f.store, unpinned, f.provider, None, "bob", "").unwrap().dataset_version, 2);
+        let missing = f.proposal(f.dataset, f.profile, "approved");
+        f.store.proposals.get_mut(&missing).unwrap().dataset_version = 9;
+        assert_eq!(publish(&f.store, missing, f.provider, None, "bob", "").err().as_deref(), Some("DATASET_VERSION_NOT_FOUND"));
+    }
+
+    #[test]
+    fn resolve_names_versions_with_or_without_the_v() {
+        let f = Fixture::new();
+        let mut api = f.product();
+        let v = publish(&f.store, f.proposal(f.dataset, f.profile, "approved"), f.provider, Some(&api), "bob", "").unwrap();
+        add(&mut api, v);
+        let name = |requested| resolve(&api, requested).map(|v| v.version.clone());
+        assert_eq!(name(None), Ok("v2".into()));
+        assert_eq!(name(Some("latest")), Ok("v2".into()));
+        assert_eq!(name(Some(" ")), Ok("v2".into()));
+        assert_eq!(name(Some("1")), Ok("v1".into()));
+        assert_eq!(name(Some("v1")), Ok("v1".into()));
+        assert_eq!(name(Some("3")), Err("VERSION_NOT_FOUND".into()));
+    }
+
+    #[test]
+    fn deprecated_versions_stop_resolving_and_the_latest_stays() {
+        let f = Fixture::new();
+        let mut api = f.product();
+        let v = publish(&f.store, f.proposal(f.dataset, f.profile, "approved"), f.provider, Some(&api), "bob", "").unwrap();
+        add(&mut api, v);
+        assert_eq!(deprecate(&mut api, "v1", Uuid::new_v4()), Err("PROVIDER_MISMATCH".into()));
+        assert_eq!(deprecate(&mut api, "2", f.provider), Err("CANNOT_DEPRECATE_LATEST".into()));
+        assert_eq!(deprecate(&mut api, "v7", f.provider), Err("VERSION_NOT_FOUND".into()));
+        deprecate(&mut api, "1", f.provider).unwrap();
+        let at = api.versions[0].deprecated_at;
+        deprecate(&mut api, "v1", f.provider).unwrap();
+        assert_eq!(api.versions[0].deprecated_at, at);
+        assert_eq!(resolve(&api, Some("v1")).err(), Some("VERSION_DEPRECATED:v1".into()));
+        assert!(resolve(&api, None).is_ok());
+    }
+}
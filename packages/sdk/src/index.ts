@@ -18,10 +18,32 @@ export class MinamClient {
   createDataset(body: any){ return this.req('/api/datasets', { method:'POST', body: JSON.stringify(body)}); }
   listDatasets(){ return this.req('/api/datasets'); }
   previewDataset(id: string){ return this.req(`/api/datasets/${id}/preview`); }
+  changeDataset(id: string, body: any){ return this.req(`/api/datasets/${id}/versions`, { method:'POST', body: JSON.stringify(body)}); }
+  listDatasetVersions(id: string){ return this.req(`/api/datasets/${id}/versions`); }
+  diffDataset(id: string, q: { from?: number; to?: number; key?: string[] } = {}){
+    const p = new URLSearchParams();
+    if (q.from !== undefined) p.set('from', String(q.from));
+    if (q.to !== undefined) p.set('to', String(q.to));
+    if (q.key?.length) p.set('key', q.key.join(','));
+    return this.req(`/api/datasets/${id}/diff?${p}`);
+  }
+  // Mappings
+  suggestMappings(body: any){ return this.req('/api/mappings/suggest', { method:'POST', body: JSON.stringify(body)}); }
   // Pipeline
   runPipeline(body: any){ return this.req('/api/pipelines', { method:'POST', body: JSON.stringify(body)}); }
+  getPipelineJob(id: string){ return this.req(`/api/pipelines/${id}`); }
+  cancelPipelineJob(id: string){ return this.req(`/api/pipelines/${id}/cancel`, { method:'POST' }); }
+  // Proposals
+  listProposals(status?: string){ return this.req(status ? `/api/proposals?status=${status}` : '/api/proposals'); }
+  getProposal(id: string){ return this.req(`/api/proposals/${id}`); }
+  reviewProposal(id: string, body: any){ return this.req(`/api/proposals/${id}/reviews`, { method:'POST', body: JSON.stringify(body)}); }
+  waiveCheck(id: string, body: any){ return this.req(`/api/proposals/${id}/waivers`, { method:'POST', body: JSON.stringify(body)}); }
   // APIs
   createApi(body: any){ return this.req('/api/apis', { method:'POST', body: JSON.stringify(body)}); }
   listApis(){ return this.req('/api/apis'); }
-  queryApi(apiId: string, body: any){ return this.req(`/v1/data/${apiId}/query`, { method:'POST', body: JSON.stringify(body)}); }
+  getApiSpec(apiId: string, version?: string){ return this.req(`/api/apis/${apiId}/openapi${version ? `?version=${version}` : ''}`); }
+  setApiStatus(apiId: string, body: any){ return this.req(`/api/apis/${apiId}/status`, { method:'POST', body: JSON.stringify(body)}); }
+  createApiVersion(apiId: string, body: any){ return this.req(`/api/apis/${apiId}/versions`, { method:'POST', body: JSON.stringify(body)}); }
+  deprecateApiVersion(apiId: string, version: string, providerId: string){ return this.req(`/api/apis/${apiId}/versions/${version}/deprecate`, { method:'POST', body: JSON.stringify({ provider_id: providerId })}); }
+  queryApi(apiId: string, body: any, version?: string){ return this.req(`/v1/data/${apiId}/query${version ? `?version=${version}` : ''}`, { method:'POST', body: JSON.stringify(body)}); }
 }